@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{LazyLock, RwLock};
+use std::time::SystemTime;
+
+use crate::CueSheet;
+
+/// Identifies a probed audio file, a changed modification time makes the file be probed again
+type DurationCacheKey = (PathBuf, SystemTime);
+
+/// Durations probed by ffprobe, also failed probes are cached
+static DURATION_CACHE: LazyLock<RwLock<HashMap<DurationCacheKey, Option<f64>>>> =
+    LazyLock::new(Default::default);
+
+/// Reads the duration of the audio file in seconds, the result is cached
+pub fn read_audio_duration(audio_file_path: &Path) -> Option<f64> {
+    let cache_key = fs::metadata(audio_file_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|modified| (audio_file_path.to_path_buf(), modified));
+
+    if let Some(ref cache_key) = cache_key {
+        if let Some(duration) = DURATION_CACHE.read().unwrap().get(cache_key) {
+            return *duration;
+        }
+    }
+
+    let duration = probe_audio_duration(audio_file_path);
+    if let Some(cache_key) = cache_key {
+        DURATION_CACHE.write().unwrap().insert(cache_key, duration);
+    }
+    duration
+}
+
+/// Reads the duration of the audio file in seconds using ffprobe
+/// Example call: ffprobe -v error -show_entries format=duration -of default=noprint_wrappers=1:nokey=1 input.mp3
+fn probe_audio_duration(audio_file_path: &Path) -> Option<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(audio_file_path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|duration| duration.is_finite())
+}
+
+/// The duration an audio file referenced by a cue sheet is expected to have
+#[derive(Debug, Copy, Clone)]
+pub struct ExpectedDuration {
+    /// Start of the last track in seconds, the audio file has to be longer
+    last_track_start: f64,
+    /// Expected length of the last track in seconds, derived from the other tracks
+    last_track_length: Option<f64>,
+}
+
+impl ExpectedDuration {
+    /// Derives the expected duration from the track start times
+    /// The last track is expected to be as long as the median of the other tracks
+    pub fn from_cue_sheet(cue_sheet: &CueSheet) -> Option<ExpectedDuration> {
+        let start_times: Vec<f64> = cue_sheet
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .start_time
+                    .map(|start_time| start_time.total_frames() as f64 / 75.0)
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        let last_track_start = *start_times.last()?;
+
+        let mut track_lengths: Vec<f64> = start_times
+            .windows(2)
+            .map(|start_times| start_times[1] - start_times[0])
+            .filter(|track_length| *track_length > 0.0)
+            .collect();
+        track_lengths.sort_by(f64::total_cmp);
+        let last_track_length = track_lengths.get(track_lengths.len() / 2).copied();
+
+        Some(ExpectedDuration {
+            last_track_start,
+            last_track_length,
+        })
+    }
+
+    /// Scores from 0 to 100 how well the duration fits
+    /// Returns None if the audio file is too short to hold the last track
+    ///
+    /// The exact expected duration scores 100. As the last track often differs, e.g. because of hidden tracks,
+    /// a deviation up to the expected last track length still scores at least 50, beyond that tolerance the score
+    /// decreases towards 0.
+    pub fn score(&self, duration: f64) -> Option<usize> {
+        if duration <= self.last_track_start {
+            return None;
+        }
+        let Some(last_track_length) = self.last_track_length else {
+            return Some(100);
+        };
+
+        let deviation = (duration - (self.last_track_start + last_track_length)).abs();
+        let tolerance = last_track_length;
+        if deviation <= tolerance {
+            Some((100.0 - 50.0 * deviation / tolerance).round() as usize)
+        } else {
+            Some((50.0 * tolerance / deviation) as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected_duration() -> ExpectedDuration {
+        ExpectedDuration {
+            last_track_start: 600.0,
+            last_track_length: Some(200.0),
+        }
+    }
+
+    #[test]
+    fn score_refuses_files_shorter_than_the_last_track_start() {
+        assert_eq!(expected_duration().score(500.0), None);
+        assert_eq!(expected_duration().score(600.0), None);
+    }
+
+    #[test]
+    fn score_decreases_within_the_tolerance() {
+        let expected_duration = expected_duration();
+        assert_eq!(expected_duration.score(800.0), Some(100));
+        assert_eq!(expected_duration.score(850.0), Some(88));
+        assert_eq!(expected_duration.score(700.0), Some(75));
+        assert_eq!(expected_duration.score(1000.0), Some(50));
+        assert!(expected_duration.score(805.0) > expected_duration.score(820.0));
+    }
+
+    #[test]
+    fn score_decreases_beyond_the_tolerance() {
+        let expected_duration = expected_duration();
+        assert_eq!(expected_duration.score(1200.0), Some(25));
+        assert_eq!(expected_duration.score(2800.0), Some(5));
+    }
+
+    #[test]
+    fn score_without_track_lengths_accepts_any_longer_file() {
+        let expected_duration = ExpectedDuration {
+            last_track_start: 0.0,
+            last_track_length: None,
+        };
+        assert_eq!(expected_duration.score(10.0), Some(100));
+    }
+}
@@ -0,0 +1,372 @@
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fs};
+
+use semver::VersionReq;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::cue_encoding::{CueConversion, CueEncoding};
+use crate::embed::CueEmbedMode;
+use crate::output::{OutputFormat, OutputTemplate};
+use crate::playlist::PlaylistFormat;
+use crate::report::ReportFormat;
+use crate::tags::TagPrecedence;
+use crate::updater::ReleaseChannel;
+use crate::InvalidCuePolicy;
+
+/// Name of the per-directory config file, the nearest one of the input folders and their parents is used
+pub const DIRECTORY_CONFIG_FILE_NAME: &str = ".cue-splatter.toml";
+
+/// Settings read from the config files, unset values fall back to the defaults of the command line options
+/// The keys are named like the command line options, with underscores instead of dashes
+/// Values set on the command line or by environment variables take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "parse")]
+    pub output_template: Option<OutputTemplate>,
+    #[serde(deserialize_with = "parse")]
+    pub format: Option<OutputFormat>,
+    pub jobs: Option<usize>,
+    pub disc_prefix: Option<bool>,
+    #[serde(deserialize_with = "parse")]
+    pub tag_precedence: Option<TagPrecedence>,
+    pub no_cover: Option<bool>,
+    pub cover_names: Option<String>,
+    pub cover_max_size: Option<u32>,
+    pub replaygain: Option<bool>,
+    #[serde(deserialize_with = "parse")]
+    pub embed_cue: Option<CueEmbedMode>,
+    pub copy_logs: Option<bool>,
+    pub split_cue: Option<bool>,
+    #[serde(deserialize_with = "parse")]
+    pub playlist: Option<PlaylistFormat>,
+    #[serde(deserialize_with = "parse")]
+    pub report: Option<ReportFormat>,
+    pub transfer: Option<bool>,
+    pub delete: Option<bool>,
+
+    #[serde(deserialize_with = "parse")]
+    pub cue_encoding: Option<CueEncoding>,
+    #[serde(deserialize_with = "parse")]
+    pub convert_cue: Option<CueConversion>,
+    pub save_fixes: Option<bool>,
+    pub non_interactive: Option<bool>,
+    #[serde(deserialize_with = "parse")]
+    pub on_invalid_cue: Option<InvalidCuePolicy>,
+    pub auto_fix_score: Option<usize>,
+    pub accept_score: Option<usize>,
+
+    pub update: UpdateConfig,
+    pub watch: WatchConfig,
+}
+
+/// The [update] section of the config files
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// Disables the automatic update, like --no-update
+    pub disabled: Option<bool>,
+    pub timeout: Option<u64>,
+    #[serde(deserialize_with = "parse")]
+    pub channel: Option<ReleaseChannel>,
+    #[serde(deserialize_with = "parse")]
+    pub pin: Option<VersionReq>,
+    pub release_url: Option<String>,
+}
+
+/// The [watch] section of the config files
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Folders watched if none are given on the command line
+    pub folders: Option<Vec<PathBuf>>,
+    pub done_dir: Option<PathBuf>,
+    pub failed_dir: Option<PathBuf>,
+    pub settle_time: Option<u64>,
+}
+
+/// Resolves a switch that can be enabled in the config files, e.g. --delete
+/// The negating switch, e.g. --no-delete, turns off a switch enabled in a config file, the switch itself wins over it
+pub fn resolve_switch(enabled: bool, disabled: bool, config_value: Option<bool>) -> bool {
+    enabled || (!disabled && config_value.unwrap_or_default())
+}
+
+/// Loads and merges the config files, values of later files override those of earlier ones:
+/// 1. the user config file, given by --config or in the XDG config folder, e.g. ~/.config/cue-splatter/config.toml
+/// 2. the nearest .cue-splatter.toml of the input files or folders or their parents, of the current folder without
+///    inputs
+pub fn load(config_file: Option<&Path>, input_paths: &[PathBuf]) -> Result<Config, String> {
+    let mut config_files = Vec::new();
+    match config_file {
+        Some(config_file) if !config_file.is_file() => {
+            return Err(format!("{} not found", config_file.display()))
+        }
+        Some(config_file) => config_files.push(config_file.to_path_buf()),
+        None => config_files.extend(
+            user_config_file(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))
+                .filter(|file| file.is_file()),
+        ),
+    }
+    let current_dir =
+        env::current_dir().map_err(|err| format!("could not read the current folder: {}", err))?;
+    config_files.extend(find_directory_config_file(&current_dir, input_paths)?);
+
+    let mut merged_table = toml::Table::new();
+    for config_file in &config_files {
+        merge_tables(&mut merged_table, read_config_file(config_file)?);
+    }
+    toml::Value::Table(merged_table)
+        .try_into()
+        .map_err(|err: toml::de::Error| err.to_string())
+}
+
+/// The config file in the XDG config folder, $XDG_CONFIG_HOME falls back to ~/.config
+fn user_config_file(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let config_dir = xdg_config_home
+        .filter(|config_dir| !config_dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home_dir| PathBuf::from(home_dir).join(".config")))?;
+    Some(config_dir.join("cue-splatter").join("config.toml"))
+}
+
+/// Finds the directory config file shared by all input paths, relative paths are resolved against the current folder
+/// Inputs with different directory config files are refused, as one run can not use the settings of both
+fn find_directory_config_file(
+    current_dir: &Path,
+    input_paths: &[PathBuf],
+) -> Result<Option<PathBuf>, String> {
+    let Some((first_input_path, other_input_paths)) = input_paths.split_first() else {
+        return Ok(nearest_directory_config_file(current_dir));
+    };
+
+    let config_file = nearest_directory_config_file(&current_dir.join(first_input_path));
+    for input_path in other_input_paths {
+        let other_config_file = nearest_directory_config_file(&current_dir.join(input_path));
+        if other_config_file != config_file {
+            return Err(format!(
+                "{} and {} use different {} files, run them separately",
+                first_input_path.display(),
+                input_path.display(),
+                DIRECTORY_CONFIG_FILE_NAME
+            ));
+        }
+    }
+    Ok(config_file)
+}
+
+/// The nearest directory config file of the folder of the input path or its parents
+fn nearest_directory_config_file(input_path: &Path) -> Option<PathBuf> {
+    // Resolves ".." and symlinks, so the real parents are searched
+    let input_path = fs::canonicalize(input_path).unwrap_or_else(|_| input_path.to_path_buf());
+    let input_dir = if input_path.is_file() {
+        input_path.parent()?
+    } else {
+        &input_path
+    };
+    input_dir
+        .ancestors()
+        .map(|dir| dir.join(DIRECTORY_CONFIG_FILE_NAME))
+        .find(|config_file| config_file.is_file())
+}
+
+/// Reads a config file, it is validated on its own, so errors name the file they are in
+fn read_config_file(config_file: &Path) -> Result<toml::Table, String> {
+    let content = fs::read_to_string(config_file)
+        .map_err(|err| format!("could not read {}: {}", config_file.display(), err))?;
+    toml::from_str::<Config>(&content)
+        .map_err(|err| format!("{}: {}", config_file.display(), err))?;
+    content
+        .parse::<toml::Table>()
+        .map_err(|err| format!("{}: {}", config_file.display(), err))
+}
+
+/// Copies the values of the overriding table into the table, sections are merged key by key
+fn merge_tables(table: &mut toml::Table, overriding_table: toml::Table) {
+    for (key, value) in overriding_table {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(section)), toml::Value::Table(overriding_section)) => {
+                merge_tables(section, overriding_section)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parses a string value like the command line option of the same name
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::CueArgs;
+    use crate::output::OutputFormat;
+
+    fn write_file(path: PathBuf, content: &str) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// A music library with a directory config file and an album folder holding a cue file
+    fn library(root: &Path, config: &str) -> PathBuf {
+        write_file(root.join(DIRECTORY_CONFIG_FILE_NAME), config);
+        write_file(root.join("Artist").join("Album").join("album.cue"), "");
+        root.join("Artist").join("Album")
+    }
+
+    #[test]
+    fn negating_switch_turns_off_the_config_value() {
+        assert!(resolve_switch(false, false, Some(true)));
+        assert!(!resolve_switch(false, true, Some(true)));
+        assert!(!resolve_switch(false, false, None));
+        assert!(resolve_switch(true, false, Some(false)));
+        assert!(resolve_switch(true, true, Some(true)));
+    }
+
+    #[test]
+    fn user_config_file_is_in_the_xdg_config_folder() {
+        assert_eq!(
+            user_config_file(Some("/xdg".into()), Some("/home/user".into())),
+            Some(PathBuf::from("/xdg/cue-splatter/config.toml"))
+        );
+        assert_eq!(
+            user_config_file(Some("".into()), Some("/home/user".into())),
+            Some(PathBuf::from("/home/user/.config/cue-splatter/config.toml"))
+        );
+        assert_eq!(user_config_file(None, None), None);
+    }
+
+    #[test]
+    fn loads_the_given_config_file() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let config_file = write_file(
+            temp_dir.path().join("config.toml"),
+            "format = \"flac\"\njobs = 2\n[update]\ntimeout = 5\n",
+        );
+        let config = load(Some(&config_file), &[temp_dir.path().to_path_buf()]).unwrap();
+        assert_eq!(config.format, Some(OutputFormat::Flac));
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.update.timeout, Some(5));
+        assert_eq!(config.replaygain, None);
+    }
+
+    #[test]
+    fn refuses_missing_and_invalid_config_files() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let input_paths = [temp_dir.path().to_path_buf()];
+
+        let missing_file = temp_dir.path().join("missing.toml");
+        let error_message = load(Some(&missing_file), &input_paths).unwrap_err();
+        assert!(error_message.contains("not found"));
+
+        for content in ["jobs = \"many\"", "colour = true", "format = \"wav\""] {
+            let config_file = write_file(temp_dir.path().join("config.toml"), content);
+            let error_message = load(Some(&config_file), &input_paths).unwrap_err();
+            assert!(error_message.starts_with(&config_file.display().to_string()));
+        }
+    }
+
+    #[test]
+    fn directory_config_of_the_input_folder_overrides_the_user_config() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let user_config_file = write_file(
+            temp_dir.path().join("config.toml"),
+            "jobs = 2\nreplaygain = true\n[update]\ntimeout = 5\n",
+        );
+        let album_dir = library(
+            &temp_dir.path().join("Music"),
+            "jobs = 4\n[update]\nchannel = \"prerelease\"\n",
+        );
+
+        for input_path in [album_dir.clone(), album_dir.join("album.cue")] {
+            let config = load(Some(&user_config_file), &[input_path]).unwrap();
+            assert_eq!(config.jobs, Some(4));
+            assert_eq!(config.replaygain, Some(true));
+            assert_eq!(config.update.timeout, Some(5));
+            assert_eq!(config.update.channel, Some(ReleaseChannel::Prerelease));
+        }
+    }
+
+    #[test]
+    fn finds_the_directory_config_relative_to_the_current_folder() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let library_dir = temp_dir.path().join("Music");
+        let album_dir = library(&library_dir, "jobs = 4\n");
+        let config_file = Some(
+            fs::canonicalize(&library_dir)
+                .unwrap()
+                .join(DIRECTORY_CONFIG_FILE_NAME),
+        );
+
+        assert_eq!(
+            find_directory_config_file(&album_dir, &[]).unwrap(),
+            config_file
+        );
+        assert_eq!(
+            find_directory_config_file(&library_dir, &[PathBuf::from("Artist/Album")]).unwrap(),
+            config_file
+        );
+        assert_eq!(
+            find_directory_config_file(&album_dir, &[PathBuf::from("../../..")]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn inputs_with_different_directory_configs_are_refused() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let album_dir = library(&temp_dir.path().join("Music"), "jobs = 4\n");
+        let other_album_dir = library(&temp_dir.path().join("Other"), "jobs = 1\n");
+        let bare_dir = temp_dir.path().join("Bare");
+        fs::create_dir_all(&bare_dir).unwrap();
+
+        let same_library = [album_dir.clone(), album_dir.join("album.cue")];
+        assert!(find_directory_config_file(temp_dir.path(), &same_library).is_ok());
+        for input_paths in [[album_dir.clone(), other_album_dir], [album_dir, bare_dir]] {
+            let error_message =
+                find_directory_config_file(temp_dir.path(), &input_paths).unwrap_err();
+            assert!(error_message.contains("use different .cue-splatter.toml files"));
+        }
+    }
+
+    #[test]
+    fn command_line_options_win_over_the_config_files() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let user_config_file = write_file(
+            temp_dir.path().join("config.toml"),
+            "accept_score = 60\nsave_fixes = false\n",
+        );
+        let album_dir = library(
+            &temp_dir.path().join("Music"),
+            "accept_score = 80\nsave_fixes = true\n",
+        );
+        let config = load(Some(&user_config_file), &[album_dir]).unwrap();
+
+        let cue_options = CueArgs::default().resolve(&config);
+        assert_eq!(cue_options.accept_score, 80);
+        assert!(cue_options.save_fixes);
+
+        let cue_args = CueArgs {
+            accept_score: Some(90),
+            no_save_fixes: true,
+            ..CueArgs::default()
+        };
+        let cue_options = cue_args.resolve(&config);
+        assert_eq!(cue_options.accept_score, 90);
+        assert!(!cue_options.save_fixes);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use colour::yellow_ln;
+use lofty::picture::{Picture, PictureType};
+
+/// Image file extensions that are considered as cover art
+const COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Default file name patterns for cover art, matched against the file name without extension
+pub const DEFAULT_COVER_NAMES: &str = "cover,folder,front";
+
+/// Finds a cover art file in the given directory
+/// The patterns are matched case-insensitive against the file name without extension and may
+/// contain `*` as wildcard, the first pattern with a match wins
+pub fn find_cover_file(dir: &Path, patterns: &str) -> Option<PathBuf> {
+    let image_files: Vec<PathBuf> = dir
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    COVER_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                })
+        })
+        .collect();
+
+    patterns
+        .split(',')
+        .map(|pattern| pattern.trim().to_lowercase())
+        .filter(|pattern| !pattern.is_empty())
+        .find_map(|pattern| {
+            image_files
+                .iter()
+                .find(|path| {
+                    path.file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .is_some_and(|stem| wildcard_match(&pattern, &stem.to_lowercase()))
+                })
+                .cloned()
+        })
+}
+
+/// Loads the cover art file as front cover picture
+/// If `max_size` is set, the image is scaled down to fit into `max_size`x`max_size` pixels and
+/// recompressed as JPEG using ffmpeg
+pub fn load_cover(cover_file: &Path, max_size: Option<u32>) -> Option<Picture> {
+    let picture = match max_size {
+        Some(max_size) => resize_cover(cover_file, max_size),
+        None => File::open(cover_file)
+            .ok()
+            .and_then(|mut file| Picture::from_reader(&mut file).ok()),
+    };
+
+    if picture.is_none() {
+        yellow_ln!(
+            "⚠️ Could not read cover art from {}, skipping it",
+            cover_file.display()
+        );
+    }
+
+    picture.map(|mut picture| {
+        picture.set_pic_type(PictureType::CoverFront);
+        picture
+    })
+}
+
+/// Scales a picture embedded in the source audio file down, like a cover art file
+/// The picture is kept as it is, if it can not be scaled
+pub fn resize_picture(picture: &Picture, max_size: u32) -> Picture {
+    let resized_picture = self_update::TempDir::new().ok().and_then(|temp_dir| {
+        let picture_file = temp_dir.path().join(format!(
+            "picture.{}",
+            picture
+                .mime_type()
+                .and_then(|mime_type| mime_type.ext())
+                .unwrap_or("jpg")
+        ));
+        fs::write(&picture_file, picture.data())
+            .ok()
+            .and_then(|_| resize_cover(&picture_file, max_size))
+    });
+
+    match resized_picture {
+        Some(mut resized_picture) => {
+            resized_picture.set_pic_type(picture.pic_type());
+            resized_picture.set_description(picture.description().map(str::to_string));
+            resized_picture
+        }
+        None => {
+            yellow_ln!("⚠️ Could not scale an embedded picture down, keeping it as it is");
+            picture.clone()
+        }
+    }
+}
+
+/// Scales the cover down and recompresses it as JPEG
+fn resize_cover(cover_file: &Path, max_size: u32) -> Option<Picture> {
+    // ffmpeg overwrites its output, so it must not be a predictable path in the shared temp dir
+    let temp_dir = self_update::TempDir::new().ok()?;
+    let resized_cover_file = temp_dir.path().join("cover.jpg");
+
+    let output = Command::new("ffmpeg")
+        .args(["-y", "-v", "error", "-i"])
+        .arg(cover_file)
+        .arg("-vf")
+        .arg(format!(
+            "scale='min({max_size},iw)':'min({max_size},ih)':force_original_aspect_ratio=decrease"
+        ))
+        .args(["-q:v", "2"])
+        .arg(&resized_cover_file)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    File::open(&resized_cover_file)
+        .ok()
+        .and_then(|mut file| Picture::from_reader(&mut file).ok())
+}
+
+/// Matches a text against a pattern with `*` wildcards
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut remaining = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
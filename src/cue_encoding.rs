@@ -0,0 +1,538 @@
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chardet::charset2encoding;
+use encoding::all::{UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use encoding::label::encoding_from_whatwg_label;
+use encoding::{DecoderTrap, Encoding, EncodingRef};
+
+use crate::cue_writer;
+
+/// Encodings which are tried if the detected encoding produces garbled text
+/// Most cue sheets are written by Windows rippers, so the common Windows code pages come first
+const CANDIDATE_ENCODINGS: [&str; 13] = [
+    "windows-1251",
+    "windows-1252",
+    "windows-1250",
+    "shift_jis",
+    "euc-jp",
+    "gbk",
+    "big5",
+    "euc-kr",
+    "koi8-r",
+    "ibm866",
+    "windows-1253",
+    "windows-1254",
+    "windows-1257",
+];
+
+/// Detections below this confidence are reported as uncertain
+const LOW_CONFIDENCE: f32 = 0.6;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// The character encoding of a cue file, given as WHATWG label, e.g. utf-8, windows-1251 or shift_jis
+#[derive(Copy, Clone)]
+pub struct CueEncoding(EncodingRef);
+
+impl CueEncoding {
+    pub fn utf8() -> CueEncoding {
+        CueEncoding(UTF_8)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.whatwg_name().unwrap_or(self.0.name())
+    }
+
+    pub fn encoding_ref(&self) -> EncodingRef {
+        self.0
+    }
+
+    fn is_utf8(&self) -> bool {
+        self.name() == UTF_8.name()
+    }
+}
+
+impl Debug for CueEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CueEncoding({})", self.name())
+    }
+}
+
+impl FromStr for CueEncoding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        encoding_from_whatwg_label(value)
+            .map(CueEncoding)
+            .ok_or(format!(
+                "unknown encoding '{}', expected a label like utf-8, windows-1251 or shift_jis",
+                value
+            ))
+    }
+}
+
+/// How cue files are rewritten as UTF-8
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CueConversion {
+    /// UTF-8 without byte order mark
+    Utf8,
+    /// UTF-8 with byte order mark, some players and burning tools need it to recognize UTF-8
+    Utf8Bom,
+}
+
+impl FromStr for CueConversion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "utf8" => Ok(CueConversion::Utf8),
+            "utf8-bom" => Ok(CueConversion::Utf8Bom),
+            _ => Err(format!(
+                "unknown cue conversion '{}', expected one of: utf8, utf8-bom",
+                value
+            )),
+        }
+    }
+}
+
+/// The decoded content of a cue file
+pub struct DecodedCue {
+    /// The text without byte order mark
+    pub text: String,
+    pub encoding: CueEncoding,
+    /// The byte order mark of the file, empty if there is none
+    pub bom: &'static [u8],
+    /// Describes how the encoding was determined
+    pub report: String,
+    /// Set if the text might be decoded with the wrong encoding
+    pub uncertain: bool,
+}
+
+/// Reads and decodes a cue file
+/// A given encoding is used instead of detecting it, unless the file starts with a byte order mark
+pub fn read_cue_file(
+    cue_file_path: &Path,
+    encoding_override: Option<CueEncoding>,
+) -> Result<DecodedCue, String> {
+    let data_buffer = fs::read(cue_file_path).map_err(|err| err.to_string())?;
+    Ok(decode_cue(&data_buffer, encoding_override))
+}
+
+/// Rewrites the cue file as UTF-8, the original is kept as backup
+/// Returns the backup file, or None if the cue file is already encoded as requested
+///
+/// A detected encoding which is uncertain or replaced invalid characters would garble the cue file for good,
+/// such cue files are only converted with an explicitly given encoding.
+pub fn convert_cue_file(
+    cue_file_path: &Path,
+    encoding_override: Option<CueEncoding>,
+    conversion: CueConversion,
+) -> Result<Option<PathBuf>, String> {
+    let decoded_cue = read_cue_file(cue_file_path, encoding_override)?;
+    if encoding_override.is_none()
+        && (decoded_cue.uncertain || decoded_cue.text.contains(char::REPLACEMENT_CHARACTER))
+    {
+        return Err(format!(
+            "the encoding {} is uncertain, pass --cue-encoding to convert it anyway",
+            decoded_cue.report
+        ));
+    }
+    let bom = match conversion {
+        CueConversion::Utf8 => &[],
+        CueConversion::Utf8Bom => UTF8_BOM,
+    };
+    if decoded_cue.encoding.is_utf8() && decoded_cue.bom == bom {
+        return Ok(None);
+    }
+
+    let backup_file = cue_writer::backup_cue_file(cue_file_path)?;
+    fs::write(cue_file_path, [bom, decoded_cue.text.as_bytes()].concat())
+        .map_err(|err| err.to_string())?;
+    Ok(Some(backup_file))
+}
+
+fn decode_cue(data_buffer: &[u8], encoding_override: Option<CueEncoding>) -> DecodedCue {
+    // A byte order mark is the most reliable hint
+    if let Some((bom, encoding_ref)) = detect_bom(data_buffer) {
+        let encoding = CueEncoding(encoding_ref);
+        return DecodedCue {
+            text: decode_lossy(encoding_ref, &data_buffer[bom.len()..]),
+            encoding,
+            bom,
+            report: format!("{} (byte order mark)", encoding.name()),
+            uncertain: false,
+        };
+    }
+
+    if let Some(encoding) = encoding_override {
+        return match encoding.0.decode(data_buffer, DecoderTrap::Strict) {
+            Ok(text) => DecodedCue {
+                text,
+                encoding,
+                bom: &[],
+                report: format!("{} (given)", encoding.name()),
+                uncertain: false,
+            },
+            Err(_) => DecodedCue {
+                text: decode_lossy(encoding.0, data_buffer),
+                encoding,
+                bom: &[],
+                report: format!(
+                    "{} (given, but the file is not valid {}, invalid characters were replaced)",
+                    encoding.name(),
+                    encoding.name()
+                ),
+                uncertain: true,
+            },
+        };
+    }
+
+    // Valid UTF-8 with non-ASCII characters is hardly ever a coincidence
+    if let Ok(text) = std::str::from_utf8(data_buffer) {
+        return DecodedCue {
+            text: text.to_string(),
+            encoding: CueEncoding::utf8(),
+            bom: &[],
+            report: format!("{} (valid UTF-8)", UTF_8.name()),
+            uncertain: false,
+        };
+    }
+
+    let (charset, confidence, _) = chardet::detect(data_buffer);
+    let detected_encoding = encoding_from_whatwg_label(charset2encoding(&charset));
+
+    // Keep the detected encoding if it produces plausible text
+    if let Some(detected_encoding) = detected_encoding {
+        if let Ok(text) = detected_encoding.decode(data_buffer, DecoderTrap::Strict) {
+            if mojibake_score(&text) == 0 {
+                let encoding = CueEncoding(detected_encoding);
+                return DecodedCue {
+                    text,
+                    encoding,
+                    bom: &[],
+                    report: format!(
+                        "{} (detected with {:.0}% confidence)",
+                        encoding.name(),
+                        confidence * 100.0
+                    ),
+                    uncertain: confidence < LOW_CONFIDENCE,
+                };
+            }
+        }
+    }
+
+    // Otherwise take the encoding producing the least garbled text, the detected encoding wins ties
+    let best_candidate = detected_encoding
+        .into_iter()
+        .chain(
+            CANDIDATE_ENCODINGS
+                .iter()
+                .flat_map(|label| encoding_from_whatwg_label(label)),
+        )
+        .filter_map(|encoding_ref| {
+            encoding_ref
+                .decode(data_buffer, DecoderTrap::Strict)
+                .ok()
+                .map(|text| (mojibake_score(&text), encoding_ref, text))
+        })
+        .min_by_key(|(score, _, _)| *score);
+
+    let detected_name = detected_encoding
+        .map(|encoding_ref| CueEncoding(encoding_ref).name())
+        .unwrap_or(&charset);
+    match best_candidate {
+        Some((_, encoding_ref, text)) => {
+            let encoding = CueEncoding(encoding_ref);
+            let report = if detected_encoding
+                .is_some_and(|detected| detected.name() == encoding_ref.name())
+            {
+                format!(
+                    "{} (detected with {:.0}% confidence, but the text looks garbled)",
+                    encoding.name(),
+                    confidence * 100.0
+                )
+            } else {
+                format!(
+                    "{} (guessed, the detected {} with {:.0}% confidence produced garbled text)",
+                    encoding.name(),
+                    detected_name,
+                    confidence * 100.0
+                )
+            };
+            DecodedCue {
+                text,
+                encoding,
+                bom: &[],
+                report,
+                uncertain: true,
+            }
+        }
+        None => {
+            let encoding_ref = detected_encoding.unwrap_or(WINDOWS_1252);
+            DecodedCue {
+                text: decode_lossy(encoding_ref, data_buffer),
+                encoding: CueEncoding(encoding_ref),
+                bom: &[],
+                report: format!(
+                    "{} (no encoding fits, invalid characters were replaced)",
+                    CueEncoding(encoding_ref).name()
+                ),
+                uncertain: true,
+            }
+        }
+    }
+}
+
+fn detect_bom(data_buffer: &[u8]) -> Option<(&'static [u8], EncodingRef)> {
+    [
+        (UTF8_BOM, UTF_8 as EncodingRef),
+        (UTF16LE_BOM, UTF_16LE as EncodingRef),
+        (UTF16BE_BOM, UTF_16BE as EncodingRef),
+    ]
+    .into_iter()
+    .find(|(bom, _)| data_buffer.starts_with(bom))
+}
+
+/// Decodes the data, invalid characters are replaced instead of silently dropped
+fn decode_lossy(encoding_ref: EncodingRef, data_buffer: &[u8]) -> String {
+    encoding_ref
+        .decode(data_buffer, DecoderTrap::Replace)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Cjk,
+    Hangul,
+    Other,
+}
+
+fn script(character: char) -> Option<Script> {
+    if !character.is_alphabetic() {
+        return None;
+    }
+    let script = match character as u32 {
+        0x0000..=0x024F => Script::Latin,
+        0x0370..=0x03FF => Script::Greek,
+        0x0400..=0x04FF => Script::Cyrillic,
+        0x3040..=0x30FF | 0x3400..=0x9FFF | 0xFF66..=0xFF9F => Script::Cjk,
+        0xAC00..=0xD7AF | 0x1100..=0x11FF => Script::Hangul,
+        _ => Script::Other,
+    };
+    Some(script)
+}
+
+/// Typographic punctuation which is common next to letters, e.g. quotes, dashes and CJK punctuation
+fn is_typographic(character: char) -> bool {
+    matches!(
+        character,
+        '«' | '»'
+            | '“'
+            | '”'
+            | '„'
+            | '‘'
+            | '’'
+            | '–'
+            | '—'
+            | '…'
+            | '·'
+            | '•'
+            | '°'
+            | '´'
+            | '¡'
+            | '¿'
+            | '・'
+    ) || (0x3000..=0x303F).contains(&(character as u32))
+        || (0xFF01..=0xFF65).contains(&(character as u32))
+}
+
+/// Scores how garbled a decoded text looks, 0 means plausible text
+///
+/// Typical patterns of text decoded with the wrong code page are control and replacement characters,
+/// unusual white space, symbols next to letters, mixed scripts within words, UTF-8 sequences read as Latin-1 (e.g. "Ã©"),
+/// long runs of accented Latin letters (e.g. "Ïðèâåò" instead of "Привет") and half-width katakana.
+fn mojibake_score(text: &str) -> usize {
+    // Unusual white space, e.g. no-break spaces, splits garbled words
+    let mut score = text
+        .chars()
+        .filter(|character| {
+            character.is_whitespace() && !character.is_ascii() && *character != '\u{3000}'
+        })
+        .count()
+        * 3;
+
+    for word in text.split_whitespace() {
+        let characters: Vec<char> = word.chars().collect();
+        let mut accented_run = 0;
+
+        for (index, character) in characters.iter().enumerate() {
+            let code_point = *character as u32;
+            if *character == '\u{fffd}'
+                || (0x80..=0x9F).contains(&code_point)
+                || character.is_control()
+            {
+                score += 10;
+            }
+            if (0xFF66..=0xFF9F).contains(&code_point) {
+                score += 2;
+            }
+
+            // UTF-8 lead bytes read as Latin-1, followed by a continuation byte
+            if let Some(next_character) = characters.get(index + 1) {
+                if ['Ã', 'Â', 'Ð', 'Ñ'].contains(character)
+                    && (0x80..=0xBF).contains(&(*next_character as u32))
+                {
+                    score += 5;
+                }
+            }
+
+            // Non-ASCII symbols next to letters
+            if !character.is_ascii() && !character.is_alphanumeric() && !is_typographic(*character)
+            {
+                let is_next_to_letter = (index > 0 && characters[index - 1].is_alphabetic())
+                    || characters
+                        .get(index + 1)
+                        .is_some_and(|next| next.is_alphabetic());
+                if is_next_to_letter {
+                    score += 2;
+                }
+            }
+
+            // Scripts changing within a word
+            if index > 0 {
+                if let (Some(previous_script), Some(current_script)) =
+                    (script(characters[index - 1]), script(*character))
+                {
+                    if previous_script != current_script {
+                        score += 3;
+                    }
+                }
+            }
+
+            // Runs of accented Latin letters are rare in real words
+            if (0xC0..=0x24F).contains(&code_point) && character.is_alphabetic() {
+                accented_run += 1;
+                if accented_run >= 4 {
+                    score += 1;
+                }
+            } else {
+                accented_run = 0;
+            }
+        }
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding::EncoderTrap;
+
+    use super::*;
+
+    const CYRILLIC_TEXT: &str = "TITLE \"Привет мир\"\r\nPERFORMER \"Кино\"\r\n";
+
+    fn encode(label: &str, text: &str) -> Vec<u8> {
+        encoding_from_whatwg_label(label)
+            .unwrap()
+            .encode(text, EncoderTrap::Strict)
+            .unwrap()
+    }
+
+    #[test]
+    fn decode_cue_recognizes_byte_order_marks() {
+        let data_buffer = [UTF8_BOM, "TITLE \"Café\"".as_bytes()].concat();
+        let decoded_cue = decode_cue(&data_buffer, None);
+        assert_eq!(decoded_cue.text, "TITLE \"Café\"");
+        assert_eq!(decoded_cue.bom, UTF8_BOM);
+        assert!(decoded_cue.encoding.is_utf8());
+        assert!(!decoded_cue.uncertain);
+
+        let data_buffer = [UTF16LE_BOM, &encode("utf-16le", "TITLE \"Café\"")].concat();
+        let decoded_cue = decode_cue(&data_buffer, None);
+        assert_eq!(decoded_cue.text, "TITLE \"Café\"");
+        assert_eq!(decoded_cue.encoding.name(), "utf-16le");
+    }
+
+    #[test]
+    fn decode_cue_prefers_valid_utf8() {
+        let decoded_cue = decode_cue(CYRILLIC_TEXT.as_bytes(), None);
+        assert_eq!(decoded_cue.text, CYRILLIC_TEXT);
+        assert!(decoded_cue.encoding.is_utf8());
+        assert!(decoded_cue.bom.is_empty());
+        assert!(!decoded_cue.uncertain);
+    }
+
+    #[test]
+    fn decode_cue_finds_windows_1251() {
+        let decoded_cue = decode_cue(&encode("windows-1251", CYRILLIC_TEXT), None);
+        assert_eq!(decoded_cue.text, CYRILLIC_TEXT);
+        assert_eq!(decoded_cue.encoding.name(), "windows-1251");
+    }
+
+    #[test]
+    fn decode_cue_finds_shift_jis() {
+        let text = "TITLE \"東京の夜\"\r\nPERFORMER \"山田太郎\"\r\n";
+        let decoded_cue = decode_cue(&encode("shift_jis", text), None);
+        assert_eq!(decoded_cue.text, text);
+        assert_eq!(decoded_cue.encoding.name(), "shift_jis");
+    }
+
+    #[test]
+    fn decode_cue_uses_the_given_encoding() {
+        let data_buffer = encode("windows-1251", CYRILLIC_TEXT);
+        let encoding = "koi8-r".parse::<CueEncoding>().unwrap();
+        let decoded_cue = decode_cue(&data_buffer, Some(encoding));
+        assert_eq!(decoded_cue.encoding.name(), "koi8-r");
+        assert!(decoded_cue.report.contains("given"));
+        assert_ne!(decoded_cue.text, CYRILLIC_TEXT);
+    }
+
+    #[test]
+    fn decode_cue_marks_invalid_text_given_encoding_as_uncertain() {
+        let data_buffer = [b"TITLE \"".as_slice(), &[0xFF, 0xFE, 0xFD], b"\""].concat();
+        let decoded_cue = decode_cue(&data_buffer, Some(CueEncoding::utf8()));
+        assert!(decoded_cue.uncertain);
+        assert!(decoded_cue.text.contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn mojibake_score_accepts_plain_text() {
+        assert_eq!(mojibake_score("TITLE \"Hello World\""), 0);
+        assert_eq!(mojibake_score("TITLE \"Café – «Déjà vu»\""), 0);
+        assert_eq!(mojibake_score(CYRILLIC_TEXT), 0);
+        assert_eq!(mojibake_score("TITLE \"東京の夜、雨。\""), 0);
+    }
+
+    #[test]
+    fn mojibake_score_detects_garbled_text() {
+        // UTF-8 read as windows-1252
+        assert!(mojibake_score("TITLE \"CafÃ©\"") > 0);
+        // windows-1251 read as windows-1252
+        assert!(mojibake_score("TITLE \"Ïðèâåò ìèð\"") > 0);
+        // Replacement and control characters
+        assert!(mojibake_score("TITLE \"Caf\u{fffd}\"") >= 10);
+        assert!(mojibake_score("TITLE \"Caf\u{0081}\"") >= 10);
+        // Scripts changing within a word
+        assert!(mojibake_score("TITLE \"Приvet\"") > 0);
+    }
+
+    #[test]
+    fn mojibake_score_ranks_the_right_encoding_lowest() {
+        let data_buffer = encode("windows-1251", CYRILLIC_TEXT);
+        let decoded_score = |label: &str| {
+            let encoding_ref = encoding_from_whatwg_label(label).unwrap();
+            mojibake_score(&decode_lossy(encoding_ref, &data_buffer))
+        };
+        assert_eq!(decoded_score("windows-1251"), 0);
+        assert!(decoded_score("windows-1252") > 0);
+    }
+}
@@ -0,0 +1,695 @@
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use colour::yellow_ln;
+use encoding::EncoderTrap;
+
+use crate::{cue_encoding, parse_cue_text, playlist, CueDuration, CueFile, CueSheet, Track};
+
+/// File name suffix of split cue sheets, e.g. "Album.split.cue"
+const SPLIT_CUE_SUFFIX: &str = "split.cue";
+
+/// File name suffix of the backup of a modified cue file, e.g. "Album.cue.bak"
+const BACKUP_SUFFIX: &str = "bak";
+
+/// Writes a split cue sheet for each cue sheet into its output directory
+/// The split cue sheet references one FILE per split track, so that players and burners can still
+/// treat the tracks as one album
+pub fn write_split_cue_sheets(cue_sheets: &[CueSheet]) {
+    println!("📝 Writing split cue sheets");
+    for cue_sheet in cue_sheets {
+        let split_cue_file = split_cue_file_path(cue_sheet);
+        match fs::write(&split_cue_file, build_split_cue_sheet(cue_sheet)) {
+            Ok(_) => println!("📝 Wrote split cue sheet: {}", split_cue_file.display()),
+            Err(err) => yellow_ln!(
+                "⚠️ Could not write split cue sheet {}: {}",
+                split_cue_file.display(),
+                err
+            ),
+        }
+    }
+}
+
+fn split_cue_file_path(cue_sheet: &CueSheet) -> PathBuf {
+    let cue_file_stem = cue_sheet
+        .cue_file_path
+        .file_stem()
+        .unwrap()
+        .to_string_lossy();
+    cue_sheet
+        .output_dir
+        .as_ref()
+        .unwrap()
+        .join(format!("{}.{}", cue_file_stem, SPLIT_CUE_SUFFIX))
+}
+
+/// Builds the split cue sheet
+///
+/// Each track file starts at INDEX 01 of its track, so the pregap of the next track is at the end of
+/// the previous track file. This is expressed by an INDEX 00 of the next track inside the FILE block
+/// of the previous track, relative to the start of the previous track file.
+fn build_split_cue_sheet(cue_sheet: &CueSheet) -> String {
+    let mut lines = Vec::new();
+
+    if let Some(ref performer) = cue_sheet.performer {
+        lines.push(format!("PERFORMER {}", quote(performer)));
+    }
+    if let Some(ref title) = cue_sheet.title {
+        lines.push(format!("TITLE {}", quote(title)));
+    }
+
+    // Set if the TRACK header was already written with an INDEX 00 in the previous FILE block
+    let mut track_header_written = false;
+
+    for (index, track) in cue_sheet.tracks.iter().enumerate() {
+        let output_file = track.output_file.as_ref().unwrap();
+        let output_file_name =
+            playlist::relative_path(cue_sheet.output_dir.as_ref().unwrap(), output_file);
+        let output_file_name = output_file_name.to_string_lossy();
+
+        lines.push(format!(
+            "FILE {} {}",
+            quote(&output_file_name),
+            file_type(output_file)
+        ));
+        if !track_header_written {
+            lines.extend(track_header(track));
+        }
+        lines.push(format!("    INDEX 01 {}", CueDuration::default()));
+        lines.extend(track_footer(track));
+
+        // The pregap of the next track lives at the end of this track file
+        track_header_written = false;
+        if let Some(next_track) = cue_sheet.tracks.get(index + 1) {
+            if let Some(pregap_offset) = pregap_offset(track, next_track) {
+                lines.extend(track_header(next_track));
+                lines.push(format!("    INDEX 00 {}", pregap_offset));
+                track_header_written = true;
+            }
+        }
+    }
+
+    lines.iter().map(|line| format!("{}\r\n", line)).collect()
+}
+
+/// Builds the TRACK command and all track commands which have to precede the INDEX commands
+fn track_header(track: &Track) -> Vec<String> {
+    let mut lines = vec![format!("  TRACK {:02} {}", track.number, track.track_type)];
+    if !track.flags.is_empty() {
+        lines.push(format!("    FLAGS {}", track.flags.join(" ")));
+    }
+    if let Some(ref title) = track.title {
+        lines.push(format!("    TITLE {}", quote(title)));
+    }
+    if let Some(ref artist) = track.artist {
+        lines.push(format!("    PERFORMER {}", quote(artist)));
+    }
+    lines.extend(
+        track
+            .other_commands
+            .iter()
+            .filter(|command| !is_postgap(command))
+            .map(|command| format!("    {}", command)),
+    );
+    lines
+}
+
+/// Builds the track commands which have to follow the INDEX commands, this is only POSTGAP
+fn track_footer(track: &Track) -> Vec<String> {
+    track
+        .other_commands
+        .iter()
+        .filter(|command| is_postgap(command))
+        .map(|command| format!("    {}", command))
+        .collect()
+}
+
+fn is_postgap(command: &str) -> bool {
+    command.split_whitespace().next() == Some("POSTGAP")
+}
+
+/// Calculates the start of the next track's pregap relative to the start of the given track
+fn pregap_offset(track: &Track, next_track: &Track) -> Option<CueDuration> {
+    let start_time = track.start_time?;
+    let pregap_start_time = next_track.pregap_start_time?;
+    let next_start_time = next_track.start_time?;
+
+    if pregap_start_time > start_time && pregap_start_time < next_start_time {
+        Some(CueDuration::from_frames(
+            pregap_start_time.total_frames() - start_time.total_frames(),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Writes the cue sheet as UTF-8 text file
+/// The written text is parsed again to make sure nothing was lost on the way
+pub fn write_cue_file(cue_sheet: &CueSheet, cue_file: &Path) -> Result<(), String> {
+    let cue_text = build_cue_sheet(cue_sheet);
+    verify_round_trip(cue_sheet, cue_file, &cue_text)?;
+    fs::write(cue_file, cue_text).map_err(|err| err.to_string())
+}
+
+/// Parses the written cue text and compares it with the cue sheet it was written from
+/// Values are only expected to differ by surrounding whitespace, see `quote`
+fn verify_round_trip(cue_sheet: &CueSheet, cue_file: &Path, cue_text: &str) -> Result<(), String> {
+    let reparsed = parse_cue_text(cue_file, cue_text);
+    let round_trip_error = |field: String| {
+        format!(
+            "The cue sheet does not survive a round trip, {} was not read back",
+            field
+        )
+    };
+
+    // All fields are listed, so that a new field can not be forgotten here
+    // The ones not stored in the cue text are skipped
+    let CueSheet {
+        cue_file_path: _,
+        audio_file_path: _,
+        audio_file_name,
+        audio_file_type,
+        audio_files: _,
+        cue_encoding: _,
+        output_dir: _,
+        title,
+        performer,
+        rem_disc_number,
+        rem_total_discs,
+        disc_number: _,
+        total_discs: _,
+        source_tags: _,
+        cover: _,
+        audio_codec: _,
+        audio_duration: _,
+        problems: _,
+        fixes: _,
+        other_commands,
+        invalid_commands,
+        tracks,
+    } = cue_sheet;
+    let audio_files: Vec<CueFile> = written_audio_files(cue_sheet)
+        .into_iter()
+        .map(|audio_file| CueFile {
+            name: quoted_value(&audio_file.name),
+            ..audio_file
+        })
+        .collect();
+
+    check_field("PERFORMER", quoted(performer), reparsed.performer)
+        .and(check_field("TITLE", quoted(title), reparsed.title))
+        .and(check_field(
+            "FILE",
+            (quoted_value(audio_file_name), audio_file_type),
+            (reparsed.audio_file_name, &reparsed.audio_file_type),
+        ))
+        .and(check_field(
+            "FILE blocks",
+            &audio_files,
+            &reparsed.audio_files,
+        ))
+        .and(check_field(
+            "REM DISCNUMBER and TOTALDISCS",
+            (rem_disc_number, rem_total_discs),
+            (&reparsed.rem_disc_number, &reparsed.rem_total_discs),
+        ))
+        .and(check_field(
+            "album commands",
+            other_commands,
+            &reparsed.other_commands,
+        ))
+        .and(check_field(
+            "invalid commands",
+            invalid_commands,
+            &reparsed.invalid_commands,
+        ))
+        .and(check_field(
+            "number of tracks",
+            tracks.len(),
+            reparsed.tracks.len(),
+        ))
+        .map_err(round_trip_error)?;
+
+    for (track, reparsed_track) in tracks.iter().zip(&reparsed.tracks) {
+        let field = |name: &str| format!("{} of track {:02}", name, track.number);
+        let Track {
+            number,
+            track_type,
+            flags,
+            title,
+            artist,
+            pregap_start_time,
+            start_time,
+            other_indexes,
+            other_commands: _,
+            output_file: _,
+            ffmpeg_args: _,
+            ffmpeg_command: _,
+        } = track;
+        check_field(
+            &field("TRACK"),
+            (number, track_type),
+            (&reparsed_track.number, &reparsed_track.track_type),
+        )
+        .and(check_field(&field("FLAGS"), flags, &reparsed_track.flags))
+        .and(check_field(
+            &field("TITLE"),
+            quoted(title),
+            reparsed_track.title.clone(),
+        ))
+        .and(check_field(
+            &field("PERFORMER"),
+            quoted(artist),
+            reparsed_track.artist.clone(),
+        ))
+        .and(check_field(
+            &field("INDEX 00"),
+            pregap_start_time,
+            &reparsed_track.pregap_start_time,
+        ))
+        .and(check_field(
+            &field("INDEX 01"),
+            start_time,
+            &reparsed_track.start_time,
+        ))
+        .and(check_field(
+            &field("INDEX"),
+            other_indexes,
+            &reparsed_track.other_indexes,
+        ))
+        .and(check_field(
+            &field("commands"),
+            &written_track_commands(track),
+            &reparsed_track.other_commands,
+        ))
+        .map_err(round_trip_error)?;
+    }
+    Ok(())
+}
+
+fn check_field<T: PartialEq + Debug>(field: &str, original: T, reparsed: T) -> Result<(), String> {
+    if original == reparsed {
+        Ok(())
+    } else {
+        Err(format!("{} {:?} (read {:?})", field, original, reparsed))
+    }
+}
+
+/// The value as it is read back after `quote`
+fn quoted_value(value: &str) -> String {
+    value.trim().to_string()
+}
+
+fn quoted(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(quoted_value)
+}
+
+/// The uninterpreted track commands in the order they are written, POSTGAP follows the INDEX commands
+fn written_track_commands(track: &Track) -> Vec<String> {
+    let (postgap_commands, commands): (Vec<String>, Vec<String>) = track
+        .other_commands
+        .iter()
+        .cloned()
+        .partition(|command| is_postgap(command));
+    [commands, postgap_commands].concat()
+}
+
+/// Rewrites the FILE command of the cue file, so it references the audio file of the cue sheet
+///
+/// All other lines stay untouched and the original cue file is kept as backup. The original encoding is kept if
+/// it can represent the new file name, otherwise the cue file is converted to UTF-8.
+pub fn save_audio_file_reference(cue_sheet: &CueSheet) -> Result<PathBuf, String> {
+    let cue_file = &cue_sheet.cue_file_path;
+    let decoded_cue = cue_encoding::read_cue_file(cue_file, Some(cue_sheet.cue_encoding))?;
+
+    let mut file_line_found = false;
+    let new_cue_text: String = decoded_cue
+        .text
+        .split_inclusive('\n')
+        .map(|line| {
+            if !file_line_found && line.trim_start().starts_with("FILE ") {
+                file_line_found = true;
+                rewrite_file_line(line, cue_sheet)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if !file_line_found {
+        return Err("The cue file has no FILE command".to_string());
+    }
+
+    // Make sure the written cue sheet references the audio file, e.g. line breaks in file names can not be written
+    let reparsed_cue_sheet = parse_cue_text(cue_file, &new_cue_text);
+    if reparsed_cue_sheet.audio_file_name != cue_sheet.audio_file_name {
+        return Err(format!(
+            "The file name {} can not be written into a cue sheet",
+            cue_sheet.audio_file_name
+        ));
+    }
+
+    let encoding_ref = decoded_cue.encoding.encoding_ref();
+    let content = match encoding_ref.encode(&new_cue_text, EncoderTrap::Strict) {
+        Ok(content) => [decoded_cue.bom, &content].concat(),
+        Err(_) => {
+            yellow_ln!(
+                "⚠️ The file name can not be encoded as {}, converting the cue file to UTF-8",
+                decoded_cue.encoding.name()
+            );
+            new_cue_text.into_bytes()
+        }
+    };
+
+    let backup_file = backup_cue_file(cue_file)?;
+    fs::write(cue_file, content).map_err(|err| err.to_string())?;
+
+    Ok(backup_file)
+}
+
+/// Copies the cue file to its backup file, an existing backup is not overwritten, as it holds the oldest version
+pub fn backup_cue_file(cue_file: &Path) -> Result<PathBuf, String> {
+    let backup_file = backup_file_path(cue_file);
+    if !backup_file.exists() {
+        fs::copy(cue_file, &backup_file).map_err(|err| err.to_string())?;
+    }
+    Ok(backup_file)
+}
+
+/// Replaces the FILE command of the line, indentation and line ending are kept
+fn rewrite_file_line(line: &str, cue_sheet: &CueSheet) -> String {
+    let line_start = &line[..line.len() - line.trim_start().len()];
+    let line_ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+    format!(
+        "{}FILE {} {}{}",
+        line_start,
+        quote(&cue_sheet.audio_file_name),
+        cue_sheet.audio_file_type,
+        line_ending
+    )
+}
+
+fn backup_file_path(cue_file: &Path) -> PathBuf {
+    let mut backup_file_name = cue_file.file_name().unwrap().to_os_string();
+    backup_file_name.push(format!(".{}", BACKUP_SUFFIX));
+    cue_file.with_file_name(backup_file_name)
+}
+
+/// Builds the cue text of a cue sheet
+///
+/// All album and track commands are written, including the ones cue-splatter does not interpret, like REM,
+/// CATALOG, ISRC or FLAGS, and the ones whose value could not be parsed. Each audio file gets its own FILE block.
+pub fn build_cue_sheet(cue_sheet: &CueSheet) -> String {
+    let tracks = &cue_sheet.tracks;
+    let mut lines: Vec<String> = cue_sheet.other_commands.clone();
+    lines.extend(invalid_commands(cue_sheet, None).map(str::to_string));
+
+    if let Some(ref performer) = cue_sheet.performer {
+        lines.push(format!("PERFORMER {}", quote(performer)));
+    }
+    if let Some(ref title) = cue_sheet.title {
+        lines.push(format!("TITLE {}", quote(title)));
+    }
+
+    // Tracks before the first FILE command are not counted by any FILE command
+    let audio_files = written_audio_files(cue_sheet);
+    let counted_tracks: usize = audio_files
+        .iter()
+        .map(|audio_file| audio_file.track_count)
+        .sum();
+    let mut first_track_index = tracks.len().saturating_sub(counted_tracks);
+    let mut files_before_track: Vec<Vec<&CueFile>> = vec![Vec::new(); tracks.len() + 1];
+    let mut files_inside_track: Vec<Vec<&CueFile>> = vec![Vec::new(); tracks.len()];
+    for audio_file in &audio_files {
+        match first_track_index.checked_sub(1) {
+            Some(previous_track_index) if audio_file.starts_inside_track => {
+                files_inside_track[previous_track_index].push(audio_file)
+            }
+            _ => files_before_track[first_track_index.min(tracks.len())].push(audio_file),
+        }
+        first_track_index += audio_file.track_count;
+    }
+
+    for (index, track) in tracks.iter().enumerate() {
+        lines.extend(
+            files_before_track[index]
+                .iter()
+                .map(|audio_file| file_command(audio_file)),
+        );
+
+        // A TRACK command with an invalid number is written as it was read
+        let (invalid_track_commands, invalid_track_values): (Vec<&str>, Vec<&str>) =
+            invalid_commands(cue_sheet, Some(track.number))
+                .partition(|command| command.starts_with("TRACK"));
+        let mut header = track_header(track);
+        if let Some(invalid_track_command) = invalid_track_commands.first() {
+            header[0] = format!("  {}", invalid_track_command);
+        }
+        lines.extend(header);
+
+        if let Some(pregap_start_time) = track.pregap_start_time {
+            lines.push(format!("    INDEX 00 {}", pregap_start_time));
+        }
+        lines.extend(
+            files_inside_track[index]
+                .iter()
+                .map(|audio_file| file_command(audio_file)),
+        );
+        if let Some(start_time) = track.start_time {
+            lines.push(format!("    INDEX 01 {}", start_time));
+        }
+        for (index_number, index_time) in &track.other_indexes {
+            lines.push(format!("    INDEX {:02} {}", index_number, index_time));
+        }
+        lines.extend(
+            invalid_track_values
+                .iter()
+                .map(|command| format!("    {}", command)),
+        );
+        lines.extend(track_footer(track));
+    }
+    lines.extend(
+        files_before_track[tracks.len()]
+            .iter()
+            .map(|audio_file| file_command(audio_file)),
+    );
+
+    lines.iter().map(|line| format!("{}\r\n", line)).collect()
+}
+
+/// The FILE commands to write, a single FILE command references the audio file of the cue sheet, which may be fixed
+fn written_audio_files(cue_sheet: &CueSheet) -> Vec<CueFile> {
+    match cue_sheet.audio_files.as_slice() {
+        [audio_file] => vec![CueFile {
+            name: cue_sheet.audio_file_name.clone(),
+            file_type: cue_sheet.audio_file_type.clone(),
+            ..audio_file.clone()
+        }],
+        audio_files => audio_files.to_vec(),
+    }
+}
+
+fn file_command(audio_file: &CueFile) -> String {
+    format!("FILE {} {}", quote(&audio_file.name), audio_file.file_type)
+        .trim_end()
+        .to_string()
+}
+
+/// The commands of the album (`None`) or of a track, whose value could not be parsed
+fn invalid_commands(cue_sheet: &CueSheet, track_number: Option<u32>) -> impl Iterator<Item = &str> {
+    cue_sheet
+        .invalid_commands
+        .iter()
+        .filter(move |(command_track_number, _)| *command_track_number == track_number)
+        .map(|(_, command)| command.as_str())
+}
+
+/// Quotes a cue sheet value
+/// Cue sheets have no escaping, double quotes inside the value are kept, as readers take the outer quotes
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.trim())
+}
+
+/// Returns the cue sheet file type for an audio file
+pub fn file_type(audio_file: &Path) -> &'static str {
+    let extension = audio_file
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" => "MP3",
+        "aiff" | "aif" => "AIFF",
+        _ => "WAVE",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUE_TEXT: &str = r#"REM GENRE Rock
+REM DATE 1994
+CATALOG 0123456789012
+PERFORMER "The Band"
+TITLE "The Album"
+FILE "The Album.flac" WAVE
+  TRACK 01 AUDIO
+    FLAGS DCP PRE
+    TITLE "First"
+    ISRC USABC9400001
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "Guest"
+    REM COMPOSER "Someone"
+    INDEX 00 03:58:40
+    INDEX 01 04:00:00
+    INDEX 02 05:10:12
+  TRACK 03 AUDIO
+    TITLE "Third"
+    INDEX 00 08:30:00
+    INDEX 01 08:32:70
+    POSTGAP 00:02:00
+"#;
+
+    fn parse(cue_text: &str) -> CueSheet {
+        parse_cue_text(Path::new("/music/The Album.cue"), cue_text)
+    }
+
+    #[test]
+    fn round_trip_keeps_all_commands() {
+        let cue_sheet = parse(CUE_TEXT);
+        let cue_text = build_cue_sheet(&cue_sheet);
+        verify_round_trip(&cue_sheet, &cue_sheet.cue_file_path, &cue_text).unwrap();
+
+        let reparsed = parse(&cue_text);
+        assert_eq!(
+            reparsed.other_commands,
+            ["REM GENRE Rock", "REM DATE 1994", "CATALOG 0123456789012"]
+        );
+        assert_eq!(reparsed.performer.as_deref(), Some("The Band"));
+        assert_eq!(reparsed.title.as_deref(), Some("The Album"));
+        assert_eq!(reparsed.audio_file_name, "The Album.flac");
+        assert_eq!(reparsed.tracks.len(), 3);
+
+        let first_track = &reparsed.tracks[0];
+        assert_eq!(first_track.flags, ["DCP", "PRE"]);
+        assert_eq!(first_track.other_commands, ["ISRC USABC9400001"]);
+
+        let second_track = &reparsed.tracks[1];
+        assert_eq!(second_track.artist.as_deref(), Some("Guest"));
+        assert_eq!(second_track.other_commands, ["REM COMPOSER \"Someone\""]);
+        assert_eq!(
+            second_track.pregap_start_time.unwrap().to_string(),
+            "03:58:40"
+        );
+        assert_eq!(second_track.start_time.unwrap().to_string(), "04:00:00");
+        assert_eq!(second_track.other_indexes.len(), 1);
+        assert_eq!(second_track.other_indexes[0].0, 2);
+        assert_eq!(second_track.other_indexes[0].1.to_string(), "05:10:12");
+
+        let third_track = &reparsed.tracks[2];
+        assert_eq!(third_track.start_time.unwrap().to_string(), "08:32:70");
+        assert_eq!(third_track.other_commands, ["POSTGAP 00:02:00"]);
+    }
+
+    /// Parses, writes and parses the cue text again, the written text must survive another round trip unchanged
+    fn round_trip(cue_text: &str) -> (CueSheet, String) {
+        let cue_sheet = parse(cue_text);
+        let written_cue_text = build_cue_sheet(&cue_sheet);
+        verify_round_trip(&cue_sheet, &cue_sheet.cue_file_path, &written_cue_text).unwrap();
+        assert_eq!(build_cue_sheet(&parse(&written_cue_text)), written_cue_text);
+        (parse(&written_cue_text), written_cue_text)
+    }
+
+    #[test]
+    fn round_trip_keeps_quotes_inside_values() {
+        let (reparsed, _) = round_trip(
+            "TITLE \"12\" Mix\"\nFILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"Say \"Hi\"\"\n    INDEX 01 00:00:00\n",
+        );
+        assert_eq!(reparsed.title.as_deref(), Some("12\" Mix"));
+        assert_eq!(reparsed.tracks[0].title.as_deref(), Some("Say \"Hi\""));
+    }
+
+    #[test]
+    fn round_trip_trims_values() {
+        let mut cue_sheet = parse(CUE_TEXT);
+        cue_sheet.tracks[0].title = Some(" First ".to_string());
+        let cue_text = build_cue_sheet(&cue_sheet);
+        verify_round_trip(&cue_sheet, &cue_sheet.cue_file_path, &cue_text).unwrap();
+        assert_eq!(parse(&cue_text).tracks[0].title.as_deref(), Some("First"));
+    }
+
+    #[test]
+    fn round_trip_detects_lost_values() {
+        let mut cue_sheet = parse(CUE_TEXT);
+        // A command with a line break is read back as two commands
+        cue_sheet.tracks[1]
+            .other_commands
+            .push("ISRC USABC9400002\nREM X".to_string());
+        let cue_text = build_cue_sheet(&cue_sheet);
+        let error_message =
+            verify_round_trip(&cue_sheet, &cue_sheet.cue_file_path, &cue_text).unwrap_err();
+        assert!(error_message.contains("commands of track 02"));
+    }
+
+    #[test]
+    fn round_trip_keeps_multiple_audio_files() {
+        let (reparsed, cue_text) = round_trip(
+            "FILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 01:00:00\nFILE \"b.flac\" WAVE\n  TRACK 03 AUDIO\n    INDEX 01 00:00:00\n",
+        );
+        assert_eq!(
+            cue_text,
+            "FILE \"a.flac\" WAVE\r\n  TRACK 01 AUDIO\r\n    INDEX 01 00:00:00\r\n  TRACK 02 AUDIO\r\n    INDEX 01 01:00:00\r\nFILE \"b.flac\" WAVE\r\n  TRACK 03 AUDIO\r\n    INDEX 01 00:00:00\r\n"
+        );
+        let track_counts: Vec<usize> = reparsed
+            .audio_files
+            .iter()
+            .map(|audio_file| audio_file.track_count)
+            .collect();
+        assert_eq!(track_counts, [2, 1]);
+    }
+
+    #[test]
+    fn round_trip_keeps_pregaps_in_the_previous_audio_file() {
+        let (reparsed, cue_text) = round_trip(
+            "FILE \"01.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 00 04:10:00\nFILE \"02.wav\" WAVE\n    INDEX 01 00:00:00\n",
+        );
+        assert!(cue_text.ends_with(
+            "  TRACK 02 AUDIO\r\n    INDEX 00 04:10:00\r\nFILE \"02.wav\" WAVE\r\n    INDEX 01 00:00:00\r\n"
+        ));
+        assert!(reparsed.audio_files[1].starts_inside_track);
+    }
+
+    #[test]
+    fn round_trip_keeps_invalid_commands() {
+        let (reparsed, cue_text) = round_trip(
+            "FILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK xx AUDIO\n    INDEX 01 00:0a:00\n",
+        );
+        assert!(cue_text.contains("  TRACK xx AUDIO\r\n    INDEX 01 00:0a:00\r\n"));
+        assert_eq!(reparsed.invalid_commands.len(), 2);
+    }
+
+    #[test]
+    fn round_trip_keeps_tracks_before_the_first_audio_file() {
+        let (reparsed, cue_text) = round_trip(
+            "TRACK 01 AUDIO\n  INDEX 01 00:00:00\nFILE \"a.flac\" WAVE\nTRACK 02 AUDIO\n  INDEX 01 00:00:00\n",
+        );
+        assert!(cue_text.starts_with("  TRACK 01 AUDIO\r\n    INDEX 01 00:00:00\r\nFILE"));
+        assert_eq!(reparsed.audio_files[0].track_count, 1);
+    }
+
+    #[test]
+    fn writes_multiple_audio_files() {
+        let cue_sheet = parse(
+            "FILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\nFILE \"b.flac\" WAVE\n  TRACK 02 AUDIO\n    INDEX 01 00:00:00\n",
+        );
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let cue_file = temp_dir.path().join("album.cue");
+        write_cue_file(&cue_sheet, &cue_file).unwrap();
+        assert_eq!(
+            parse(&fs::read_to_string(&cue_file).unwrap()).audio_file_count(),
+            2
+        );
+    }
+}
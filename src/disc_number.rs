@@ -0,0 +1,261 @@
+use std::path::Path;
+
+/// Words that introduce a disc number, e.g. "CD2", "Disc II" or "Disk-3"
+const DISC_PREFIXES: [&str; 5] = ["cd", "disc", "disk", "disque", "dvd"];
+
+/// Roman numerals commonly used for disc numbers
+const ROMAN_NUMERALS: [&str; 10] = ["i", "ii", "iii", "iv", "v", "vi", "vii", "viii", "ix", "x"];
+
+/// How certain we are about a derived disc number
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// A bare number, e.g. "2 - Album" or "Album 2"
+    Low,
+    /// A vinyl side, e.g. "Side B", or a folder named after a vinyl position, e.g. "A1"
+    Medium,
+    /// An explicit disc marker, e.g. "CD2", "Disc II" or "(2 of 3)"
+    High,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DiscNumber {
+    pub number: usize,
+    pub total: Option<usize>,
+    pub confidence: Confidence,
+}
+
+/// Detects the disc number of a cue file
+/// The cue file name is checked first, if it yields nothing certain the folder name is checked
+pub fn detect(cue_file_path: &Path) -> Option<DiscNumber> {
+    let from_file_name = cue_file_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(parse);
+    if matches!(from_file_name, Some(disc) if disc.confidence == Confidence::High) {
+        return from_file_name;
+    }
+
+    let from_folder_name = cue_file_path
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .and_then(|name| name.to_str())
+        .and_then(parse_folder_name)
+        .filter(|disc| disc.confidence > Confidence::Low);
+
+    match (from_file_name, from_folder_name) {
+        (Some(file), Some(folder)) if folder.confidence > file.confidence => Some(folder),
+        (None, folder) => folder,
+        (file, _) => file,
+    }
+}
+
+/// Checks if the given folder name denotes a disc folder, e.g. `CD1/`, `Disc 2/` or `Side A/`
+pub fn is_disc_folder_name(folder_name: &str) -> bool {
+    parse_folder_name(folder_name).is_some_and(|disc| disc.confidence > Confidence::Low)
+}
+
+/// Parses a disc number from a folder name
+/// Besides the conventions of file names, a folder named after a vinyl position, e.g. "A1" or "B12", denotes a side
+pub fn parse_folder_name(folder_name: &str) -> Option<DiscNumber> {
+    parse(folder_name).or_else(|| {
+        let word = folder_name.trim().to_lowercase();
+        let (side, position) = word.split_at(word.chars().next().map_or(0, char::len_utf8));
+        if position.is_empty() || position.len() > 2 || parse_number(position).is_none() {
+            return None;
+        }
+        parse_side(side).map(medium)
+    })
+}
+
+/// Parses a disc number from a file or folder name
+/// Supports common conventions like "CD2", "Disk 2", "Disc II", "(2 of 3)" or "Side B"
+/// Vinyl positions like "A1" are not taken from names, as they also appear in titles
+/// Returns the match with the highest confidence
+pub fn parse(name: &str) -> Option<DiscNumber> {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let tokens: Vec<String> = words.iter().flat_map(|word| split_digits(word)).collect();
+
+    let mut candidates = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).map(String::as_str);
+        let after_next = tokens.get(i + 2).map(String::as_str);
+
+        // "CD2", "Disc II", "Disk-3"
+        if DISC_PREFIXES.contains(&token.as_str()) {
+            if let Some(number) = next.and_then(parse_number_or_roman) {
+                candidates.push(high(number, None));
+            }
+        }
+
+        // "(2 of 3)"
+        if let (Some(number), Some("of"), Some(total)) =
+            (parse_number(token), next, after_next.and_then(parse_number))
+        {
+            if number <= total {
+                candidates.push(high(number, Some(total)));
+            }
+        }
+
+        // "Side B"
+        if token == "side" {
+            if let Some(number) = next.and_then(parse_side) {
+                candidates.push(medium(number));
+            }
+        }
+    }
+
+    // A bare number as first or last word, e.g. "2 - Album" or "Album 2"
+    for word in [words.first(), words.last()].into_iter().flatten() {
+        if word.len() <= 2 {
+            if let Some(number) = parse_number(word) {
+                candidates.push(DiscNumber {
+                    number,
+                    total: None,
+                    confidence: Confidence::Low,
+                });
+            }
+        }
+    }
+
+    candidates.into_iter().reduce(|best, candidate| {
+        if candidate.confidence > best.confidence {
+            candidate
+        } else {
+            best
+        }
+    })
+}
+
+fn high(number: usize, total: Option<usize>) -> DiscNumber {
+    DiscNumber {
+        number,
+        total,
+        confidence: Confidence::High,
+    }
+}
+
+fn medium(number: usize) -> DiscNumber {
+    DiscNumber {
+        number,
+        total: None,
+        confidence: Confidence::Medium,
+    }
+}
+
+/// Splits a word at letter/digit boundaries, e.g. "cd02" becomes ["cd", "02"]
+fn split_digits(word: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for c in word.chars() {
+        match tokens.last_mut() {
+            Some(token)
+                if token.ends_with(|last: char| last.is_ascii_digit() == c.is_ascii_digit()) =>
+            {
+                token.push(c)
+            }
+            _ => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+/// Parses a positive disc number, years and other large numbers are rejected
+fn parse_number(token: &str) -> Option<usize> {
+    if token.is_empty() || token.len() > 3 || !token.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    token
+        .parse::<usize>()
+        .ok()
+        .filter(|number| (1..=99).contains(number))
+}
+
+fn parse_number_or_roman(token: &str) -> Option<usize> {
+    parse_number(token).or_else(|| {
+        ROMAN_NUMERALS
+            .iter()
+            .position(|roman| *roman == token)
+            .map(|index| index + 1)
+    })
+}
+
+/// Parses a vinyl side letter, sides are numbered from A = 1
+fn parse_side(token: &str) -> Option<usize> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(side @ 'a'..='h'), None) => Some(side as usize - 'a' as usize + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(name: &str) -> Option<(usize, Option<usize>, Confidence)> {
+        parse(name).map(|disc| (disc.number, disc.total, disc.confidence))
+    }
+
+    #[test]
+    fn parses_explicit_disc_markers() {
+        assert_eq!(parsed("CD2"), Some((2, None, Confidence::High)));
+        assert_eq!(parsed("Album (Disc II)"), Some((2, None, Confidence::High)));
+        assert_eq!(parsed("Album Disk-3"), Some((3, None, Confidence::High)));
+        assert_eq!(parsed("cd02"), Some((2, None, Confidence::High)));
+        assert_eq!(
+            parsed("Album (2 of 3)"),
+            Some((2, Some(3), Confidence::High))
+        );
+    }
+
+    #[test]
+    fn parses_vinyl_sides() {
+        assert_eq!(
+            parsed("Album - Side B"),
+            Some((2, None, Confidence::Medium))
+        );
+    }
+
+    #[test]
+    fn parses_bare_numbers_with_low_confidence() {
+        assert_eq!(parsed("2 - Album"), Some((2, None, Confidence::Low)));
+        assert_eq!(parsed("Album 2"), Some((2, None, Confidence::Low)));
+    }
+
+    #[test]
+    fn ignores_years_and_titles_without_disc_numbers() {
+        assert_eq!(parsed("Album 1999"), None);
+        assert_eq!(parsed("Album"), None);
+    }
+
+    #[test]
+    fn ignores_vinyl_positions_in_names() {
+        assert_eq!(parsed("Artist - A1 Remixes"), None);
+        assert_eq!(parsed("B2"), None);
+    }
+
+    #[test]
+    fn parses_vinyl_positions_as_folder_names() {
+        let disc = parse_folder_name("B2").unwrap();
+        assert_eq!((disc.number, disc.confidence), (2, Confidence::Medium));
+        assert!(is_disc_folder_name("A1"));
+        assert!(is_disc_folder_name("CD1"));
+        assert!(!is_disc_folder_name("A1 Remixes"));
+        assert!(!is_disc_folder_name("Album 2"));
+    }
+
+    #[test]
+    fn detects_disc_numbers_from_file_and_folder_names() {
+        let disc = detect(Path::new("Album/CD2/Album.cue")).unwrap();
+        assert_eq!((disc.number, disc.confidence), (2, Confidence::High));
+        let disc = detect(Path::new("Album/Album CD3.cue")).unwrap();
+        assert_eq!((disc.number, disc.confidence), (3, Confidence::High));
+        let disc = detect(Path::new("Album/Side B/Album.cue")).unwrap();
+        assert_eq!((disc.number, disc.confidence), (2, Confidence::Medium));
+        assert!(detect(Path::new("Album A1/Album.cue")).is_none());
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use colour::yellow_ln;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::flac::FlacFile;
+use lofty::id3::v2::Id3v2Tag;
+use lofty::mp4::{Atom, AtomData, AtomIdent, Ilst, Mp4File};
+use lofty::mpeg::MpegFile;
+use lofty::ogg::{OpusFile, VorbisComments, VorbisFile};
+
+use crate::cue_encoding::read_cue_file;
+use crate::CueSheet;
+
+/// Tag key used to embed the cue sheet, as understood by foobar2000, Kodi and others
+const CUESHEET_KEY: &str = "CUESHEET";
+
+/// Which split tracks get the original cue sheet embedded
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CueEmbedMode {
+    /// Every track of the cue sheet
+    All,
+    /// Only the first track of the cue sheet
+    First,
+}
+
+impl FromStr for CueEmbedMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "all" => Ok(CueEmbedMode::All),
+            "first" => Ok(CueEmbedMode::First),
+            _ => Err(format!(
+                "unknown cue embed mode '{}', expected one of: all, first",
+                value
+            )),
+        }
+    }
+}
+
+/// Embeds the original cue sheet, converted to UTF-8, as CUESHEET tag into the split tracks
+pub fn embed_cue_sheets(cue_sheets: &[CueSheet], mode: CueEmbedMode) {
+    println!("📜 Embedding cue sheets into tracks");
+    for cue_sheet in cue_sheets {
+        let cue_text = match read_cue_file(&cue_sheet.cue_file_path, Some(cue_sheet.cue_encoding)) {
+            Ok(decoded_cue) => normalize_cue_text(&decoded_cue.text),
+            Err(err) => {
+                yellow_ln!(
+                    "⚠️ Could not read cue file {}: {}",
+                    cue_sheet.cue_file_path.display(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        let track_count = match mode {
+            CueEmbedMode::All => cue_sheet.tracks.len(),
+            CueEmbedMode::First => 1,
+        };
+
+        for output_file in cue_sheet
+            .tracks
+            .iter()
+            .take(track_count)
+            .filter_map(|track| track.output_file.as_ref())
+        {
+            if let Err(error_message) = embed_text_tag(output_file, CUESHEET_KEY, &cue_text) {
+                yellow_ln!(
+                    "⚠️ Could not embed cue sheet into {}: {}",
+                    output_file.display(),
+                    error_message
+                );
+            }
+        }
+    }
+}
+
+/// Copies rip logs (`.log` files next to the cue file) into the output directory
+/// Only logs named like the cue or audio file are copied, unless the cue sheet is the only one in its folder
+pub fn copy_rip_logs(cue_sheets: &[CueSheet]) {
+    println!("📜 Copying rip logs to output directories");
+    for cue_sheet in cue_sheets {
+        let cue_dir = cue_sheet.cue_file_path.parent().unwrap();
+        let output_dir = cue_sheet.output_dir.as_ref().unwrap();
+        if cue_dir == output_dir {
+            continue;
+        }
+
+        for log_file in find_matching_rip_logs(cue_sheet, cue_dir) {
+            let output_log_file = output_dir.join(log_file.file_name().unwrap());
+            match fs::copy(&log_file, &output_log_file) {
+                Ok(_) => println!("📜 Copied rip log to: {}", output_log_file.display()),
+                Err(err) => yellow_ln!("⚠️ Could not copy rip log {}: {}", log_file.display(), err),
+            }
+        }
+    }
+}
+
+/// Picks the rip logs of a cue sheet, as several rips may share one folder
+fn find_matching_rip_logs(cue_sheet: &CueSheet, cue_dir: &Path) -> Vec<PathBuf> {
+    let rip_logs = find_files_with_extension(cue_dir, "log");
+    let stems = [&cue_sheet.cue_file_path, &cue_sheet.audio_file_path]
+        .map(|path| path.file_stem().unwrap_or_default().to_string_lossy());
+
+    let matching_rip_logs: Vec<PathBuf> = rip_logs
+        .iter()
+        .filter(|log_file| {
+            let log_stem = log_file.file_stem().unwrap_or_default().to_string_lossy();
+            stems
+                .iter()
+                .any(|stem| stem.eq_ignore_ascii_case(&log_stem))
+        })
+        .cloned()
+        .collect();
+
+    if matching_rip_logs.is_empty() && find_files_with_extension(cue_dir, "cue").len() == 1 {
+        rip_logs
+    } else {
+        matching_rip_logs
+    }
+}
+
+fn find_files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    dir.read_dir()
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter(|path| {
+                    path.extension()
+                        .unwrap_or("".as_ref())
+                        .eq_ignore_ascii_case(extension)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Normalizes line endings to CRLF, as used by most rippers
+fn normalize_cue_text(cue_text: &str) -> String {
+    cue_text.lines().collect::<Vec<&str>>().join("\r\n")
+}
+
+/// Writes a free-form text tag, which is not covered by lofty's generic tag keys
+/// Vorbis comments are used for FLAC and Ogg, TXXX frames for MP3 and freeform atoms for MP4
+fn embed_text_tag(audio_file: &Path, key: &str, text: &str) -> Result<(), String> {
+    let file_type = lofty::read_from_path(audio_file)
+        .map_err(|err| err.to_string())?
+        .file_type();
+    let mut file = File::open(audio_file).map_err(|err| err.to_string())?;
+    let parse_options = ParseOptions::new();
+
+    let result = match file_type {
+        FileType::Flac => {
+            let mut flac_file =
+                FlacFile::read_from(&mut file, parse_options).map_err(|err| err.to_string())?;
+            if flac_file.vorbis_comments().is_none() {
+                flac_file.set_vorbis_comments(VorbisComments::default());
+            }
+            if let Some(vorbis_comments) = flac_file.vorbis_comments_mut() {
+                vorbis_comments.insert(key.to_string(), text.to_string());
+            }
+            flac_file.save_to_path(audio_file, WriteOptions::default())
+        }
+        FileType::Opus => {
+            let mut opus_file =
+                OpusFile::read_from(&mut file, parse_options).map_err(|err| err.to_string())?;
+            opus_file
+                .vorbis_comments_mut()
+                .insert(key.to_string(), text.to_string());
+            opus_file.save_to_path(audio_file, WriteOptions::default())
+        }
+        FileType::Vorbis => {
+            let mut vorbis_file =
+                VorbisFile::read_from(&mut file, parse_options).map_err(|err| err.to_string())?;
+            vorbis_file
+                .vorbis_comments_mut()
+                .insert(key.to_string(), text.to_string());
+            vorbis_file.save_to_path(audio_file, WriteOptions::default())
+        }
+        FileType::Mpeg => {
+            let mut mpeg_file =
+                MpegFile::read_from(&mut file, parse_options).map_err(|err| err.to_string())?;
+            if mpeg_file.id3v2().is_none() {
+                mpeg_file.set_id3v2(Id3v2Tag::default());
+            }
+            if let Some(id3v2) = mpeg_file.id3v2_mut() {
+                id3v2.insert_user_text(key.to_string(), text.to_string());
+            }
+            mpeg_file.save_to_path(audio_file, WriteOptions::default())
+        }
+        FileType::Mp4 => {
+            let mut mp4_file =
+                Mp4File::read_from(&mut file, parse_options).map_err(|err| err.to_string())?;
+            if mp4_file.ilst().is_none() {
+                mp4_file.set_ilst(Ilst::default());
+            }
+            if let Some(ilst) = mp4_file.ilst_mut() {
+                ilst.insert(Atom::new(
+                    AtomIdent::Freeform {
+                        mean: "com.apple.iTunes".into(),
+                        name: key.to_string().into(),
+                    },
+                    AtomData::UTF8(text.to_string()),
+                ));
+            }
+            mp4_file.save_to_path(audio_file, WriteOptions::default())
+        }
+        _ => return Err(format!("{:?} files are not supported", file_type)),
+    };
+
+    result.map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUE_TEXT: &str = "FILE \"Album.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n";
+
+    fn rip_folder(file_names: &[&str]) -> self_update::TempDir {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        for file_name in file_names {
+            fs::write(temp_dir.path().join(file_name), "").unwrap();
+        }
+        temp_dir
+    }
+
+    fn log_names(cue_dir: &Path, cue_file_name: &str) -> Vec<String> {
+        let cue_sheet = crate::parse_cue_text(&cue_dir.join(cue_file_name), CUE_TEXT);
+        let mut log_names: Vec<String> = find_matching_rip_logs(&cue_sheet, cue_dir)
+            .iter()
+            .map(|log_file| log_file.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        log_names.sort();
+        log_names
+    }
+
+    #[test]
+    fn parses_cue_embed_modes() {
+        assert_eq!("all".parse(), Ok(CueEmbedMode::All));
+        assert_eq!("first".parse(), Ok(CueEmbedMode::First));
+        assert!("last".parse::<CueEmbedMode>().is_err());
+    }
+
+    #[test]
+    fn normalizes_line_endings_to_crlf() {
+        assert_eq!(normalize_cue_text("A\nB\r\nC\n"), "A\r\nB\r\nC");
+    }
+
+    #[test]
+    fn copies_logs_named_like_the_cue_or_audio_file() {
+        let temp_dir = rip_folder(&["Rip.cue", "Other.cue", "rip.LOG", "Album.log", "Other.log"]);
+        assert_eq!(
+            log_names(temp_dir.path(), "Rip.cue"),
+            ["Album.log", "rip.LOG"]
+        );
+    }
+
+    #[test]
+    fn copies_all_logs_of_the_only_cue_sheet_in_a_folder() {
+        let temp_dir = rip_folder(&["Rip.cue", "EAC.log", "Notes.txt"]);
+        assert_eq!(log_names(temp_dir.path(), "Rip.cue"), ["EAC.log"]);
+    }
+
+    #[test]
+    fn copies_no_foreign_logs_if_the_folder_has_several_cue_sheets() {
+        let temp_dir = rip_folder(&["Rip.cue", "Other.cue", "Other.log"]);
+        assert!(log_names(temp_dir.path(), "Rip.cue").is_empty());
+    }
+}
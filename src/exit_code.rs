@@ -0,0 +1,70 @@
+/// Everything was done
+pub const SUCCESS: i32 = 0;
+/// Any other failure, e.g. a failed update or a file that could not be written
+pub const ERROR: i32 = 1;
+/// Some tracks could not be split or tagged, the others were
+pub const SOME_TRACKS_FAILED: i32 = 2;
+/// No track could be split or tagged
+pub const ALL_TRACKS_FAILED: i32 = 3;
+/// Invalid arguments, no cue files found, or cue files that are invalid and were skipped
+pub const INVALID_INPUT: i32 = 4;
+/// A required tool, e.g. ffmpeg, is not in the path
+pub const MISSING_TOOLS: i32 = 5;
+/// The user declined to proceed or quit
+pub const USER_ABORT: i32 = 6;
+
+/// Decides the exit code of a run that processed tracks
+/// Failed tracks weigh more than skipped cue files, as they leave incomplete output behind
+pub fn from_track_counts(
+    failed_tracks: usize,
+    total_tracks: usize,
+    has_invalid_input: bool,
+) -> i32 {
+    if failed_tracks > 0 && failed_tracks == total_tracks {
+        ALL_TRACKS_FAILED
+    } else if failed_tracks > 0 {
+        SOME_TRACKS_FAILED
+    } else if has_invalid_input {
+        INVALID_INPUT
+    } else {
+        SUCCESS
+    }
+}
+
+/// Describes the exit code for the final message of a run
+pub fn describe(exit_code: i32) -> &'static str {
+    match exit_code {
+        SUCCESS => "success",
+        SOME_TRACKS_FAILED => "some tracks failed",
+        ALL_TRACKS_FAILED => "all tracks failed",
+        INVALID_INPUT => "invalid input",
+        MISSING_TOOLS => "missing tools",
+        USER_ABORT => "aborted by the user",
+        _ => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn successful_run_is_a_success() {
+        assert_eq!(from_track_counts(0, 12, false), SUCCESS);
+        assert_eq!(from_track_counts(0, 0, false), SUCCESS);
+    }
+
+    #[test]
+    fn skipped_cue_files_are_invalid_input() {
+        assert_eq!(from_track_counts(0, 12, true), INVALID_INPUT);
+        assert_eq!(from_track_counts(0, 0, true), INVALID_INPUT);
+    }
+
+    #[test]
+    fn failed_tracks_weigh_more_than_skipped_cue_files() {
+        assert_eq!(from_track_counts(3, 12, false), SOME_TRACKS_FAILED);
+        assert_eq!(from_track_counts(3, 12, true), SOME_TRACKS_FAILED);
+        assert_eq!(from_track_counts(12, 12, false), ALL_TRACKS_FAILED);
+        assert_eq!(from_track_counts(12, 12, true), ALL_TRACKS_FAILED);
+    }
+}
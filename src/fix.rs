@@ -0,0 +1,137 @@
+use argh::FromArgs;
+use colour::{green_ln, yellow_ln};
+
+use crate::config::Config;
+use crate::cue_encoding::{CueConversion, CueEncoding};
+use crate::options::{CueArgs, CueOptions};
+use crate::{check_tools, exit_code, find_all_cue_files, load_cue_sheets, InvalidCuePolicy};
+
+/// Repair cue files without splitting, accepted fixes are saved into the cue files
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "fix")]
+pub struct FixArgs {
+    /// accept a similar audio file without asking, if its match score reaches the given percentage
+    #[argh(option)]
+    auto_fix_score: Option<usize>,
+
+    /// score in percent above which a similar audio file is the default answer (default: 70)
+    #[argh(option)]
+    accept_score: Option<usize>,
+
+    /// decode cue files with the given encoding instead of detecting it, e.g. windows-1251 or shift_jis
+    #[argh(option)]
+    cue_encoding: Option<CueEncoding>,
+
+    /// rewrite cue files as UTF-8 without or with byte order mark, the original is kept as .bak:
+    /// utf8 or utf8-bom
+    #[argh(option)]
+    convert_cue: Option<CueConversion>,
+
+    /// do not ask any questions, same as --non-interactive
+    #[argh(switch, short = 'y')]
+    yes: bool,
+
+    /// do not ask any questions, every question takes its default answer and invalid cue files are handled by
+    /// --on-invalid-cue, this is enabled automatically if stdin is not a terminal
+    #[argh(switch)]
+    non_interactive: bool,
+
+    /// ask questions, even if non_interactive is enabled in a config file
+    #[argh(switch)]
+    interactive: bool,
+
+    /// what to do with invalid cue files in non-interactive mode: skip or abort (default: skip)
+    #[argh(option)]
+    on_invalid_cue: Option<InvalidCuePolicy>,
+
+    /// file or folder paths to fix
+    /// default is "."
+    #[argh(positional, greedy)]
+    pub cue_file_or_folders: Vec<String>,
+}
+
+impl FixArgs {
+    /// Fixes are always saved, that is what the command is for
+    fn cue_options(&self, config: &Config) -> CueOptions {
+        let cue_args = CueArgs {
+            cue_encoding: self.cue_encoding,
+            convert_cue: self.convert_cue,
+            auto_fix_score: self.auto_fix_score,
+            accept_score: self.accept_score,
+            yes: self.yes,
+            non_interactive: self.non_interactive,
+            interactive: self.interactive,
+            on_invalid_cue: self.on_invalid_cue,
+            ..CueArgs::default()
+        };
+        CueOptions {
+            save_fixes: true,
+            ..cue_args.resolve(config)
+        }
+    }
+}
+
+/// Runs the fix command
+/// The cue sheets run through the same verification as before splitting, but fixes are always saved
+/// Options which are not given on the command line are taken from the config files
+pub fn fix(fix_args: FixArgs, config: &Config) {
+    check_tools(vec!["ffprobe"]);
+
+    let cue_options = fix_args.cue_options(config);
+    let cue_file_paths = find_all_cue_files(&fix_args.cue_file_or_folders);
+    let loaded_cue_sheets = load_cue_sheets(&cue_file_paths, &cue_options, None);
+    if let Some(abort_exit_code) = loaded_cue_sheets.abort_exit_code {
+        std::process::exit(abort_exit_code);
+    }
+    let skipped_cue_files = loaded_cue_sheets.skipped_cue_files;
+
+    if skipped_cue_files.is_empty() {
+        green_ln!("✅ All {} cue file(s) are valid", cue_file_paths.len());
+    } else {
+        yellow_ln!(
+            "⚠️ {} of {} cue file(s) are still invalid:",
+            skipped_cue_files.len(),
+            cue_file_paths.len()
+        );
+        for skipped_cue_file in skipped_cue_files {
+            yellow_ln!(
+                "\t{}: {}",
+                skipped_cue_file.cue_file_path.display(),
+                skipped_cue_file.reason
+            );
+        }
+        std::process::exit(exit_code::INVALID_INPUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix_args(args: &[&str]) -> FixArgs {
+        FixArgs::from_args(&["fix"], args).unwrap()
+    }
+
+    #[test]
+    fn fixes_are_always_saved() {
+        let config: Config = toml::from_str("save_fixes = false").unwrap();
+        assert!(fix_args(&[]).cue_options(&config).save_fixes);
+    }
+
+    #[test]
+    fn options_not_given_are_taken_from_the_config() {
+        let config: Config =
+            toml::from_str("auto_fix_score = 95\naccept_score = 80\nconvert_cue = \"utf8\"")
+                .unwrap();
+        let cue_options = fix_args(&[]).cue_options(&config);
+        assert_eq!(cue_options.auto_fix_score, Some(95));
+        assert_eq!(cue_options.accept_score, 80);
+        assert_eq!(cue_options.convert_cue, Some(CueConversion::Utf8));
+
+        let cue_options =
+            fix_args(&["--auto-fix-score", "90", "--accept-score", "60"]).cue_options(&config);
+        assert_eq!(cue_options.auto_fix_score, Some(90));
+        assert_eq!(cue_options.accept_score, 60);
+        assert!(!cue_options.allow_missing_audio_file);
+    }
+}
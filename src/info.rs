@@ -0,0 +1,170 @@
+use argh::FromArgs;
+use colour::yellow_ln;
+
+use crate::config::Config;
+use crate::cue_encoding::CueEncoding;
+use crate::{find_all_cue_files, parse_cue_file, CueDuration, CueSheet};
+
+/// Print the parsed content of cue files, nothing is verified or changed
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "info")]
+pub struct InfoArgs {
+    /// decode cue files with the given encoding instead of detecting it, e.g. windows-1251 or shift_jis
+    #[argh(option)]
+    cue_encoding: Option<CueEncoding>,
+
+    /// file or folder paths to parse
+    /// default is "."
+    #[argh(positional, greedy)]
+    pub cue_file_or_folders: Vec<String>,
+}
+
+/// Runs the info command
+pub fn info(info_args: InfoArgs, config: &Config) {
+    let cue_encoding = info_args.cue_encoding.or(config.cue_encoding);
+    for cue_file_path in find_all_cue_files(&info_args.cue_file_or_folders) {
+        println!();
+        match parse_cue_file(&cue_file_path, cue_encoding) {
+            Some(cue_sheet) => print_cue_sheet(&cue_sheet),
+            None => yellow_ln!("⚠️ Could not read cue file {}", cue_file_path.display()),
+        }
+    }
+}
+
+fn print_cue_sheet(cue_sheet: &CueSheet) {
+    println!("📄 {}", cue_sheet.cue_file_path.display());
+    println!(
+        "\tPerformer: {}",
+        cue_sheet.performer.as_deref().unwrap_or("-")
+    );
+    println!("\tTitle: {}", cue_sheet.title.as_deref().unwrap_or("-"));
+    println!(
+        "\tAudio file: {} ({}){}",
+        cue_sheet.audio_file_name,
+        cue_sheet.audio_file_type,
+        if cue_sheet.audio_file_path.exists() {
+            ""
+        } else {
+            ", not found"
+        }
+    );
+    if cue_sheet.audio_file_count() > 1 {
+        println!("\tAudio files: {}", cue_sheet.audio_file_count());
+    }
+    println!("\tEncoding: {}", cue_sheet.cue_encoding.name());
+    for command in &cue_sheet.other_commands {
+        println!("\t{}", command);
+    }
+
+    println!("\tTracks: {}", cue_sheet.tracks.len());
+    for index in 0..cue_sheet.tracks.len() {
+        println!("\t{}", track_line(cue_sheet, index));
+    }
+}
+
+/// Formats a track as e.g. "01  00:00:00 [03:12:40]  Artist - Title"
+fn track_line(cue_sheet: &CueSheet, index: usize) -> String {
+    let track = &cue_sheet.tracks[index];
+    let start_time = track
+        .start_time
+        .map(|start_time| start_time.to_string())
+        .unwrap_or_else(|| "--:--:--".to_string());
+    // The length of the last track is only known from the audio file
+    let length = cue_sheet
+        .tracks
+        .get(index + 1)
+        .and_then(|next_track| track_length(track.start_time?, next_track.start_time?))
+        .map(|length| format!(" [{}]", length))
+        .unwrap_or_default();
+    let pregap = track
+        .pregap_start_time
+        .map(|pregap_start_time| format!(" (pregap from {})", pregap_start_time))
+        .unwrap_or_default();
+
+    format!(
+        "{:02}  {}{}  {} - {}{}",
+        track.number,
+        start_time,
+        length,
+        track
+            .artist
+            .as_deref()
+            .or(cue_sheet.performer.as_deref())
+            .unwrap_or("-"),
+        track.title.as_deref().unwrap_or("-"),
+        pregap
+    )
+}
+
+fn track_length(start_time: CueDuration, next_start_time: CueDuration) -> Option<CueDuration> {
+    next_start_time
+        .total_frames()
+        .checked_sub(start_time.total_frames())
+        .map(CueDuration::from_frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parse_cue_text;
+
+    const CUE_TEXT: &str = r#"PERFORMER "Album Artist"
+TITLE "Album"
+FILE "album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "Guest"
+    INDEX 00 03:10:00
+    INDEX 01 03:12:40
+  TRACK 03 AUDIO
+    INDEX 01 07:00:00
+"#;
+
+    #[test]
+    fn formats_tracks_with_their_length() {
+        let cue_sheet = parse_cue_text(Path::new("album.cue"), CUE_TEXT);
+        assert_eq!(
+            track_line(&cue_sheet, 0),
+            "01  00:00:00 [03:12:40]  Album Artist - First"
+        );
+        assert_eq!(
+            track_line(&cue_sheet, 1),
+            "02  03:12:40 [03:47:35]  Guest - Second (pregap from 03:10:00)"
+        );
+    }
+
+    #[test]
+    fn last_track_has_no_length() {
+        let cue_sheet = parse_cue_text(Path::new("album.cue"), CUE_TEXT);
+        assert_eq!(track_line(&cue_sheet, 2), "03  07:00:00  Album Artist - -");
+    }
+
+    #[test]
+    fn tracks_without_start_time_are_marked() {
+        let cue_text = CUE_TEXT.replace("    INDEX 01 07:00:00\n", "");
+        let cue_sheet = parse_cue_text(Path::new("album.cue"), &cue_text);
+        assert_eq!(
+            track_line(&cue_sheet, 1),
+            "02  03:12:40  Guest - Second (pregap from 03:10:00)"
+        );
+        assert_eq!(track_line(&cue_sheet, 2), "03  --:--:--  Album Artist - -");
+    }
+
+    #[test]
+    fn track_length_is_none_for_unordered_tracks() {
+        let start_time = CueDuration::from_frames(1000);
+        assert_eq!(
+            track_length(start_time, CueDuration::from_frames(1075)),
+            Some(CueDuration::from_frames(75))
+        );
+        assert_eq!(
+            track_length(start_time, CueDuration::from_frames(999)),
+            None
+        );
+    }
+}
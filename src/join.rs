@@ -0,0 +1,661 @@
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use argh::FromArgs;
+use colour::red_ln;
+use lofty::file::TaggedFileExt;
+use lofty::tag::{Accessor, ItemKey};
+
+use crate::cue_encoding::CueEncoding;
+use crate::{
+    check_tools, cue_writer, display_command, exit_code, CueDuration, CueFile, CueSheet, Track,
+};
+
+/// Audio file extensions that are joined
+const AUDIO_EXTENSIONS: [&str; 10] = [
+    "flac", "wav", "aiff", "aif", "ape", "wv", "m4a", "ogg", "opus", "mp3",
+];
+
+/// Lossless codecs as named by ffprobe, tracks in any other codec can not be joined losslessly
+/// PCM codecs (e.g. pcm_s16le) are matched by their prefix
+const LOSSLESS_CODECS: [&str; 5] = ["flac", "alac", "ape", "wavpack", "tta"];
+
+/// Merge split tracks back into a single audio file plus cue sheet
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "join")]
+pub struct JoinArgs {
+    /// only print the ffmpeg command and the cue sheet
+    #[argh(switch)]
+    dry_run: bool,
+
+    /// output audio format: flac or wav (default: flac)
+    #[argh(option, default = "JoinFormat::Flac")]
+    format: JoinFormat,
+
+    /// track order: tag (disc and track number) or filename (default: tag)
+    #[argh(option, default = "JoinOrder::Tag")]
+    order: JoinOrder,
+
+    /// output audio file, default is "<album>.<format>" next to the track folder
+    #[argh(option)]
+    output: Option<PathBuf>,
+
+    /// folder containing the tracks to join
+    #[argh(positional)]
+    pub folder: PathBuf,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JoinFormat {
+    Flac,
+    Wav,
+}
+
+impl FromStr for JoinFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "flac" => Ok(JoinFormat::Flac),
+            "wav" => Ok(JoinFormat::Wav),
+            _ => Err(format!(
+                "unknown join format '{}', expected one of: flac, wav",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JoinOrder {
+    Tag,
+    Filename,
+}
+
+impl FromStr for JoinOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "tag" => Ok(JoinOrder::Tag),
+            "filename" => Ok(JoinOrder::Filename),
+            _ => Err(format!(
+                "unknown join order '{}', expected one of: tag, filename",
+                value
+            )),
+        }
+    }
+}
+
+/// A track file with its tags and exact length
+#[derive(Debug, Clone)]
+struct TrackFile {
+    path: PathBuf,
+    disc_number: Option<u32>,
+    track_number: Option<u32>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    codec: String,
+    samples: u64,
+    sample_rate: u64,
+    channels: Option<u32>,
+    bits_per_sample: Option<u32>,
+}
+
+/// Joins all tracks of a folder into a single audio file and writes a cue sheet for it
+pub fn join(join_args: JoinArgs) {
+    check_tools(vec!["ffmpeg", "ffprobe"]);
+
+    println!("🔍 Reading tracks in {}", join_args.folder.display());
+    let mut track_files: Vec<TrackFile> =
+        match find_track_files(&join_args.folder, join_args.output.as_deref()) {
+            Ok(track_files) => track_files,
+            Err(error_message) => {
+                red_ln!("❌ {}", error_message);
+                std::process::exit(exit_code::INVALID_INPUT);
+            }
+        };
+    if track_files.is_empty() {
+        red_ln!("❌ No audio files found in {}", join_args.folder.display());
+        std::process::exit(exit_code::INVALID_INPUT);
+    }
+
+    sort_track_files(&mut track_files, join_args.order);
+
+    if let Err(error_message) = check_lossless_join(&track_files) {
+        red_ln!("❌ {}", error_message);
+        std::process::exit(exit_code::INVALID_INPUT);
+    }
+
+    let output_file = join_args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output_file(&join_args.folder, &track_files, join_args.format));
+    let cue_sheet = build_cue_sheet(&output_file, &track_files);
+    let cue_file = output_file.with_extension("cue");
+    let cue_text = cue_writer::build_cue_sheet(&cue_sheet);
+
+    let codec = match output_codec(join_args.format, &track_files[0]) {
+        Ok(codec) => codec,
+        Err(error_message) => {
+            red_ln!("❌ {}", error_message);
+            std::process::exit(exit_code::INVALID_INPUT);
+        }
+    };
+
+    // ffmpeg reads the tracks from a list file, which must not be a predictable path in the shared temp dir
+    let temp_dir = match self_update::TempDir::new() {
+        Ok(temp_dir) => temp_dir,
+        Err(err) => {
+            red_ln!("❌ Could not create a temporary directory: {}", err);
+            std::process::exit(exit_code::ERROR);
+        }
+    };
+    let concat_list_file = temp_dir.path().join("tracks.txt");
+    let ffmpeg_args = build_ffmpeg_join_args(&concat_list_file, &output_file, &codec);
+    let ffmpeg_command = display_command("ffmpeg", &ffmpeg_args);
+
+    if join_args.dry_run {
+        println!("🚀 Dry run, only printing ffmpeg command and cue sheet");
+        println!("{}", ffmpeg_command);
+        println!();
+        println!("{}", cue_text);
+        return;
+    }
+
+    println!(
+        "🔗 Joining {} track(s) into {}",
+        track_files.len(),
+        output_file.display()
+    );
+    let joined = run_ffmpeg_join(&concat_list_file, &track_files, &ffmpeg_args);
+    // Exiting skips destructors, so the temporary directory is removed first
+    drop(temp_dir);
+    if let Err(error_message) = joined {
+        red_ln!(
+            "❌ Failed to join tracks\nCommand: {}\n{}",
+            ffmpeg_command,
+            error_message
+        );
+        std::process::exit(exit_code::ERROR);
+    }
+
+    match cue_writer::write_cue_file(&cue_sheet, &cue_file) {
+        Ok(_) => println!("📝 Wrote cue sheet: {}", cue_file.display()),
+        Err(error_message) => {
+            red_ln!(
+                "❌ Could not write cue sheet {}: {}",
+                cue_file.display(),
+                error_message
+            );
+            std::process::exit(exit_code::ERROR);
+        }
+    }
+    println!("🎉 All tracks have been joined");
+}
+
+/// Finds the audio files of a folder, except the output file of a previous join
+fn find_track_files(folder: &Path, output_file: Option<&Path>) -> Result<Vec<TrackFile>, String> {
+    let entries = folder
+        .read_dir()
+        .map_err(|err| format!("Could not read folder {}: {}", folder.display(), err))?;
+    let output_file = output_file.and_then(|output_file| output_file.canonicalize().ok());
+
+    let audio_files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+        })
+        .filter(|path| output_file.is_none() || path.canonicalize().ok() != output_file)
+        .collect();
+
+    audio_files
+        .into_iter()
+        .map(|path| read_track_file(&path))
+        .collect()
+}
+
+/// Tracks can only be joined without loss, if they are lossless and share the same stream format
+fn check_lossless_join(track_files: &[TrackFile]) -> Result<(), String> {
+    if let Some(track_file) = track_files.iter().find(|track_file| {
+        !LOSSLESS_CODECS.contains(&track_file.codec.as_str())
+            && !track_file.codec.starts_with("pcm_")
+    }) {
+        return Err(format!(
+            "{} is encoded with the lossy or unsupported codec {}, only lossless tracks can be joined",
+            track_file.path.display(),
+            track_file.codec
+        ));
+    }
+
+    let first_track = &track_files[0];
+    if let Some(track_file) = track_files.iter().find(|track_file| {
+        track_file.sample_rate != first_track.sample_rate
+            || track_file.channels != first_track.channels
+            || track_file.bits_per_sample != first_track.bits_per_sample
+            || is_float(&track_file.codec) != is_float(&first_track.codec)
+    }) {
+        return Err(format!(
+            "All tracks must have the same sample rate, channels and bit depth to be joined losslessly, {} differs from {}",
+            track_file.path.display(),
+            first_track.path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn read_track_file(path: &Path) -> Result<TrackFile, String> {
+    let audio_stream = read_audio_stream(path)?;
+
+    let tagged_file = lofty::read_from_path(path).ok();
+    let tag = tagged_file
+        .as_ref()
+        .and_then(|tagged_file| tagged_file.primary_tag().or(tagged_file.first_tag()));
+
+    Ok(TrackFile {
+        path: path.to_path_buf(),
+        disc_number: tag.and_then(|tag| tag.disk()),
+        track_number: tag.and_then(|tag| tag.track()),
+        title: tag
+            .and_then(|tag| tag.title())
+            .map(|value| value.to_string()),
+        artist: tag
+            .and_then(|tag| tag.artist())
+            .map(|value| value.to_string()),
+        album: tag
+            .and_then(|tag| tag.album())
+            .map(|value| value.to_string()),
+        album_artist: tag
+            .and_then(|tag| tag.get_string(ItemKey::AlbumArtist))
+            .map(str::to_string),
+        codec: audio_stream.codec,
+        samples: audio_stream.samples,
+        sample_rate: audio_stream.sample_rate,
+        channels: audio_stream.channels,
+        bits_per_sample: audio_stream.bits_per_sample,
+    })
+}
+
+/// The stream format and exact length of a track, as reported by ffprobe
+struct AudioStream {
+    codec: String,
+    samples: u64,
+    sample_rate: u64,
+    channels: Option<u32>,
+    bits_per_sample: Option<u32>,
+}
+
+/// Reads the codec, the exact length in samples, the sample rate, the channels and the bit depth using ffprobe
+/// Example call: ffprobe -v error -select_streams a:0 -show_entries stream=codec_name,sample_rate,channels,duration_ts,duration,bits_per_raw_sample,bits_per_sample,time_base -of default=noprint_wrappers=1 input.flac
+fn read_audio_stream(path: &Path) -> Result<AudioStream, String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "a:0",
+            "-show_entries",
+            "stream=codec_name,sample_rate,channels,duration_ts,duration,bits_per_raw_sample,bits_per_sample,time_base",
+            "-of",
+            "default=noprint_wrappers=1",
+        ])
+        .arg(path)
+        .output()
+        .map_err(|err| format!("Could not run ffprobe on {}: {}", path.display(), err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed to read {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let value = |key: &str| -> Option<String> {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{}=", key)))
+            .map(str::to_string)
+            .filter(|value| value != "N/A")
+    };
+
+    let sample_rate = value("sample_rate")
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or(format!("Could not read sample rate of {}", path.display()))?;
+    let codec = value("codec_name").ok_or(format!("Could not read codec of {}", path.display()))?;
+    let channels = value("channels").and_then(|value| value.parse::<u32>().ok());
+    // PCM streams only report bits_per_sample, compressed streams only bits_per_raw_sample
+    let bits_per_sample = ["bits_per_raw_sample", "bits_per_sample"]
+        .iter()
+        .filter_map(|key| value(key).and_then(|value| value.parse::<u32>().ok()))
+        .find(|bits| *bits > 0);
+
+    // duration_ts is exact, if the time base equals the sample rate (e.g. FLAC, WAV)
+    let exact_samples = value("duration_ts")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|_| value("time_base") == Some(format!("1/{}", sample_rate)));
+    let samples = exact_samples
+        .or_else(|| {
+            value("duration")
+                .and_then(|value| value.parse::<f64>().ok())
+                .map(|seconds| (seconds * sample_rate as f64).round() as u64)
+        })
+        .ok_or(format!("Could not read length of {}", path.display()))?;
+
+    Ok(AudioStream {
+        codec,
+        samples,
+        sample_rate,
+        channels,
+        bits_per_sample,
+    })
+}
+
+/// Sorts the track files by disc and track number tags or by file name
+/// Files without track number are sorted by file name after the tagged ones
+fn sort_track_files(track_files: &mut [TrackFile], order: JoinOrder) {
+    track_files.sort_by(|a, b| {
+        let by_file_name = || compare_file_names(&a.path, &b.path);
+        match order {
+            JoinOrder::Filename => by_file_name(),
+            JoinOrder::Tag => match (a.track_number, b.track_number) {
+                (Some(a_track), Some(b_track)) => (a.disc_number.unwrap_or(1), a_track)
+                    .cmp(&(b.disc_number.unwrap_or(1), b_track))
+                    .then_with(by_file_name),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => by_file_name(),
+            },
+        }
+    });
+}
+
+/// Compares file names naturally, so that "2 b.flac" comes before "10 a.flac"
+fn compare_file_names(a: &Path, b: &Path) -> Ordering {
+    let a_name = a.file_name().unwrap().to_string_lossy().to_lowercase();
+    let b_name = b.file_name().unwrap().to_string_lossy().to_lowercase();
+    let leading_number = |name: &str| -> Option<u64> {
+        name.chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse::<u64>()
+            .ok()
+    };
+
+    match (leading_number(&a_name), leading_number(&b_name)) {
+        (Some(a_number), Some(b_number)) if a_number != b_number => a_number.cmp(&b_number),
+        _ => a_name.cmp(&b_name),
+    }
+}
+
+/// The joined file is written next to the track folder, so it is not taken as a track on the next join
+fn default_output_file(folder: &Path, track_files: &[TrackFile], format: JoinFormat) -> PathBuf {
+    let folder = folder.canonicalize().unwrap_or(folder.to_path_buf());
+    let name = track_files[0]
+        .album
+        .clone()
+        .unwrap_or_else(|| {
+            folder
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or("joined".to_string())
+        })
+        .replace(['/', '\\', ':'], "-");
+    let extension = match format {
+        JoinFormat::Flac => "flac",
+        JoinFormat::Wav => "wav",
+    };
+    folder
+        .parent()
+        .unwrap_or(&folder)
+        .join(format!("{}.{}", name, extension))
+}
+
+/// Builds a cue sheet for the joined file
+/// Track start times are derived from the accumulated sample lengths and rounded down to CDDA frames
+fn build_cue_sheet(output_file: &Path, track_files: &[TrackFile]) -> CueSheet {
+    let mut position_samples: u64 = 0;
+    let mut tracks = Vec::new();
+
+    for (index, track_file) in track_files.iter().enumerate() {
+        let start_frames = position_samples * 75 / track_file.sample_rate;
+        tracks.push(Track {
+            number: index as u32 + 1,
+            track_type: "AUDIO".to_string(),
+            flags: Vec::new(),
+            title: track_file.title.clone().or_else(|| {
+                track_file
+                    .path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+            }),
+            artist: track_file.artist.clone(),
+            pregap_start_time: None,
+            start_time: Some(CueDuration::from_frames(start_frames as u32)),
+            other_indexes: Vec::new(),
+            other_commands: Vec::new(),
+            output_file: Some(track_file.path.clone()),
+            ffmpeg_args: Vec::new(),
+            ffmpeg_command: None,
+        });
+        position_samples += track_file.samples;
+    }
+
+    let first_track = &track_files[0];
+    let audio_file = CueFile {
+        name: output_file
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string(),
+        file_type: cue_writer::file_type(output_file).to_string(),
+        track_count: tracks.len(),
+        starts_inside_track: false,
+    };
+    CueSheet {
+        cue_file_path: output_file.with_extension("cue"),
+        audio_file_path: output_file.to_path_buf(),
+        audio_file_name: audio_file.name.clone(),
+        audio_file_type: audio_file.file_type.clone(),
+        audio_files: vec![audio_file],
+        cue_encoding: CueEncoding::utf8(),
+        output_dir: None,
+        title: first_track.album.clone(),
+        performer: first_track
+            .album_artist
+            .clone()
+            .or(first_track.artist.clone()),
+        rem_disc_number: None,
+        rem_total_discs: None,
+        disc_number: None,
+        total_discs: None,
+        source_tags: None,
+        cover: None,
+        audio_codec: None,
+        audio_duration: None,
+        problems: Vec::new(),
+        fixes: Vec::new(),
+        other_commands: Vec::new(),
+        invalid_commands: Vec::new(),
+        tracks,
+    }
+}
+
+/// Builds the input list for the ffmpeg concat demuxer
+fn build_concat_list(track_files: &[TrackFile]) -> String {
+    track_files
+        .iter()
+        .map(|track_file| {
+            let path = track_file
+                .path
+                .canonicalize()
+                .unwrap_or(track_file.path.clone());
+            format!(
+                "file '{}'\n",
+                path.display().to_string().replace('\'', "'\\''")
+            )
+        })
+        .collect()
+}
+
+/// Floating point PCM, e.g. pcm_f32le
+fn is_float(codec: &str) -> bool {
+    codec.starts_with("pcm_f")
+}
+
+/// Picks the output codec that holds the samples of the tracks without loss
+/// Bit depths are rounded up to the next PCM sample size, e.g. 20 bit tracks are written as 24 bit WAV
+fn output_codec(format: JoinFormat, track_file: &TrackFile) -> Result<String, String> {
+    match (format, track_file.bits_per_sample) {
+        (JoinFormat::Flac, _) if is_float(&track_file.codec) => Err(format!(
+            "FLAC can not hold the floating point samples of {}, join them with --format wav",
+            track_file.path.display()
+        )),
+        (JoinFormat::Flac, Some(bits_per_sample)) if bits_per_sample > 24 => Err(format!(
+            "FLAC can not hold the {} bit samples of {}, join them with --format wav",
+            bits_per_sample,
+            track_file.path.display()
+        )),
+        // Without a known bit depth, ffmpeg keeps the sample format of the decoder
+        (JoinFormat::Flac, _) => Ok("flac".to_string()),
+        (JoinFormat::Wav, None) => Err(format!(
+            "Could not read the bit depth of {}, it can not be joined into a WAV file losslessly",
+            track_file.path.display()
+        )),
+        (JoinFormat::Wav, Some(64)) if is_float(&track_file.codec) => Ok("pcm_f64le".to_string()),
+        (JoinFormat::Wav, Some(_)) if is_float(&track_file.codec) => Ok("pcm_f32le".to_string()),
+        (JoinFormat::Wav, Some(0..=8)) => Ok("pcm_u8".to_string()),
+        (JoinFormat::Wav, Some(9..=16)) => Ok("pcm_s16le".to_string()),
+        (JoinFormat::Wav, Some(17..=24)) => Ok("pcm_s24le".to_string()),
+        (JoinFormat::Wav, Some(25..=32)) => Ok("pcm_s32le".to_string()),
+        (JoinFormat::Wav, Some(bits_per_sample)) => Err(format!(
+            "WAV can not hold the {} bit samples of {}",
+            bits_per_sample,
+            track_file.path.display()
+        )),
+    }
+}
+
+/// Writes the concat list and runs ffmpeg
+fn run_ffmpeg_join(
+    concat_list_file: &Path,
+    track_files: &[TrackFile],
+    ffmpeg_args: &[OsString],
+) -> Result<(), String> {
+    fs::write(concat_list_file, build_concat_list(track_files)).map_err(|err| {
+        format!(
+            "Could not write the track list {}: {}",
+            concat_list_file.display(),
+            err
+        )
+    })?;
+
+    let output = Command::new("ffmpeg")
+        .args(ffmpeg_args)
+        .output()
+        .map_err(|err| format!("Could not run ffmpeg: {}", err))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    Ok(())
+}
+
+fn build_ffmpeg_join_args(
+    concat_list_file: &Path,
+    output_file: &Path,
+    codec: &str,
+) -> Vec<OsString> {
+    vec![
+        "-y".into(),
+        "-f".into(),
+        "concat".into(),
+        "-safe".into(),
+        "0".into(),
+        "-i".into(),
+        concat_list_file.into(),
+        "-map_metadata".into(),
+        "-1".into(),
+        "-c:a".into(),
+        codec.into(),
+        output_file.into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track_file(codec: &str, bits_per_sample: Option<u32>) -> TrackFile {
+        TrackFile {
+            path: PathBuf::from("01 Track.wav"),
+            disc_number: None,
+            track_number: Some(1),
+            title: None,
+            artist: None,
+            album: None,
+            album_artist: None,
+            codec: codec.to_string(),
+            samples: 44100,
+            sample_rate: 44100,
+            channels: Some(2),
+            bits_per_sample,
+        }
+    }
+
+    #[test]
+    fn rounds_bit_depths_up_to_the_next_pcm_sample_size() {
+        let wav_codec = |bits| output_codec(JoinFormat::Wav, &track_file("flac", Some(bits)));
+        assert_eq!(wav_codec(8), Ok("pcm_u8".to_string()));
+        assert_eq!(wav_codec(16), Ok("pcm_s16le".to_string()));
+        assert_eq!(wav_codec(20), Ok("pcm_s24le".to_string()));
+        assert_eq!(wav_codec(24), Ok("pcm_s24le".to_string()));
+        assert_eq!(wav_codec(32), Ok("pcm_s32le".to_string()));
+    }
+
+    #[test]
+    fn keeps_floating_point_samples() {
+        assert_eq!(
+            output_codec(JoinFormat::Wav, &track_file("pcm_f32le", Some(32))),
+            Ok("pcm_f32le".to_string())
+        );
+        assert_eq!(
+            output_codec(JoinFormat::Wav, &track_file("pcm_f64le", Some(64))),
+            Ok("pcm_f64le".to_string())
+        );
+        assert!(output_codec(JoinFormat::Flac, &track_file("pcm_f32le", Some(32))).is_err());
+    }
+
+    #[test]
+    fn refuses_bit_depths_flac_can_not_hold() {
+        assert_eq!(
+            output_codec(JoinFormat::Flac, &track_file("pcm_s24le", Some(24))),
+            Ok("flac".to_string())
+        );
+        assert!(output_codec(JoinFormat::Flac, &track_file("pcm_s32le", Some(32))).is_err());
+        assert_eq!(
+            output_codec(JoinFormat::Flac, &track_file("ape", None)),
+            Ok("flac".to_string())
+        );
+        assert!(output_codec(JoinFormat::Wav, &track_file("ape", None)).is_err());
+    }
+
+    #[test]
+    fn refuses_mixed_integer_and_floating_point_tracks() {
+        let track_files = [
+            track_file("pcm_s32le", Some(32)),
+            track_file("pcm_f32le", Some(32)),
+        ];
+        assert!(check_lossless_join(&track_files).is_err());
+        assert!(check_lossless_join(&track_files[..1]).is_ok());
+    }
+}
@@ -0,0 +1,768 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use argh::FromArgs;
+use colour::{green_ln, red_ln, yellow_ln};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::cue_encoding::{self, CueEncoding};
+use crate::{
+    audio_duration, check_tools, exit_code, find_cue_files, parse_cue_text, probe_audio_stream,
+    CueDuration, CueSheet,
+};
+
+/// Check cue files for problems, without changing or splitting anything
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "lint")]
+#[argh(
+    note = "Exits with 4 (invalid input) if a cue file has an error, run with --list-rules to see all rules"
+)]
+pub struct LintArgs {
+    /// output format: text or json (default: text)
+    #[argh(option, default = "LintFormat::Text")]
+    format: LintFormat,
+
+    /// list all rules with their severity and exit
+    #[argh(switch)]
+    list_rules: bool,
+
+    /// decode cue files with the given encoding instead of detecting it, e.g. windows-1251 or shift_jis
+    #[argh(option)]
+    cue_encoding: Option<CueEncoding>,
+
+    /// file or folder paths to check
+    /// default is "."
+    #[argh(positional, greedy)]
+    pub cue_file_or_folders: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LintFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LintFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(LintFormat::Text),
+            "json" => Ok(LintFormat::Json),
+            _ => Err(format!(
+                "unknown lint format '{}', expected one of: text, json",
+                value
+            )),
+        }
+    }
+}
+
+/// How bad a finding is, only errors make lint fail
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth knowing, split handles it
+    Info,
+    /// Split works, but the result is probably not what was intended, e.g. missing tags
+    Warning,
+    /// Split refuses the cue sheet or produces broken tracks
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+const UNREADABLE_CUE_FILE: Rule = Rule {
+    id: "unreadable-cue-file",
+    severity: Severity::Error,
+    description: "The cue file can not be read",
+};
+const UNDECODABLE_CHARACTERS: Rule = Rule {
+    id: "undecodable-characters",
+    severity: Severity::Error,
+    description: "Characters of the cue file are invalid in its encoding",
+};
+const UNCERTAIN_ENCODING: Rule = Rule {
+    id: "uncertain-encoding",
+    severity: Severity::Warning,
+    description: "The encoding of the cue file could not be detected reliably",
+};
+const MISSING_FILE: Rule = Rule {
+    id: "missing-file",
+    severity: Severity::Error,
+    description: "The cue sheet has no FILE command",
+};
+const MULTIPLE_FILES: Rule = Rule {
+    id: "multiple-files",
+    severity: Severity::Info,
+    description: "The cue sheet references one file per track, it seems to be already split",
+};
+const TRACKS_ACROSS_FILES: Rule = Rule {
+    id: "tracks-across-files",
+    severity: Severity::Error,
+    description:
+        "The cue sheet references several files with more than one track, split can not handle it",
+};
+const ABSOLUTE_FILE_PATH: Rule = Rule {
+    id: "absolute-file-path",
+    severity: Severity::Warning,
+    description: "The FILE command references an absolute path",
+};
+const AUDIO_FILE_MISSING: Rule = Rule {
+    id: "audio-file-missing",
+    severity: Severity::Error,
+    description: "The referenced audio file does not exist",
+};
+const AUDIO_FILE_UNREADABLE: Rule = Rule {
+    id: "audio-file-unreadable",
+    severity: Severity::Error,
+    description: "ffmpeg can not decode the referenced audio file",
+};
+const NO_TRACKS: Rule = Rule {
+    id: "no-tracks",
+    severity: Severity::Error,
+    description: "The cue sheet has no tracks",
+};
+const INVALID_TRACK_NUMBER: Rule = Rule {
+    id: "invalid-track-number",
+    severity: Severity::Error,
+    description: "A TRACK has no valid number",
+};
+const DUPLICATE_TRACK_NUMBER: Rule = Rule {
+    id: "duplicate-track-number",
+    severity: Severity::Error,
+    description: "Two tracks have the same number",
+};
+const TRACK_NUMBER_GAP: Rule = Rule {
+    id: "track-number-gap",
+    severity: Severity::Warning,
+    description: "The track numbers do not count up from 1 without gaps",
+};
+const INVALID_INDEX: Rule = Rule {
+    id: "invalid-index",
+    severity: Severity::Error,
+    description: "An INDEX has no valid mm:ss:ff time",
+};
+const MISSING_INDEX: Rule = Rule {
+    id: "missing-index",
+    severity: Severity::Error,
+    description: "A track has no INDEX 01, its start time is unknown",
+};
+const NON_MONOTONIC_INDEX: Rule = Rule {
+    id: "non-monotonic-index",
+    severity: Severity::Error,
+    description: "An index does not start after the previous one",
+};
+const INDEX_PAST_AUDIO_END: Rule = Rule {
+    id: "index-past-audio-end",
+    severity: Severity::Error,
+    description: "An index starts after the end of the audio file",
+};
+const MISSING_ALBUM_TITLE: Rule = Rule {
+    id: "missing-album-title",
+    severity: Severity::Warning,
+    description: "The cue sheet has no album TITLE",
+};
+const MISSING_TRACK_TITLE: Rule = Rule {
+    id: "missing-track-title",
+    severity: Severity::Warning,
+    description: "A track has no TITLE",
+};
+
+/// All rules in the order they are checked
+pub const RULES: [&Rule; 19] = [
+    &UNREADABLE_CUE_FILE,
+    &UNDECODABLE_CHARACTERS,
+    &UNCERTAIN_ENCODING,
+    &MISSING_FILE,
+    &MULTIPLE_FILES,
+    &TRACKS_ACROSS_FILES,
+    &ABSOLUTE_FILE_PATH,
+    &AUDIO_FILE_MISSING,
+    &AUDIO_FILE_UNREADABLE,
+    &NO_TRACKS,
+    &INVALID_TRACK_NUMBER,
+    &DUPLICATE_TRACK_NUMBER,
+    &TRACK_NUMBER_GAP,
+    &INVALID_INDEX,
+    &MISSING_INDEX,
+    &NON_MONOTONIC_INDEX,
+    &INDEX_PAST_AUDIO_END,
+    &MISSING_ALBUM_TITLE,
+    &MISSING_TRACK_TITLE,
+];
+
+/// A rule violation found in a cue file
+pub struct Finding {
+    pub rule: &'static Rule,
+    /// The track the finding refers to, None for the whole cue sheet
+    pub track: Option<u32>,
+    pub message: String,
+}
+
+/// The findings of a single cue file
+pub struct LintReport {
+    pub cue_file_path: PathBuf,
+    /// The encoding the cue file was decoded with, None if it could not be read
+    pub encoding: Option<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl LintReport {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.rule.severity == severity)
+            .count()
+    }
+}
+
+/// Runs the lint command, the process exits with invalid input if a cue file has an error
+/// Nothing but the report is printed, so the JSON output can be parsed
+pub fn lint(lint_args: LintArgs, config: &Config) {
+    if lint_args.list_rules {
+        print_rules();
+        return;
+    }
+
+    check_tools(vec!["ffprobe"]);
+
+    let cue_encoding = lint_args.cue_encoding.or(config.cue_encoding);
+    let cue_file_or_folders = if lint_args.cue_file_or_folders.is_empty() {
+        vec![".".to_string()]
+    } else {
+        lint_args.cue_file_or_folders
+    };
+    let lint_reports: Vec<LintReport> = cue_file_or_folders
+        .iter()
+        .flat_map(|input_path| find_cue_files(Path::new(input_path)))
+        .map(|cue_file_path| lint_cue_file(&cue_file_path, cue_encoding))
+        .collect();
+
+    match lint_args.format {
+        LintFormat::Text => print_text_report(&lint_reports),
+        LintFormat::Json => print_json_report(&lint_reports),
+    }
+
+    let has_errors = lint_reports
+        .iter()
+        .any(|lint_report| lint_report.count(Severity::Error) > 0);
+    if has_errors {
+        std::process::exit(exit_code::INVALID_INPUT);
+    }
+}
+
+/// Checks a single cue file against all rules
+pub fn lint_cue_file(cue_file_path: &Path, encoding_override: Option<CueEncoding>) -> LintReport {
+    let mut lint_report = LintReport {
+        cue_file_path: cue_file_path.to_path_buf(),
+        encoding: None,
+        findings: Vec::new(),
+    };
+
+    let decoded_cue = match cue_encoding::read_cue_file(cue_file_path, encoding_override) {
+        Ok(decoded_cue) => decoded_cue,
+        Err(err) => {
+            add_finding(&mut lint_report.findings, &UNREADABLE_CUE_FILE, None, err);
+            return lint_report;
+        }
+    };
+    lint_report.encoding = Some(decoded_cue.encoding.name().to_string());
+
+    let findings = &mut lint_report.findings;
+    if decoded_cue.text.contains(char::REPLACEMENT_CHARACTER) {
+        add_finding(
+            findings,
+            &UNDECODABLE_CHARACTERS,
+            None,
+            format!(
+                "Some characters are invalid in {}, pass the right one with --cue-encoding",
+                decoded_cue.encoding.name()
+            ),
+        );
+    } else if decoded_cue.uncertain {
+        add_finding(
+            findings,
+            &UNCERTAIN_ENCODING,
+            None,
+            format!("Encoding: {}", decoded_cue.report),
+        );
+    }
+
+    let cue_sheet = parse_cue_text(cue_file_path, &decoded_cue.text);
+    check_audio_file(&cue_sheet, findings);
+    check_track_numbers(&cue_sheet, findings);
+    check_indexes(&cue_sheet, findings);
+    check_titles(&cue_sheet, findings);
+
+    lint_report
+}
+
+fn check_audio_file(cue_sheet: &CueSheet, findings: &mut Vec<Finding>) {
+    match cue_sheet.audio_file_count() {
+        0 => {
+            add_finding(
+                findings,
+                &MISSING_FILE,
+                None,
+                "No audio file is referenced".to_string(),
+            );
+            return;
+        }
+        1 => {}
+        audio_file_count if cue_sheet.is_already_split() => {
+            add_finding(
+                findings,
+                &MULTIPLE_FILES,
+                None,
+                format!(
+                    "{} audio files with one track each are referenced, split skips this cue sheet",
+                    audio_file_count
+                ),
+            );
+            return;
+        }
+        audio_file_count => {
+            add_finding(
+                findings,
+                &TRACKS_ACROSS_FILES,
+                None,
+                format!(
+                    "{} audio files are referenced, split can only handle a single audio file",
+                    audio_file_count
+                ),
+            );
+            return;
+        }
+    }
+
+    if is_absolute_path(&cue_sheet.audio_file_name) {
+        add_finding(
+            findings,
+            &ABSOLUTE_FILE_PATH,
+            None,
+            format!(
+                "{} only exists on the machine the cue sheet was made on, reference the file name only",
+                cue_sheet.audio_file_name
+            ),
+        );
+    }
+
+    if !cue_sheet.audio_file_path.is_file() {
+        add_finding(
+            findings,
+            &AUDIO_FILE_MISSING,
+            None,
+            format!("{} was not found", cue_sheet.audio_file_path.display()),
+        );
+        return;
+    }
+
+    if let Err(ffprobe_output) = probe_audio_stream(&cue_sheet.audio_file_path) {
+        add_finding(
+            findings,
+            &AUDIO_FILE_UNREADABLE,
+            None,
+            format!(
+                "{} is corrupt or its codec is not supported: {}",
+                cue_sheet.audio_file_name,
+                ffprobe_output.trim()
+            ),
+        );
+    }
+}
+
+/// Windows paths are checked as well, as cue sheets are often written on Windows
+fn is_absolute_path(file_name: &str) -> bool {
+    let bytes = file_name.as_bytes();
+    let has_drive_letter = bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    file_name.starts_with('/') || file_name.starts_with('\\') || has_drive_letter
+}
+
+fn check_track_numbers(cue_sheet: &CueSheet, findings: &mut Vec<Finding>) {
+    if cue_sheet.tracks.is_empty() {
+        add_finding(
+            findings,
+            &NO_TRACKS,
+            None,
+            "No TRACK command was found".to_string(),
+        );
+        return;
+    }
+
+    for (track_number, command) in &cue_sheet.invalid_commands {
+        if command.starts_with("TRACK") {
+            add_finding(
+                findings,
+                &INVALID_TRACK_NUMBER,
+                *track_number,
+                format!("{} has no valid track number", command),
+            );
+        }
+    }
+
+    let mut track_numbers = HashSet::new();
+    for track in &cue_sheet.tracks {
+        if !track_numbers.insert(track.number) {
+            add_finding(
+                findings,
+                &DUPLICATE_TRACK_NUMBER,
+                Some(track.number),
+                format!("Track {:02} exists more than once", track.number),
+            );
+        }
+    }
+
+    let mut expected_number = 1;
+    for track in &cue_sheet.tracks {
+        if track.number != expected_number {
+            add_finding(
+                findings,
+                &TRACK_NUMBER_GAP,
+                Some(track.number),
+                format!(
+                    "Track {:02} follows track {:02}",
+                    track.number,
+                    expected_number - 1
+                ),
+            );
+        }
+        expected_number = track.number + 1;
+    }
+}
+
+fn check_indexes(cue_sheet: &CueSheet, findings: &mut Vec<Finding>) {
+    let invalid_indexes: Vec<(Option<u32>, &str)> = cue_sheet
+        .invalid_commands
+        .iter()
+        .filter(|(_, command)| command.starts_with("INDEX"))
+        .map(|(track_number, command)| (*track_number, command.as_str()))
+        .collect();
+    for (track_number, command) in &invalid_indexes {
+        add_finding(
+            findings,
+            &INVALID_INDEX,
+            *track_number,
+            format!("{} has no valid mm:ss:ff time", command),
+        );
+    }
+
+    let mut previous_index: Option<(u32, CueDuration)> = None;
+    for track in &cue_sheet.tracks {
+        // A track whose INDEX 01 is invalid was reported already
+        let has_invalid_index = invalid_indexes
+            .iter()
+            .any(|(track_number, _)| *track_number == Some(track.number));
+        if track.start_time.is_none() && !has_invalid_index {
+            add_finding(
+                findings,
+                &MISSING_INDEX,
+                Some(track.number),
+                format!("Track {:02} has no INDEX 01", track.number),
+            );
+        }
+
+        // All indexes of all tracks have to grow, INDEX 00 may equal the end of the previous track though
+        let indexes = [(0, track.pregap_start_time), (1, track.start_time)]
+            .into_iter()
+            .filter_map(|(index_number, index_time)| Some((index_number, index_time?)))
+            .chain(track.other_indexes.iter().copied());
+        for (index_number, index_time) in indexes {
+            if let Some((previous_track_number, previous_index_time)) = previous_index {
+                if index_time <= previous_index_time {
+                    add_finding(
+                        findings,
+                        &NON_MONOTONIC_INDEX,
+                        Some(track.number),
+                        format!(
+                            "INDEX {:02} of track {:02} at {} does not start after {} of track {:02}",
+                            index_number,
+                            track.number,
+                            index_time,
+                            previous_index_time,
+                            previous_track_number
+                        ),
+                    );
+                }
+            }
+            previous_index = Some((track.number, index_time));
+        }
+    }
+
+    // Only probe the duration of audio files ffmpeg can read, other problems were reported already
+    let has_audio_findings = findings.iter().any(|finding| {
+        finding.rule.id == AUDIO_FILE_MISSING.id || finding.rule.id == AUDIO_FILE_UNREADABLE.id
+    });
+    if cue_sheet.audio_file_count() != 1 || has_audio_findings {
+        return;
+    }
+    let Some(audio_duration) = audio_duration::read_audio_duration(&cue_sheet.audio_file_path)
+    else {
+        return;
+    };
+    for track in &cue_sheet.tracks {
+        let Some(start_time) = track.start_time else {
+            continue;
+        };
+        if start_time.total_frames() as f64 / 75.0 >= audio_duration {
+            add_finding(
+                findings,
+                &INDEX_PAST_AUDIO_END,
+                Some(track.number),
+                format!(
+                    "Track {:02} starts at {}, but the audio file ends at {}",
+                    track.number,
+                    start_time,
+                    CueDuration::from_frames((audio_duration * 75.0) as u32)
+                ),
+            );
+        }
+    }
+}
+
+fn check_titles(cue_sheet: &CueSheet, findings: &mut Vec<Finding>) {
+    if cue_sheet.title.as_deref().is_none_or(str::is_empty) {
+        add_finding(
+            findings,
+            &MISSING_ALBUM_TITLE,
+            None,
+            "The album title is missing".to_string(),
+        );
+    }
+    for track in &cue_sheet.tracks {
+        if track.title.as_deref().is_none_or(str::is_empty) {
+            add_finding(
+                findings,
+                &MISSING_TRACK_TITLE,
+                Some(track.number),
+                format!("Track {:02} has no title", track.number),
+            );
+        }
+    }
+}
+
+fn add_finding(
+    findings: &mut Vec<Finding>,
+    rule: &'static Rule,
+    track: Option<u32>,
+    message: String,
+) {
+    findings.push(Finding {
+        rule,
+        track,
+        message,
+    });
+}
+
+fn print_rules() {
+    for rule in RULES {
+        println!(
+            "{:<24} {:<8} {}",
+            rule.id,
+            rule.severity.to_string(),
+            rule.description
+        );
+    }
+}
+
+fn print_text_report(lint_reports: &[LintReport]) {
+    for lint_report in lint_reports {
+        if lint_report.findings.is_empty() {
+            continue;
+        }
+        println!("📄 {}", lint_report.cue_file_path.display());
+        for finding in &lint_report.findings {
+            let line = format!(
+                "\t{:<8} {:<24} {}",
+                finding.rule.severity.to_string(),
+                finding.rule.id,
+                finding.message
+            );
+            match finding.rule.severity {
+                Severity::Error => red_ln!("{}", line),
+                Severity::Warning => yellow_ln!("{}", line),
+                Severity::Info => println!("{}", line),
+            }
+        }
+        println!();
+    }
+
+    let count = |severity| {
+        lint_reports
+            .iter()
+            .map(|lint_report| lint_report.count(severity))
+            .sum::<usize>()
+    };
+    let failed_count = lint_reports
+        .iter()
+        .filter(|lint_report| lint_report.count(Severity::Error) > 0)
+        .count();
+    let summary = format!(
+        "{} cue file(s) checked, {} with errors: {} error(s), {} warning(s), {} info(s)",
+        lint_reports.len(),
+        failed_count,
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info)
+    );
+    if failed_count == 0 {
+        green_ln!("✅ {}", summary);
+    } else {
+        red_ln!("❌ {}", summary);
+    }
+}
+
+/// Prints the report as JSON, the schema is documented in the README
+fn print_json_report(lint_reports: &[LintReport]) {
+    let files: Vec<serde_json::Value> = lint_reports
+        .iter()
+        .map(|lint_report| {
+            json!({
+                "path": lint_report.cue_file_path.display().to_string(),
+                "encoding": lint_report.encoding,
+                "findings": lint_report.findings.iter().map(|finding| json!({
+                    "rule": finding.rule.id,
+                    "severity": finding.rule.severity.to_string(),
+                    "track": finding.track,
+                    "message": finding.message,
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let count = |severity| {
+        lint_reports
+            .iter()
+            .map(|lint_report| lint_report.count(severity))
+            .sum::<usize>()
+    };
+
+    let report = json!({
+        "files": files,
+        "summary": {
+            "files": lint_reports.len(),
+            "errors": count(Severity::Error),
+            "warnings": count(Severity::Warning),
+            "infos": count(Severity::Info),
+        },
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_cue_text(cue_text: &str) -> Vec<&'static str> {
+        let cue_sheet = parse_cue_text(Path::new("album.cue"), cue_text);
+        let mut findings = Vec::new();
+        check_track_numbers(&cue_sheet, &mut findings);
+        check_indexes(&cue_sheet, &mut findings);
+        check_titles(&cue_sheet, &mut findings);
+        findings.iter().map(|finding| finding.rule.id).collect()
+    }
+
+    const VALID_CUE: &str = "TITLE \"Album\"\nFILE \"album.flac\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"One\"\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    TITLE \"Two\"\n    INDEX 00 02:58:00\n    INDEX 01 03:00:00\n";
+
+    #[test]
+    fn valid_cue_sheet_has_no_findings() {
+        assert!(lint_cue_text(VALID_CUE).is_empty());
+    }
+
+    #[test]
+    fn malformed_index_time_is_an_invalid_index() {
+        for index_time in [
+            "00:0a:00",
+            "0000",
+            "03:00",
+            "03:00:00:00",
+            "03:60:00",
+            "03:00:75",
+        ] {
+            let cue_text =
+                VALID_CUE.replace("INDEX 01 03:00:00", &format!("INDEX 01 {}", index_time));
+            assert_eq!(
+                lint_cue_text(&cue_text),
+                vec!["invalid-index"],
+                "{}",
+                index_time
+            );
+        }
+    }
+
+    #[test]
+    fn index_without_time_is_an_invalid_index() {
+        let cue_text = VALID_CUE.replace("INDEX 00 02:58:00", "INDEX 00");
+        assert_eq!(lint_cue_text(&cue_text), vec!["invalid-index"]);
+    }
+
+    #[test]
+    fn malformed_track_number_is_an_invalid_track_number() {
+        for track_command in ["TRACK xx AUDIO", "TRACK -1 AUDIO"] {
+            let cue_text = VALID_CUE.replace("TRACK 02 AUDIO", track_command);
+            assert_eq!(
+                lint_cue_text(&cue_text),
+                vec!["invalid-track-number"],
+                "{}",
+                track_command
+            );
+        }
+    }
+
+    #[test]
+    fn track_without_index_is_a_missing_index() {
+        let cue_text = VALID_CUE.replace("    INDEX 01 03:00:00\n", "");
+        assert_eq!(lint_cue_text(&cue_text), vec!["missing-index"]);
+    }
+
+    #[test]
+    fn index_before_the_previous_track_is_non_monotonic() {
+        let cue_text = VALID_CUE.replace("INDEX 00 02:58:00", "INDEX 00 00:00:00");
+        assert_eq!(lint_cue_text(&cue_text), vec!["non-monotonic-index"]);
+    }
+
+    #[test]
+    fn track_numbers_are_checked() {
+        let cue_text = VALID_CUE.replace("TRACK 02", "TRACK 03");
+        assert_eq!(lint_cue_text(&cue_text), vec!["track-number-gap"]);
+        let cue_text = VALID_CUE.replace("TRACK 02", "TRACK 01");
+        assert_eq!(
+            lint_cue_text(&cue_text),
+            vec!["duplicate-track-number", "track-number-gap"]
+        );
+    }
+
+    #[test]
+    fn missing_titles_are_warnings() {
+        let cue_text = VALID_CUE
+            .replace("TITLE \"Album\"\n", "")
+            .replace("TITLE \"Two\"", "");
+        assert_eq!(
+            lint_cue_text(&cue_text),
+            vec!["missing-album-title", "missing-track-title"]
+        );
+    }
+
+    #[test]
+    fn rules_have_unique_ids() {
+        let rule_ids: HashSet<&str> = RULES.iter().map(|rule| rule.id).collect();
+        assert_eq!(rule_ids.len(), RULES.len());
+    }
+}
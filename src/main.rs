@@ -1,27 +1,131 @@
+mod audio_duration;
+mod config;
+mod cover;
+mod cue_encoding;
+mod cue_writer;
+mod disc_number;
+mod embed;
+mod exit_code;
+mod fix;
+mod info;
+mod join;
+mod lint;
+mod options;
+mod output;
+mod playlist;
+mod release;
+mod replaygain;
+mod report;
+mod tag;
+mod tags;
+mod update_verify;
 mod updater;
+mod watch;
 
 use argh::FromArgs;
-use chardet::charset2encoding;
+use audio_duration::ExpectedDuration;
 use colour::{blue_ln, green_ln, red_ln, yellow_ln};
-use encoding::DecoderTrap;
+use config::Config;
+use cue_encoding::{CueConversion, CueEncoding};
+use embed::CueEmbedMode;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use lazy_static::lazy_static;
 use lofty::config::WriteOptions;
 use lofty::file::TaggedFileExt;
-use lofty::tag::{Accessor, Tag, TagExt};
+use lofty::picture::{Picture, PictureType};
+use lofty::tag::{Accessor, ItemKey, Tag, TagExt};
+use options::{CueArgs, CueOptions, OutputArgs, OutputOptions};
+use output::{OutputFormat, OutputTemplate};
+use playlist::PlaylistFormat;
 use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
+use release::Release;
+use report::{ReportFormat, SplitReport};
 use std::cmp::{Ordering, PartialEq, PartialOrd};
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
 use std::fs;
-use std::fs::{DirEntry, File};
-use std::io::{BufReader, Read};
+use std::fs::DirEntry;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 use std::sync::RwLock;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+use tags::{SourceTags, TagPrecedence};
+
+/// Weight of the name similarity in percent, when ranking candidates for a broken audio file reference
+/// The remaining weight goes to the duration similarity
+const NAME_SCORE_WEIGHT: usize = 70;
+
+/// Score in percent above which a similar audio file is suggested as default answer
+const DEFAULT_ACCEPT_SCORE: usize = 70;
+
+/// Commands of cue-splatter, a run without a command splits, as cue-splatter did before it had commands
+const COMMAND_NAMES: [&str; 10] = [
+    "split", "info", "lint", "fix", "tag", "join", "watch", "update", "rollback", "help",
+];
 
 /// Split audio files based on cue sheets
 #[derive(Debug, FromArgs)]
+#[argh(
+    note = "Without a command, split is run, e.g. `cue-splatter album/` is `cue-splatter split album/`"
+)]
 struct CliArgs {
+    /// config file to use instead of the one in the XDG config folder, e.g. ~/.config/cue-splatter/config.toml
+    #[argh(option)]
+    config: Option<PathBuf>,
+
+    /// do not check for updates, also disabled by setting the CUE_SPLATTER_NO_UPDATE environment variable
+    #[argh(switch)]
+    no_update: bool,
+
+    /// only check whether a new release is available and exit
+    #[argh(switch)]
+    check_update: bool,
+
+    /// seconds to wait for the release server when checking for and downloading updates (default: 10)
+    #[argh(option)]
+    update_timeout: Option<u64>,
+
+    #[argh(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum CliCommand {
+    Split(SplitArgs),
+    Info(info::InfoArgs),
+    Lint(lint::LintArgs),
+    Fix(fix::FixArgs),
+    Tag(tag::TagArgs),
+    Join(join::JoinArgs),
+    Watch(watch::WatchArgs),
+    Update(updater::UpdateArgs),
+    Rollback(updater::RollbackArgs),
+}
+
+impl CliCommand {
+    /// The files and folders the command works on, their nearest directory config file applies to the run
+    fn input_paths(&self) -> Vec<PathBuf> {
+        let cue_file_or_folders = match self {
+            CliCommand::Split(split_args) => &split_args.cue_file_or_folders,
+            CliCommand::Info(info_args) => &info_args.cue_file_or_folders,
+            CliCommand::Lint(lint_args) => &lint_args.cue_file_or_folders,
+            CliCommand::Fix(fix_args) => &fix_args.cue_file_or_folders,
+            CliCommand::Tag(tag_args) => &tag_args.cue_file_or_folders,
+            CliCommand::Join(join_args) => return vec![join_args.folder.clone()],
+            CliCommand::Watch(watch_args) => return watch_args.folders.clone(),
+            CliCommand::Update(_) | CliCommand::Rollback(_) => return Vec::new(),
+        };
+        cue_file_or_folders.iter().map(PathBuf::from).collect()
+    }
+}
+
+/// Split audio files based on cue sheets
+#[derive(Debug, Default, FromArgs)]
+#[argh(subcommand, name = "split")]
+struct SplitArgs {
     /// only print the ffmpeg commands
     #[argh(switch)]
     dry_run: bool,
@@ -30,33 +134,302 @@ struct CliArgs {
     #[argh(switch)]
     transfer: bool,
 
+    /// keep the original files in place, even if transfer is enabled in a config file
+    #[argh(switch)]
+    no_transfer: bool,
+
     /// delete the original full-length audio file after successful splitting
     #[argh(switch)]
     delete: bool,
 
+    /// keep the original full-length audio file, even if delete is enabled in a config file
+    #[argh(switch)]
+    no_delete: bool,
+
+    /// prefix track numbers with the disc number on multi-disc releases, e.g. 101, 213
+    #[argh(switch)]
+    disc_prefix: bool,
+
+    /// do not prefix track numbers with the disc number, even if disc_prefix is enabled in a config file
+    #[argh(switch)]
+    no_disc_prefix: bool,
+
+    /// which tags win if both the cue sheet and the source audio file provide a value:
+    /// cue, source or fill-gaps (default: cue)
+    #[argh(option)]
+    tag_precedence: Option<TagPrecedence>,
+
+    /// do not embed cover art into the split tracks
+    #[argh(switch)]
+    no_cover: bool,
+
+    /// embed cover art, even if no_cover is enabled in a config file
+    #[argh(switch)]
+    cover: bool,
+
+    /// comma separated cover art file names to look for, without extension, `*` is a wildcard
+    /// default is "cover,folder,front"
+    #[argh(option)]
+    cover_names: Option<String>,
+
+    /// scale cover art down to fit into the given size in pixels and recompress it as JPEG
+    #[argh(option)]
+    cover_max_size: Option<u32>,
+
+    /// file name template of the tracks without extension, e.g. "{number} - {title}", placeholders:
+    /// {number}, {title}, {artist}, {album}, {album_artist} and {disc}, a `/` creates sub folders
+    #[argh(option)]
+    output_template: Option<OutputTemplate>,
+
+    /// convert the tracks instead of keeping the codec of the source audio file: flac, alac, mp3 or opus
+    #[argh(option)]
+    format: Option<OutputFormat>,
+
+    /// number of tracks split in parallel (default: number of CPU cores)
+    #[argh(option)]
+    jobs: Option<usize>,
+
+    /// measure track and album loudness and write ReplayGain (or R128 for Opus) tags
+    #[argh(switch)]
+    replaygain: bool,
+
+    /// do not write ReplayGain tags, even if replaygain is enabled in a config file
+    #[argh(switch)]
+    no_replaygain: bool,
+
+    /// embed the original cue sheet as CUESHEET tag into all tracks or only the first track:
+    /// all or first
+    #[argh(option)]
+    embed_cue: Option<CueEmbedMode>,
+
+    /// copy rip logs (.log files next to the cue file) into the output directory
+    #[argh(switch)]
+    copy_logs: bool,
+
+    /// do not copy rip logs, even if copy_logs is enabled in a config file
+    #[argh(switch)]
+    no_copy_logs: bool,
+
+    /// write a split cue sheet, referencing the split tracks, into the output directory
+    #[argh(switch)]
+    split_cue: bool,
+
+    /// do not write a split cue sheet, even if split_cue is enabled in a config file
+    #[argh(switch)]
+    no_split_cue: bool,
+
+    /// write a playlist per album into the output directory: m3u8, m3u, xspf or pls
+    #[argh(option)]
+    playlist: Option<PlaylistFormat>,
+
+    /// write a machine-readable report of the sheets, tracks, fixes, timings and failures of the run: json
+    #[argh(option)]
+    report: Option<ReportFormat>,
+
+    /// write the report into the given file instead of printing it, implies --report json
+    #[argh(option)]
+    report_file: Option<PathBuf>,
+
+    /// write accepted fixes of the audio file reference back into the cue file, the original is kept as .bak
+    #[argh(switch)]
+    save_fixes: bool,
+
+    /// do not write fixes back into the cue file, even if save_fixes is enabled in a config file
+    #[argh(switch)]
+    no_save_fixes: bool,
+
+    /// accept a similar audio file without asking, if its match score reaches the given percentage
+    #[argh(option)]
+    auto_fix_score: Option<usize>,
+
+    /// score in percent above which a similar audio file is the default answer (default: 70)
+    #[argh(option)]
+    accept_score: Option<usize>,
+
+    /// decode cue files with the given encoding instead of detecting it, e.g. windows-1251 or shift_jis
+    #[argh(option)]
+    cue_encoding: Option<CueEncoding>,
+
+    /// rewrite cue files as UTF-8 without or with byte order mark, the original is kept as .bak:
+    /// utf8 or utf8-bom
+    #[argh(option)]
+    convert_cue: Option<CueConversion>,
+
+    /// do not ask any questions, same as --non-interactive
+    #[argh(switch, short = 'y')]
+    yes: bool,
+
+    /// do not ask any questions, every question takes its default answer and invalid cue files are handled by
+    /// --on-invalid-cue, this is enabled automatically if stdin is not a terminal
+    #[argh(switch)]
+    non_interactive: bool,
+
+    /// ask questions, even if non_interactive is enabled in a config file
+    #[argh(switch)]
+    interactive: bool,
+
+    /// what to do with invalid cue files in non-interactive mode: skip or abort (default: skip)
+    #[argh(option)]
+    on_invalid_cue: Option<InvalidCuePolicy>,
+
     /// file or folder paths to parse
     /// default is "."
     #[argh(positional, greedy)]
     cue_file_or_folders: Vec<String>,
 }
 
+impl SplitArgs {
+    /// Takes the options of splitting which are not given on the command line from the config files
+    /// A switch enabled in a config file is disabled by its negating switch, e.g. --no-delete
+    /// The options shared with other commands are merged by `cue_options` and `output_options`
+    fn apply_config(&mut self, config: &Config) {
+        self.transfer = config::resolve_switch(self.transfer, self.no_transfer, config.transfer);
+        self.delete = config::resolve_switch(self.delete, self.no_delete, config.delete);
+        self.jobs = self.jobs.or(config.jobs);
+        self.replaygain =
+            config::resolve_switch(self.replaygain, self.no_replaygain, config.replaygain);
+        self.embed_cue = self.embed_cue.or(config.embed_cue);
+        self.copy_logs =
+            config::resolve_switch(self.copy_logs, self.no_copy_logs, config.copy_logs);
+        self.split_cue =
+            config::resolve_switch(self.split_cue, self.no_split_cue, config.split_cue);
+        self.playlist = self.playlist.or(config.playlist);
+        self.report = self.report.or(config.report);
+    }
+
+    fn cue_options(&self, config: &Config) -> CueOptions {
+        CueArgs {
+            cue_encoding: self.cue_encoding,
+            convert_cue: self.convert_cue,
+            save_fixes: self.save_fixes,
+            no_save_fixes: self.no_save_fixes,
+            auto_fix_score: self.auto_fix_score,
+            accept_score: self.accept_score,
+            yes: self.yes,
+            non_interactive: self.non_interactive,
+            interactive: self.interactive,
+            on_invalid_cue: self.on_invalid_cue,
+        }
+        .resolve(config)
+    }
+
+    fn output_options(&self, config: &Config) -> OutputOptions {
+        OutputArgs {
+            disc_prefix: self.disc_prefix,
+            no_disc_prefix: self.no_disc_prefix,
+            tag_precedence: self.tag_precedence,
+            no_cover: self.no_cover,
+            cover: self.cover,
+            cover_names: self.cover_names.clone(),
+            cover_max_size: self.cover_max_size,
+            output_template: self.output_template.clone(),
+            format: self.format,
+        }
+        .resolve(config)
+    }
+
+    fn report_format(&self) -> Option<ReportFormat> {
+        match self.report {
+            Some(report_format) => Some(report_format),
+            None => self.report_file.as_ref().map(|_| ReportFormat::Json),
+        }
+    }
+
+    /// The report is printed to stdout, so everything else has to go to stderr
+    fn reports_to_stdout(&self) -> bool {
+        self.report_format().is_some() && self.report_file.is_none()
+    }
+
+    /// Writes the report of the run, if one was requested
+    fn write_report(&self, split_report: &SplitReport) {
+        if let Some(report_format) = self.report_format() {
+            report::write_report(split_report, report_format, self.report_file.as_deref());
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct CueSheet {
     cue_file_path: PathBuf,
     audio_file_path: PathBuf,
     audio_file_name: String,
+    /// The file type of the FILE command, e.g. WAVE or MP3
+    audio_file_type: String,
+    /// The FILE commands in the order of the cue sheet, `audio_file_name` is the last one
+    audio_files: Vec<CueFile>,
+    /// The encoding the cue file was decoded with
+    cue_encoding: CueEncoding,
     output_dir: Option<PathBuf>,
     title: Option<String>,
+    performer: Option<String>,
+    rem_disc_number: Option<usize>,
+    rem_total_discs: Option<usize>,
+    disc_number: Option<usize>,
+    total_discs: Option<usize>,
+    source_tags: Option<SourceTags>,
+    cover: Option<Picture>,
+    /// The codec of the audio stream, as reported by ffprobe while verifying
+    audio_codec: Option<String>,
+    /// The duration of the audio file in seconds, probed while verifying, as it may be moved or deleted later
+    audio_duration: Option<f64>,
+    /// Problems found while verifying, e.g. a missing audio file
+    problems: Vec<String>,
+    /// Fixes applied while verifying, e.g. a corrected audio file reference
+    fixes: Vec<String>,
+    /// Album level commands we do not interpret, e.g. REM, CATALOG or SONGWRITER, kept verbatim
+    other_commands: Vec<String>,
+    /// Commands whose value could not be parsed, with the number of the track they belong to
+    /// They are dropped from the cue sheet, e.g. INDEX 01 00:0a:00
+    invalid_commands: Vec<(Option<u32>, String)>,
     tracks: Vec<Track>,
 }
 
+impl CueSheet {
+    fn audio_file_count(&self) -> usize {
+        self.audio_files.len()
+    }
+
+    /// Cue sheets with several audio files holding one track each, e.g. split cue sheets written by us
+    fn is_already_split(&self) -> bool {
+        self.audio_file_count() > 1
+            && self
+                .audio_files
+                .iter()
+                .all(|audio_file| audio_file.track_count == 1)
+    }
+}
+
+/// A FILE command of a cue sheet
+#[derive(Debug, Clone, PartialEq)]
+struct CueFile {
+    name: String,
+    /// The file type, e.g. WAVE or MP3
+    file_type: String,
+    /// The number of TRACK commands following the FILE command
+    track_count: usize,
+    /// Set if the FILE command is placed inside the previous track, before its INDEX 01
+    /// Some rippers write the pregap into the previous audio file this way
+    starts_inside_track: bool,
+}
+
 #[derive(Debug, Clone)]
 struct Track {
     number: u32,
+    /// The data type of the TRACK command, e.g. AUDIO
+    track_type: String,
+    flags: Vec<String>,
     title: Option<String>,
     artist: Option<String>,
+    pregap_start_time: Option<CueDuration>,
     start_time: Option<CueDuration>,
+    /// Sub indexes beyond INDEX 01, e.g. INDEX 02
+    other_indexes: Vec<(u32, CueDuration)>,
+    /// Track level commands we do not interpret, e.g. ISRC, PREGAP or REM, kept verbatim
+    other_commands: Vec<String>,
     output_file: Option<PathBuf>,
+    /// The arguments of the ffmpeg call that splits the track
+    ffmpeg_args: Vec<OsString>,
+    /// The ffmpeg call for display, e.g. in the dry run output and the report
     ffmpeg_command: Option<String>,
 }
 
@@ -75,56 +448,217 @@ enum UserDefaultAction {
 
 impl PartialOrd for CueDuration {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let self_frames = self.minutes * 60 * 75 + self.seconds * 75 + self.frames;
-        let other_frames = other.minutes * 60 * 75 + other.seconds * 75 + other.frames;
-        Some(self_frames.cmp(&other_frames))
+        Some(self.total_frames().cmp(&other.total_frames()))
     }
 }
 
-fn main() {
-    let mut cli_args: CliArgs = argh::from_env();
-    if cli_args.cue_file_or_folders.is_empty() {
-        cli_args.cue_file_or_folders.push(".".to_string());
+impl CueDuration {
+    /// Converts the duration to CDDA frames (1 frame = 1/75 second)
+    fn total_frames(&self) -> u32 {
+        self.minutes * 60 * 75 + self.seconds * 75 + self.frames
     }
 
-    // Check for updates, if available, update the binary and restart
-    updater::update();
+    fn from_frames(total_frames: u32) -> CueDuration {
+        CueDuration {
+            minutes: total_frames / (60 * 75),
+            seconds: total_frames / 75 % 60,
+            frames: total_frames % 75,
+        }
+    }
+}
 
-    check_tools(vec!["ffmpeg", "ffprobe"]);
+/// Formats the duration as used in cue sheets: mm:ss:ff
+impl Display for CueDuration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}",
+            self.minutes, self.seconds, self.frames
+        )
+    }
+}
 
-    // Find cue files
-    println!(
-        "🔍 Searching for cue files in {}",
-        cli_args.cue_file_or_folders.join(", ")
+fn main() {
+    let args = with_default_command(std::env::args().collect());
+    let cli_args = parse_cli_args(&args);
+    let input_paths = cli_args.command.input_paths();
+    let config = config::load(cli_args.config.as_deref(), &input_paths).unwrap_or_else(|err| {
+        red_ln!("❌ Invalid config: {}", err);
+        std::process::exit(exit_code::INVALID_INPUT);
+    });
+
+    let update_timeout = Duration::from_secs(
+        cli_args
+            .update_timeout
+            .or(config.update.timeout)
+            .unwrap_or(updater::DEFAULT_UPDATE_TIMEOUT_SECS),
     );
-    let cue_file_paths = cli_args
-        .cue_file_or_folders
-        .iter()
-        .flat_map(|input_path| find_cue_files(Path::new(input_path)))
-        .collect();
+    if cli_args.check_update {
+        updater::check_update(update_timeout, &config.update);
+        return;
+    }
 
-    // Show cue files to user
-    let_user_verify_cue_files(&cue_file_paths);
+    // Read-only commands do not update, so their output stays machine-readable and CI runs reproducible
+    match cli_args.command {
+        CliCommand::Join(join_args) => join::join(join_args),
+        CliCommand::Update(update_args) => updater::run(update_args, &config.update),
+        CliCommand::Rollback(rollback_args) => updater::rollback(rollback_args),
+        CliCommand::Info(info_args) => info::info(info_args, &config),
+        CliCommand::Lint(lint_args) => lint::lint(lint_args, &config),
+        mut command => {
+            // A report printed to stdout has to be the only output there, the update check included
+            let reports_to_stdout = match &mut command {
+                CliCommand::Split(split_args) => {
+                    split_args.apply_config(&config);
+                    split_args.reports_to_stdout()
+                }
+                CliCommand::Watch(_) => config.report.is_some(),
+                _ => false,
+            };
+            if reports_to_stdout {
+                report::redirect_stdout_to_stderr();
+            }
 
-    // Verify cue files
-    let cue_sheets: Vec<CueSheet> = cue_file_paths
-        .iter()
-        .flat_map(parse_cue_file)
-        .filter_map(|mut cue_sheet| {
-            let fix_action = verify_cue_files(&mut cue_sheet);
-            match fix_action {
-                CueFixAction::Deleted => return None,
-                CueFixAction::Modified => {}
-                CueFixAction::None => {}
+            // Check for updates, if available, update the binary and restart
+            let update_disabled = config.update.disabled.unwrap_or_default();
+            if !cli_args.no_update && !updater::is_disabled(update_disabled) {
+                updater::update(update_timeout, &config.update);
             }
 
-            augment_with_ffmpeg_commands(&mut cue_sheet);
-            augment_with_output_dir(&mut cue_sheet);
-            Some(cue_sheet)
-        })
+            match command {
+                CliCommand::Split(split_args) => split(split_args, &config),
+                CliCommand::Fix(fix_args) => fix::fix(fix_args, &config),
+                CliCommand::Tag(tag_args) => tag::tag(tag_args, &config),
+                CliCommand::Watch(watch_args) => {
+                    watch::watch(watch_args, &config, cli_args.config.as_deref())
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Inserts the split command if no command is given, e.g. `cue-splatter album/` runs `cue-splatter split album/`
+/// Only the global options may precede the command
+fn with_default_command(mut args: Vec<String>) -> Vec<String> {
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "--no-update" | "--check-update" => index += 1,
+            "--config" | "--update-timeout" => index += 2,
+            _ => break,
+        }
+    }
+
+    let has_command = args
+        .get(index)
+        .is_some_and(|arg| COMMAND_NAMES.contains(&arg.as_str()) || arg == "--help" || arg == "-h");
+    if !has_command {
+        args.insert(index.min(args.len()), "split".to_string());
+    }
+    args
+}
+
+/// Parses the command line arguments like `argh::from_env`, help and errors exit the process
+fn parse_cli_args(args: &[String]) -> CliArgs {
+    let command_name = Path::new(&args[0])
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_else(|| args[0].clone());
+    let args: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+
+    CliArgs::from_args(&[&command_name], &args).unwrap_or_else(|early_exit| {
+        match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                std::process::exit(0);
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {} --help for more information.",
+                    early_exit.output, command_name
+                );
+                std::process::exit(exit_code::INVALID_INPUT);
+            }
+        }
+    })
+}
+
+/// Never wait for answers that can not be given, e.g. in cron jobs or containers
+fn is_non_interactive(yes: bool, non_interactive: bool) -> bool {
+    yes || non_interactive || !std::io::stdin().is_terminal()
+}
+
+/// Splits the audio files of the cue sheets into tracks, options not given on the command line are taken from the config
+fn split(split_args: SplitArgs, config: &Config) {
+    if let Some(command) = find_missing_tool(&["ffmpeg", "ffprobe"]) {
+        eprintln!("Command {} not found in path", command);
+        split_args.write_report(&SplitReport {
+            started_at: SystemTime::now(),
+            dry_run: split_args.dry_run,
+            exit_code: exit_code::MISSING_TOOLS,
+            releases: &[],
+            skipped_cue_files: &[],
+            track_results: &[],
+            timings: &[],
+        });
+        std::process::exit(exit_code::MISSING_TOOLS);
+    }
+
+    let cue_file_paths = find_all_cue_files(&split_args.cue_file_or_folders);
+    let exit_status = split_cue_files(&split_args, config, &cue_file_paths);
+    print_goodbye(exit_status);
+    if exit_status != exit_code::SUCCESS {
+        std::process::exit(exit_status);
+    }
+}
+
+/// Runs the split pipeline on the found cue files, this is shared by the split and watch commands
+/// Returns the exit code of the run
+fn split_cue_files(split_args: &SplitArgs, config: &Config, cue_file_paths: &Vec<PathBuf>) -> i32 {
+    let started_at = SystemTime::now();
+    let run_start_time = Instant::now();
+    let mut timings: Vec<(&'static str, Duration)> = Vec::new();
+
+    let loaded_cue_sheets = load_cue_sheets(
+        cue_file_paths,
+        &split_args.cue_options(config),
+        Some("Proceed with splitting?"),
+    );
+    let skipped_cue_files = loaded_cue_sheets.skipped_cue_files;
+    timings.push(("load", run_start_time.elapsed()));
+
+    // Nothing is split after an abort, the report lists the cue files skipped until then
+    if let Some(abort_exit_code) = loaded_cue_sheets.abort_exit_code {
+        timings.push(("total", run_start_time.elapsed()));
+        split_args.write_report(&SplitReport {
+            started_at,
+            dry_run: split_args.dry_run,
+            exit_code: abort_exit_code,
+            releases: &[],
+            skipped_cue_files: &skipped_cue_files,
+            track_results: &[],
+            timings: &timings,
+        });
+        return abort_exit_code;
+    }
+
+    let phase_start_time = Instant::now();
+    let output_options = split_args.output_options(config);
+    let mut releases = prepare_releases(loaded_cue_sheets.cue_sheets, &output_options);
+    for cue_sheet in releases.iter_mut().flat_map(|release| &mut release.discs) {
+        augment_with_ffmpeg_commands(cue_sheet, &output_options);
+    }
+    let cue_sheets: Vec<CueSheet> = releases
+        .iter()
+        .flat_map(|release| release.discs.clone())
         .collect();
+    timings.push(("prepare", phase_start_time.elapsed()));
+
+    let mut track_results = Vec::new();
+    let mut post_processing_failed = false;
 
-    if cli_args.dry_run {
+    if split_args.dry_run {
         println!("🚀 Dry run, only printing ffmpeg commands");
         for cue_sheet in &cue_sheets {
             for track in &cue_sheet.tracks {
@@ -135,82 +669,335 @@ fn main() {
         println!();
 
         // Split tracks and write metadata
-        let failed_tracks = run_ffmpeg_split_commands(&cue_sheets);
-        if failed_tracks.is_empty() {
+        let phase_start_time = Instant::now();
+        track_results = run_ffmpeg_split_commands(&releases, &output_options, split_args.jobs);
+        timings.push(("split", phase_start_time.elapsed()));
+
+        let phase_start_time = Instant::now();
+        if track_results
+            .iter()
+            .all(|track_result| track_result.error.is_none())
+        {
             println!("🎉 All tracks have been splitted");
 
+            // Analyze loudness before the source audio files are moved or deleted
+            if split_args.replaygain {
+                replaygain::apply_replaygain(&cue_sheets);
+            }
+
+            // Keep a record of the original track layout, once the source audio file is gone
+            if let Some(embed_mode) = split_args.embed_cue {
+                embed::embed_cue_sheets(&cue_sheets, embed_mode);
+            }
+            if split_args.copy_logs {
+                embed::copy_rip_logs(&cue_sheets);
+            }
+            if split_args.split_cue {
+                cue_writer::write_split_cue_sheets(&cue_sheets);
+            }
+            if let Some(playlist_format) = split_args.playlist {
+                playlist::write_playlists(&releases, playlist_format);
+            }
+
             // Moves the audio file to the output dir
-            if cli_args.transfer {
-                move_input_files(cue_sheets.clone());
+            if split_args.transfer && !move_input_files(cue_sheets.clone()) {
+                post_processing_failed = true;
             }
 
             // Delete the original full-length audio file
-            if cli_args.delete {
+            if split_args.delete {
                 delete_original_audio_files(cue_sheets);
             }
+            timings.push(("post_processing", phase_start_time.elapsed()));
         } else {
-            report_failed_tracks(failed_tracks);
+            report_failed_tracks(&track_results);
+        }
+    }
+
+    let failed_track_count = track_results
+        .iter()
+        .filter(|track_result| track_result.error.is_some())
+        .count();
+    let exit_status = if post_processing_failed {
+        exit_code::ERROR
+    } else {
+        exit_code::from_track_counts(
+            failed_track_count,
+            track_results.len(),
+            cue_file_paths.is_empty() || !skipped_cue_files.is_empty(),
+        )
+    };
+
+    // The report is printed after the progress output, so it is not interrupted by it
+    timings.push(("total", run_start_time.elapsed()));
+    split_args.write_report(&SplitReport {
+        started_at,
+        dry_run: split_args.dry_run,
+        exit_code: exit_status,
+        releases: &releases,
+        skipped_cue_files: &skipped_cue_files,
+        track_results: &track_results,
+        timings: &timings,
+    });
+
+    exit_status
+}
+
+/// Prints the last message of a run, naming the exit code if the run was not successful
+fn print_goodbye(exit_status: i32) {
+    if exit_status == exit_code::SUCCESS {
+        println!("🚪 Everything is done, bye bye");
+    } else {
+        yellow_ln!(
+            "🚪 Done, exiting with code {} ({}), bye bye",
+            exit_status,
+            exit_code::describe(exit_status)
+        );
+    }
+}
+
+/// Finds the cue files in the given files and folders, the current folder is searched if none are given
+fn find_all_cue_files(cue_file_or_folders: &[String]) -> Vec<PathBuf> {
+    let cue_file_or_folders = if cue_file_or_folders.is_empty() {
+        vec![".".to_string()]
+    } else {
+        cue_file_or_folders.to_vec()
+    };
+
+    println!(
+        "🔍 Searching for cue files in {}",
+        cue_file_or_folders.join(", ")
+    );
+    cue_file_or_folders
+        .iter()
+        .flat_map(|input_path| find_cue_files(Path::new(input_path)))
+        .collect()
+}
+
+/// Parses and verifies the found cue files, this is the pipeline shared by the commands
+/// If a question is given, the user has to confirm the found cue files first
+/// Cue sheets which are invalid and were not fixed are left out, they are returned as skipped cue files
+/// Loading stops if the user quits or an invalid cue file aborts the run
+fn load_cue_sheets(
+    cue_file_paths: &Vec<PathBuf>,
+    cue_options: &CueOptions,
+    question: Option<&str>,
+) -> LoadedCueSheets {
+    let mut cue_sheets = Vec::new();
+    let mut skipped_cue_files = Vec::new();
+
+    // Show cue files to user
+    if !let_user_verify_cue_files(cue_file_paths, question, cue_options.non_interactive) {
+        return LoadedCueSheets {
+            cue_sheets,
+            skipped_cue_files,
+            abort_exit_code: Some(exit_code::USER_ABORT),
+        };
+    }
+    for cue_file_path in cue_file_paths {
+        let Some(mut cue_sheet) = parse_cue_file(cue_file_path, cue_options.cue_encoding) else {
+            skipped_cue_files.push(SkippedCueFile {
+                cue_file_path: cue_file_path.clone(),
+                reason: "The cue file could not be read".to_string(),
+            });
+            continue;
+        };
+        if let Some(conversion) = cue_options.convert_cue {
+            convert_cue_file(&mut cue_sheet, cue_options.cue_encoding, conversion);
         }
+
+        let fix_action = verify_cue_files(&mut cue_sheet, cue_options);
+        let reason = match fix_action {
+            CueFixAction::Deleted => "The cue file was deleted".to_string(),
+            CueFixAction::Skipped => cue_sheet
+                .problems
+                .last()
+                .cloned()
+                .unwrap_or_else(|| "The cue file is invalid".to_string()),
+            CueFixAction::Aborted(abort_exit_code) => {
+                skipped_cue_files.push(SkippedCueFile {
+                    cue_file_path: cue_file_path.clone(),
+                    reason: format!(
+                        "Stopped the run: {}",
+                        cue_sheet
+                            .problems
+                            .last()
+                            .map_or("The cue file is invalid", String::as_str)
+                    ),
+                });
+                return LoadedCueSheets {
+                    cue_sheets,
+                    skipped_cue_files,
+                    abort_exit_code: Some(abort_exit_code),
+                };
+            }
+            CueFixAction::Modified | CueFixAction::None => {
+                cue_sheets.push(cue_sheet);
+                continue;
+            }
+        };
+        skipped_cue_files.push(SkippedCueFile {
+            cue_file_path: cue_file_path.clone(),
+            reason,
+        });
+    }
+    LoadedCueSheets {
+        cue_sheets,
+        skipped_cue_files,
+        abort_exit_code: None,
     }
+}
+
+/// The cue sheets loaded by the pipeline
+struct LoadedCueSheets {
+    /// Valid cue sheets, possibly fixed while verifying
+    cue_sheets: Vec<CueSheet>,
+    /// Cue files which could not be read, or were invalid and not fixed
+    skipped_cue_files: Vec<SkippedCueFile>,
+    /// The exit code, if the user quit or --on-invalid-cue abort stopped the run
+    abort_exit_code: Option<i32>,
+}
 
-    println!("🚪 Everything is done, bye bye");
+/// Groups cue sheets into releases and plans the split tracks
+/// This assigns disc numbers to multi-disc releases and the output file of each track, and reads the source tags
+/// and cover art. The source audio file is not needed, so already split releases can be planned as well.
+fn prepare_releases(cue_sheets: Vec<CueSheet>, output_options: &OutputOptions) -> Vec<Release> {
+    let mut releases: Vec<Release> = release::group_into_releases(cue_sheets);
+    for release in &mut releases {
+        let track_number_width = track_number_width(release);
+        for cue_sheet in &mut release.discs {
+            augment_with_output_files(cue_sheet, track_number_width, output_options);
+            augment_with_output_dir(cue_sheet);
+            augment_with_source_tags(cue_sheet);
+            augment_with_cover(cue_sheet, output_options);
+        }
+    }
+    releases
 }
 
+/// The output dir holds all tracks of the cue sheet, an output template may put them into different sub folders
 fn augment_with_output_dir(cue_sheet: &mut CueSheet) {
-    let first_track = cue_sheet.tracks.first().unwrap();
-    let output_dir = first_track.output_file.as_ref().unwrap().parent().unwrap();
-    cue_sheet.output_dir = Some(output_dir.to_path_buf());
+    let track_dirs: Vec<&Path> = cue_sheet
+        .tracks
+        .iter()
+        .map(|track| track.output_file.as_ref().unwrap().parent().unwrap())
+        .collect();
+    cue_sheet.output_dir = Some(playlist::common_ancestor(&track_dirs));
+}
+
+/// Reads the tags of the source audio file once, they are copied into every track
+fn augment_with_source_tags(cue_sheet: &mut CueSheet) {
+    cue_sheet.source_tags = tags::read_source_tags(&cue_sheet.audio_file_path);
+}
+
+/// Applies the cover options to the pictures of the source audio file and looks for cover art next to it
+/// A cover art file is only loaded, if the source audio file has no front cover, as that is preferred
+fn augment_with_cover(cue_sheet: &mut CueSheet, output_options: &OutputOptions) {
+    if let Some(ref mut source_tags) = cue_sheet.source_tags {
+        if output_options.no_cover {
+            source_tags.pictures.clear();
+        } else if let Some(max_size) = output_options.cover_max_size {
+            source_tags.pictures = source_tags
+                .pictures
+                .iter()
+                .map(|picture| cover::resize_picture(picture, max_size))
+                .collect();
+        }
+    }
+    if output_options.no_cover {
+        return;
+    }
+
+    let has_front_cover = cue_sheet.source_tags.as_ref().is_some_and(|source_tags| {
+        source_tags
+            .pictures
+            .iter()
+            .any(|picture| picture.pic_type() == PictureType::CoverFront)
+    });
+    if has_front_cover {
+        return;
+    }
+
+    let audio_dir = cue_sheet.audio_file_path.parent().unwrap();
+    if let Some(cover_file) = cover::find_cover_file(audio_dir, &output_options.cover_names) {
+        println!("🖼️ Found cover art: {}", cover_file.display());
+        cue_sheet.cover = cover::load_cover(&cover_file, output_options.cover_max_size);
+    }
 }
 
-fn write_audio_metadata_to_track(cue_sheet: &CueSheet, track: &Track) -> (bool, String) {
+fn write_audio_metadata_to_track(
+    release: &Release,
+    cue_sheet: &CueSheet,
+    track: &Track,
+    output_options: &OutputOptions,
+) -> (bool, String) {
     let output_file_path = track.output_file.as_ref().unwrap();
     let tagged_file = lofty::read_from_path(output_file_path);
     if tagged_file.is_err() {
         return (
             false,
             format!(
-                "❌ Could not read file {}\n{}",
+                "Could not read file {}\n{}",
                 output_file_path.display(),
                 tagged_file.err().unwrap()
             ),
         );
     }
-    let mut tagged_file = tagged_file.unwrap();
-
-    let primary_tag = match tagged_file.primary_tag_mut() {
-        Some(primary_tag) => primary_tag,
-        None => {
-            if let Some(first_tag) = tagged_file.first_tag_mut() {
-                first_tag
-            } else {
-                let tag_type = tagged_file.primary_tag_type();
-                tagged_file.insert_tag(Tag::new(tag_type));
-                tagged_file.primary_tag_mut().unwrap()
-            }
-        }
-    };
-
-    primary_tag.set_track(track.number);
-    if let Some(ref album) = cue_sheet.title {
-        primary_tag.set_album(album.to_string());
+    let tagged_file = tagged_file.unwrap();
+
+    // Build a tag from the cue sheet data and merge it with the tags of the source file
+    let mut cue_tag = Tag::new(tagged_file.primary_tag_type());
+    cue_tag.set_track(track.number);
+    cue_tag.set_track_total(cue_sheet.tracks.len() as u32);
+    if let Some(disc_number) = cue_sheet.disc_number {
+        cue_tag.set_disk(disc_number as u32);
+    }
+    if let Some(total_discs) = cue_sheet.total_discs {
+        cue_tag.set_disk_total(total_discs as u32);
+    }
+    if let Some(ref album) = release.title {
+        cue_tag.set_album(album.to_string());
+    }
+    if let Some(ref album_artist) = release.performer {
+        cue_tag.insert_text(ItemKey::AlbumArtist, album_artist.to_string());
     }
     if let Some(ref title) = track.title {
-        primary_tag.set_title(title.to_string());
+        cue_tag.set_title(title.to_string());
     }
     if let Some(ref artist) = track.artist {
-        primary_tag.set_artist(artist.to_string());
+        cue_tag.set_artist(artist.to_string());
     }
 
-    primary_tag
-        .save_to_path(output_file_path, WriteOptions::default())
-        .unwrap();
+    let mut merged_tag = tags::merge_tags(
+        cue_sheet.source_tags.as_ref(),
+        &cue_tag,
+        output_options.tag_precedence,
+    );
+
+    // The cover art file is only loaded, if the source file has no front cover
+    if let Some(ref cover) = cue_sheet.cover {
+        merged_tag.push_picture(cover.clone());
+    }
+
+    if let Err(err) = merged_tag.save_to_path(output_file_path, WriteOptions::default()) {
+        return (
+            false,
+            format!(
+                "Could not write tags to file {}\n{}",
+                output_file_path.display(),
+                err
+            ),
+        );
+    }
 
     (true, "".to_string())
 }
 
 /// Moves input audio and cue file to the output directory
-fn move_input_files(cue_sheets: Vec<CueSheet>) {
+/// Returns false if a file could not be moved, the cue file stays with its audio file in that case
+fn move_input_files(cue_sheets: Vec<CueSheet>) -> bool {
     println!("⏩  Moving audio files to output directories");
+    let mut all_moved = true;
     for cue_file in cue_sheets {
         let output_dir = cue_file.output_dir.unwrap();
 
@@ -221,7 +1008,16 @@ fn move_input_files(cue_sheets: Vec<CueSheet>) {
         } else {
             let audio_file_name = cue_file.audio_file_path.file_name().unwrap();
             let output_audio_file = output_dir.join(audio_file_name);
-            fs::rename(&cue_file.audio_file_path, &output_audio_file).unwrap();
+            if let Err(err) = fs::rename(&cue_file.audio_file_path, &output_audio_file) {
+                red_ln!(
+                    "❌ Could not move the audio file {} to {}: {}",
+                    cue_file.audio_file_path.display(),
+                    output_audio_file.display(),
+                    err
+                );
+                all_moved = false;
+                continue;
+            }
             println!("📦 Moved audio file to: {}", output_audio_file.display());
         }
 
@@ -232,38 +1028,78 @@ fn move_input_files(cue_sheets: Vec<CueSheet>) {
         } else {
             let cue_file_name = cue_file.cue_file_path.file_name().unwrap();
             let output_cue_file = output_dir.join(cue_file_name);
-            fs::rename(&cue_file.cue_file_path, &output_cue_file).unwrap();
+            if let Err(err) = fs::rename(&cue_file.cue_file_path, &output_cue_file) {
+                red_ln!(
+                    "❌ Could not move the cue file {} to {}: {}",
+                    cue_file.cue_file_path.display(),
+                    output_cue_file.display(),
+                    err
+                );
+                all_moved = false;
+                continue;
+            }
             println!("📦 Moved cue file to: {}", output_cue_file.display());
         }
     }
-    println!("🎉 All files have been moved");
+    if all_moved {
+        println!("🎉 All files have been moved");
+    }
+    all_moved
 }
 
-fn let_user_verify_cue_files(cue_files: &Vec<PathBuf>) {
+/// Returns false if the user declined to proceed
+fn let_user_verify_cue_files(
+    cue_files: &Vec<PathBuf>,
+    question: Option<&str>,
+    non_interactive: bool,
+) -> bool {
     println!("Found {} cue file(s):", cue_files.len());
     for cue_file in cue_files {
         println!("\t{}", cue_file.display());
     }
     println!();
-    blue_ln!("Proceed with splitting? (Y/n): ");
+    let Some(question) = question else {
+        return true;
+    };
+    if non_interactive {
+        return true;
+    }
+    blue_ln!("{} (Y/n): ", question);
 
     // proceed if user enters y|Y or just hits ENTER
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
+    let input = read_user_input().unwrap_or_default();
     if !input.trim().is_empty() && !input.trim().eq_ignore_ascii_case("y") {
         println!("🚪 Exiting ...");
-        std::process::exit(0);
+        return false;
     }
+    true
+}
+
+/// The outcome of splitting a single track
+#[derive(Debug, Clone)]
+struct TrackResult {
+    track: Track,
+    /// Time spent splitting and tagging the track
+    elapsed: Duration,
+    /// The error message, if the track failed
+    error: Option<String>,
 }
 
-fn report_failed_tracks(failed_tracks: Vec<(Track, String)>) {
+fn report_failed_tracks(track_results: &[TrackResult]) {
     println!("❌ Failed to split the following tracks:");
     println!();
-    for (track, error_message) in failed_tracks {
-        println!("\tArtist: {:?}", track.artist);
-        println!("\tTitle: {:?}", track.title);
-        println!("\tCommand: {}", track.ffmpeg_command.unwrap());
-        println!("\tOutput file: {}", track.output_file.unwrap().display());
+    for track_result in track_results {
+        let Some(ref error_message) = track_result.error else {
+            continue;
+        };
+        let track = &track_result.track;
+        println!("\tArtist: {}", track.artist.as_deref().unwrap_or("-"));
+        println!("\tTitle: {}", track.title.as_deref().unwrap_or("-"));
+        println!("\tCommand: {}", track.ffmpeg_command.as_ref().unwrap());
+        println!(
+            "\tOutput file: {}",
+            track.output_file.as_ref().unwrap().display()
+        );
         println!("\tError message: {}", error_message);
         println!();
         println!();
@@ -271,9 +1107,16 @@ fn report_failed_tracks(failed_tracks: Vec<(Track, String)>) {
     }
 }
 
-fn run_ffmpeg_split_commands(cue_sheets: &[CueSheet]) -> Vec<(Track, String)> {
-    let total_track_count = cue_sheets
+/// Splits and tags all tracks of the releases, returns the result of each track
+/// Without a number of jobs, as many tracks as CPU cores are split in parallel
+fn run_ffmpeg_split_commands(
+    releases: &[Release],
+    output_options: &OutputOptions,
+    jobs: Option<usize>,
+) -> Vec<TrackResult> {
+    let total_track_count = releases
         .iter()
+        .flat_map(|release| &release.discs)
         .map(|cue_sheet| cue_sheet.tracks.len() as u64)
         .sum();
 
@@ -289,50 +1132,84 @@ fn run_ffmpeg_split_commands(cue_sheets: &[CueSheet]) -> Vec<(Track, String)> {
     );
     mp_progress_bar.enable_steady_tick(Duration::from_millis(100));
 
-    // Collect failed tracks in a vec
-    let failed_tracks: RwLock<Vec<(Track, String)>> = RwLock::new(Vec::new());
+    // Collect the track results in a vec
+    let track_results: RwLock<Vec<TrackResult>> = RwLock::new(Vec::new());
 
-    cue_sheets
-        .iter()
-        .flat_map(|cue_sheet| cue_sheet.tracks.iter().map(move |track| (cue_sheet, track)))
-        .par_bridge()
-        .for_each(|(cue_sheet, track)| {
-            split_track(&multi_progress_bar, &failed_tracks, cue_sheet, track);
-            mp_progress_bar.inc(1);
-        });
+    let split_all_tracks = || {
+        releases
+            .iter()
+            .flat_map(|release| {
+                release
+                    .discs
+                    .iter()
+                    .map(move |cue_sheet| (release, cue_sheet))
+            })
+            .flat_map(|(release, cue_sheet)| {
+                cue_sheet
+                    .tracks
+                    .iter()
+                    .map(move |track| (release, cue_sheet, track))
+            })
+            .par_bridge()
+            .for_each(|(release, cue_sheet, track)| {
+                split_track(
+                    &multi_progress_bar,
+                    &track_results,
+                    release,
+                    cue_sheet,
+                    track,
+                    output_options,
+                );
+                mp_progress_bar.inc(1);
+            });
+    };
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or_default())
+        .build()
+    {
+        Ok(thread_pool) => thread_pool.install(split_all_tracks),
+        Err(err) => {
+            yellow_ln!(
+                "⚠️ Could not start {} jobs, splitting with one per CPU core: {}",
+                jobs.unwrap_or_default(),
+                err
+            );
+            split_all_tracks();
+        }
+    }
 
     mp_progress_bar.finish_and_clear();
 
-    failed_tracks.into_inner().unwrap()
+    track_results.into_inner().unwrap()
 }
 
 fn split_track(
     multi_progress_bar: &MultiProgress,
-    failed_tracks: &RwLock<Vec<(Track, String)>>,
+    track_results: &RwLock<Vec<TrackResult>>,
+    release: &Release,
     cue_sheet: &CueSheet,
     track: &Track,
+    output_options: &OutputOptions,
 ) {
     let split_command_bar = create_spinner(multi_progress_bar, track);
+    let start_time = Instant::now();
 
     // Run ffmpeg split command
     let (is_ok, error_message) = run_ffmpeg_split_command(track);
 
-    if is_ok {
+    let error = if is_ok {
         // Write metadata to track
-        let (is_ok, error_message) = write_audio_metadata_to_track(cue_sheet, track);
-
-        if !is_ok {
-            failed_tracks
-                .write()
-                .unwrap()
-                .push((track.clone(), error_message));
-        }
+        let (is_ok, error_message) =
+            write_audio_metadata_to_track(release, cue_sheet, track, output_options);
+        (!is_ok).then_some(error_message)
     } else {
-        failed_tracks
-            .write()
-            .unwrap()
-            .push((track.clone(), error_message));
-    }
+        Some(error_message)
+    };
+    track_results.write().unwrap().push(TrackResult {
+        track: track.clone(),
+        elapsed: start_time.elapsed(),
+        error,
+    });
 
     split_command_bar.finish_and_clear();
 }
@@ -360,18 +1237,20 @@ fn create_spinner(multi_progress_bar: &MultiProgress, track: &Track) -> Progress
 /// Runs the ffmpeg command to split the audio file
 /// Returns if the command was successful and the error message
 fn run_ffmpeg_split_command(track: &Track) -> (bool, String) {
-    let ffmpeg_command = track.ffmpeg_command.as_ref().unwrap();
-
     // Make sure all sub dirs exist
     let output_file = track.output_file.as_ref().unwrap();
     let output_dir = output_file.parent().unwrap();
-    fs::create_dir_all(output_dir).unwrap();
+    if let Err(err) = fs::create_dir_all(output_dir) {
+        return (
+            false,
+            format!("Could not create {}: {}", output_dir.display(), err),
+        );
+    }
 
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(ffmpeg_command)
-        .output()
-        .expect("Failed to execute command");
+    let output = match Command::new("ffmpeg").args(&track.ffmpeg_args).output() {
+        Ok(output) => output,
+        Err(err) => return (false, format!("Could not run ffmpeg: {}", err)),
+    };
 
     if output.status.success() {
         (true, "".to_string())
@@ -382,17 +1261,60 @@ fn run_ffmpeg_split_command(track: &Track) -> (bool, String) {
     }
 }
 
-fn verify_cue_files(cue_sheet: &mut CueSheet) -> CueFixAction {
+fn verify_cue_files(cue_sheet: &mut CueSheet, cue_options: &CueOptions) -> CueFixAction {
     println!("🔍 Verifying cue file",);
 
+    // Cue sheets with one file per track, e.g. split cue sheets written by us, have nothing to split
+    if cue_sheet.is_already_split() {
+        println!(
+            "⏭️ The cue sheet references {} audio files with one track each, it seems to be already split, skipping",
+            cue_sheet.audio_file_count()
+        );
+        cue_sheet.problems.push(format!(
+            "The cue sheet references {} audio files with one track each, it seems to be already split",
+            cue_sheet.audio_file_count()
+        ));
+        return CueFixAction::Skipped;
+    }
+
+    // The tracks of several audio files would be cut from the last one only
+    if cue_sheet.audio_file_count() > 1 {
+        eprintln!(
+            "❌ The cue sheet references {} audio files, only cue sheets with a single audio file can be split",
+            cue_sheet.audio_file_count()
+        );
+        cue_sheet.problems.push(format!(
+            "The cue sheet references {} audio files, only a single audio file can be split",
+            cue_sheet.audio_file_count()
+        ));
+        let user_action = ask_user_for_fix(cue_sheet, cue_options);
+        if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
+            return edit_action;
+        }
+    }
+
+    // Without the audio file there is nothing to probe, the tracks are verified from the cue sheet alone
+    let audio_file_gone =
+        cue_options.allow_missing_audio_file && !cue_sheet.audio_file_path.exists();
+    if audio_file_gone {
+        println!(
+            "ℹ️ The referenced audio file is gone, continuing with the cue sheet: {}",
+            cue_sheet.audio_file_path.display()
+        );
+    }
+
     // Verify that the audio file name exists
-    if !cue_sheet.audio_file_path.exists() {
+    if !audio_file_gone && !cue_sheet.audio_file_path.exists() {
         yellow_ln!(
             "❌ The referenced audio file of the cue sheet was not found: {:?}",
             cue_sheet.audio_file_path
         );
-        let user_action = fix_cue_sheet_audio_file_reference(cue_sheet);
-        if let Some(edit_action) = handle_user_action(cue_sheet, user_action) {
+        cue_sheet.problems.push(format!(
+            "The referenced audio file was not found: {}",
+            cue_sheet.audio_file_path.display()
+        ));
+        let user_action = fix_cue_sheet_audio_file_reference(cue_sheet, cue_options);
+        if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
             return edit_action;
         }
     };
@@ -403,42 +1325,67 @@ fn verify_cue_files(cue_sheet: &mut CueSheet) -> CueFixAction {
             "❌ No tracks found in cue file {}",
             cue_sheet.audio_file_name
         );
-        let user_action = ask_user_for_fix(cue_sheet);
-        if let Some(edit_action) = handle_user_action(cue_sheet, user_action) {
+        cue_sheet.problems.push("No tracks found".to_string());
+        let user_action = ask_user_for_fix(cue_sheet, cue_options);
+        if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
             return edit_action;
         }
     }
 
-    // Verify that ffmpeg can process the input file
-    // Example: ffprobe -v error -select_streams a:0 -count_packets -show_entries stream=codec_type,codec_name -of csv=p=0 input_file.mp3
-    let ffprobe_cmd = format!(
-        "ffprobe -v error -select_streams a:0 -count_packets -show_entries stream=codec_type,codec_name -of csv=p=0 \"{}\"",
-        cue_sheet.audio_file_path.display()
-    );
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(ffprobe_cmd)
-        .output()
-        .expect("Failed to execute command");
-    if !output.status.success() {
-        eprintln!(
-            "❌ ffmpeg failed to process file, most likely the file is corrupt or codec is not supported: {}\nstdout: {}\nstderr: {}",
-            cue_sheet.audio_file_name,
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        );
-        let user_action = ask_user_for_fix(cue_sheet);
-        if let Some(edit_action) = handle_user_action(cue_sheet, user_action) {
+    // Verify that all commands could be parsed
+    for (track_number, command) in cue_sheet.invalid_commands.clone() {
+        let location = match track_number {
+            Some(track_number) => format!("of track {}", track_number),
+            None => "of the album".to_string(),
+        };
+        eprintln!("❌ Invalid command {}: {}", location, command);
+        cue_sheet
+            .problems
+            .push(format!("Invalid command {}: {}", location, command));
+        let user_action = ask_user_for_fix(cue_sheet, cue_options);
+        if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
             return edit_action;
         }
     }
 
+    // Verify that ffmpeg can process the input file
+    let probe_result = match audio_file_gone {
+        true => Ok(None),
+        false => probe_audio_stream(&cue_sheet.audio_file_path),
+    };
+    match probe_result {
+        Ok(audio_codec) => {
+            cue_sheet.audio_codec = audio_codec;
+            if !audio_file_gone {
+                cue_sheet.audio_duration =
+                    audio_duration::read_audio_duration(&cue_sheet.audio_file_path);
+            }
+        }
+        Err(ffprobe_output) => {
+            eprintln!(
+                "❌ ffmpeg failed to process file, most likely the file is corrupt or codec is not supported: {}\n{}",
+                cue_sheet.audio_file_name, ffprobe_output
+            );
+            cue_sheet.problems.push(format!(
+                "ffmpeg failed to process the audio file: {}",
+                ffprobe_output.trim()
+            ));
+            let user_action = ask_user_for_fix(cue_sheet, cue_options);
+            if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
+                return edit_action;
+            }
+        }
+    }
+
     // Verify that all tracks have a start time
     for track in cue_sheet.tracks.clone() {
         if track.start_time.is_none() {
             eprintln!("❌ No start time found for track {}", track.number);
-            let user_action = ask_user_for_fix(cue_sheet);
-            if let Some(edit_action) = handle_user_action(cue_sheet, user_action) {
+            cue_sheet
+                .problems
+                .push(format!("No start time found for track {}", track.number));
+            let user_action = ask_user_for_fix(cue_sheet, cue_options);
+            if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
                 return edit_action;
             }
         }
@@ -454,8 +1401,14 @@ fn verify_cue_files(cue_sheet: &mut CueSheet) -> CueFixAction {
                     "❌ Most likely the cue file is not valid: \"{}\"",
                     cue_sheet.cue_file_path.display()
                 );
-                let user_action = ask_user_for_fix(cue_sheet);
-                if let Some(edit_action) = handle_user_action(cue_sheet, user_action) {
+                cue_sheet.problems.push(format!(
+                    "Track {} starts at {}, but the previous track starts at {}",
+                    track.number,
+                    track.start_time.unwrap(),
+                    previous_track.start_time.unwrap()
+                ));
+                let user_action = ask_user_for_fix(cue_sheet, cue_options);
+                if let Some(edit_action) = handle_user_action(cue_sheet, user_action, cue_options) {
                     return edit_action;
                 }
             }
@@ -468,98 +1421,287 @@ fn verify_cue_files(cue_sheet: &mut CueSheet) -> CueFixAction {
     CueFixAction::None
 }
 
-fn handle_user_action(cue_sheet: &mut CueSheet, user_action: CueFixAction) -> Option<CueFixAction> {
+/// Checks that ffmpeg can decode the audio stream of the file and returns its codec, or the ffprobe output otherwise
+/// Example: ffprobe -v error -select_streams a:0 -count_packets -show_entries stream=codec_type,codec_name -of csv=p=0 input_file.mp3
+fn probe_audio_stream(audio_file_path: &Path) -> Result<Option<String>, String> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "a:0",
+            "-count_packets",
+            "-show_entries",
+            "stream=codec_type,codec_name",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(audio_file_path)
+        .output()
+        .map_err(|err| format!("Could not run ffprobe: {}", err))?;
+    if output.status.success() {
+        // The output is e.g. "flac,audio", the codec name comes first
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .split(',')
+            .next()
+            .map(|codec_name| codec_name.trim().to_string())
+            .filter(|codec_name| !codec_name.is_empty()))
+    } else {
+        Err(format!(
+            "stdout: {}\nstderr: {}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+fn handle_user_action(
+    cue_sheet: &mut CueSheet,
+    user_action: CueFixAction,
+    cue_options: &CueOptions,
+) -> Option<CueFixAction> {
     match user_action {
         CueFixAction::Modified => {
             println!("🔄 Retrying verification ...");
-            let parse_cue_file = parse_cue_file(&cue_sheet.cue_file_path);
-            let mut new_cue_sheet = parse_cue_file.unwrap();
-            cue_sheet.audio_file_path = new_cue_sheet.audio_file_path.clone();
-            cue_sheet.audio_file_name = new_cue_sheet.audio_file_name.clone();
-            return Some(verify_cue_files(&mut new_cue_sheet));
+            let Some(mut edited_cue_sheet) =
+                parse_cue_file(&cue_sheet.cue_file_path, cue_options.cue_encoding)
+            else {
+                cue_sheet
+                    .problems
+                    .push("The edited cue file could not be read".to_string());
+                return Some(CueFixAction::Skipped);
+            };
+
+            // A fixed audio file reference, which was not saved, still applies to the edited cue file,
+            // unless the user changed the reference to an existing file
+            if edited_cue_sheet.audio_file_name != cue_sheet.audio_file_name
+                && edited_cue_sheet.audio_file_count() == 1
+                && !edited_cue_sheet.audio_file_path.exists()
+                && cue_sheet.audio_file_path.exists()
+            {
+                edited_cue_sheet.audio_file_path = cue_sheet.audio_file_path.clone();
+                edited_cue_sheet.audio_file_name = cue_sheet.audio_file_name.clone();
+                edited_cue_sheet.audio_file_type = cue_sheet.audio_file_type.clone();
+            }
+
+            // Verify the reparsed cue sheet in place, so fixes made while verifying it are kept
+            edited_cue_sheet.fixes = std::mem::take(&mut cue_sheet.fixes);
+            edited_cue_sheet
+                .fixes
+                .push("The cue file was edited by the user".to_string());
+            *cue_sheet = edited_cue_sheet;
+            let fix_action = verify_cue_files(cue_sheet, cue_options);
+
+            // Fixes applied to the cue file again are only listed once
+            let mut listed_fixes = HashSet::new();
+            cue_sheet
+                .fixes
+                .retain(|fix| listed_fixes.insert(fix.clone()));
+            return Some(fix_action);
         }
         CueFixAction::Deleted => {
             return Some(CueFixAction::Deleted);
         }
+        CueFixAction::Skipped => {
+            return Some(CueFixAction::Skipped);
+        }
+        CueFixAction::Aborted(abort_exit_code) => {
+            return Some(CueFixAction::Aborted(abort_exit_code));
+        }
         CueFixAction::None => {}
     }
     None
 }
 
+/// What to do with cue files that can not be fixed without asking the user
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum InvalidCuePolicy {
+    /// Skip the cue file and continue with the others
+    Skip,
+    /// Exit with an error
+    Abort,
+}
+
+impl FromStr for InvalidCuePolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "skip" => Ok(InvalidCuePolicy::Skip),
+            "abort" => Ok(InvalidCuePolicy::Abort),
+            _ => Err(format!(
+                "unknown invalid cue policy '{}', expected one of: skip, abort",
+                value
+            )),
+        }
+    }
+}
+
+/// A cue file left out by the pipeline, because it could not be read or was invalid and not fixed
+#[derive(Debug, Clone)]
+struct SkippedCueFile {
+    cue_file_path: PathBuf,
+    reason: String,
+}
+
 enum CueFixAction {
     /// The cue file was modified by the fix action, we should retry the process
     Modified,
     /// The cue file was deleted by the fix action, we should skip the process
     Deleted,
+    /// The cue file can not be processed, e.g. because it was already split, we should skip the process
+    Skipped,
+    /// The user quit or --on-invalid-cue abort applies, the whole run stops with the exit code
+    Aborted(i32),
     /// The cue file was not modified by the fix action, nothing special to do
     None,
 }
 
 /// Lets the user fix the cue file
-fn ask_user_for_fix(cue_sheet: &mut CueSheet) -> CueFixAction {
+/// In non-interactive mode, or if stdin is closed, the invalid cue policy decides
+fn ask_user_for_fix(cue_sheet: &mut CueSheet, cue_options: &CueOptions) -> CueFixAction {
     blue_ln!("🔧 {}", cue_sheet.cue_file_path.display());
+    if cue_options.non_interactive {
+        return apply_invalid_cue_policy(cue_options.on_invalid_cue);
+    }
     blue_ln!("🔧 What do you want to do with the cure file? (e)dit, (d)elete, (l)ist files, (v)iew, (r)etry, (q)uit: ");
 
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
+    let Some(input) = read_user_input() else {
+        return apply_invalid_cue_policy(cue_options.on_invalid_cue);
+    };
 
     match input.trim() {
         "e" => {
             let editor = std::env::var("EDITOR").unwrap_or("vi".to_string());
-            Command::new(editor)
+            if let Err(err) = Command::new(&editor)
                 .arg(cue_sheet.cue_file_path.as_os_str())
                 .status()
-                .expect("Failed to open file in editor");
+            {
+                red_ln!("❌ Could not open the editor {}: {}", editor, err);
+                return CueFixAction::Aborted(exit_code::ERROR);
+            }
 
-            ask_user_for_fix(cue_sheet)
+            ask_user_for_fix(cue_sheet, cue_options)
         }
         "d" => {
-            fs::remove_file(&cue_sheet.cue_file_path).unwrap();
+            if let Err(err) = fs::remove_file(&cue_sheet.cue_file_path) {
+                red_ln!(
+                    "❌ Could not delete the cue file {}: {}",
+                    cue_sheet.cue_file_path.display(),
+                    err
+                );
+                return CueFixAction::Aborted(exit_code::ERROR);
+            }
             println!("🗑 Deleted cue file: {:?}", cue_sheet.cue_file_path);
 
             CueFixAction::Deleted
         }
         "v" => {
             // Read and print cue file content
-            let data = fs::read_to_string(&cue_sheet.cue_file_path).unwrap();
+            let data = match fs::read_to_string(&cue_sheet.cue_file_path) {
+                Ok(data) => data,
+                Err(err) => {
+                    red_ln!(
+                        "❌ Could not read the cue file {}: {}",
+                        cue_sheet.cue_file_path.display(),
+                        err
+                    );
+                    return CueFixAction::Aborted(exit_code::ERROR);
+                }
+            };
             // clear screen
             println!();
             println!();
             println!("📄 Cue file content:");
             println!("{}", data);
 
-            ask_user_for_fix(cue_sheet)
+            ask_user_for_fix(cue_sheet, cue_options)
         }
         "l" => {
             let parent_dir = cue_sheet.audio_file_path.parent().unwrap();
-            let files_in_directory: Vec<DirEntry> = parent_dir
-                .read_dir()
-                .unwrap()
-                .filter_map(|entry| entry.ok())
-                .filter(|entry| entry.path().is_file())
-                .collect();
+            let files_in_directory: Vec<DirEntry> = match parent_dir.read_dir() {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_file())
+                    .collect(),
+                Err(err) => {
+                    red_ln!(
+                        "❌ Could not read the directory {}: {}",
+                        parent_dir.display(),
+                        err
+                    );
+                    return CueFixAction::Aborted(exit_code::ERROR);
+                }
+            };
             for entry in files_in_directory {
                 println!(" * {}", entry.path().display());
             }
 
-            ask_user_for_fix(cue_sheet)
+            ask_user_for_fix(cue_sheet, cue_options)
         }
         "r" => CueFixAction::Modified,
         "q" => {
             println!("🚪 Exiting ...");
-            std::process::exit(1);
+            CueFixAction::Aborted(exit_code::USER_ABORT)
+        }
+        _ => {
+            println!("Invalid input, please try again");
+            ask_user_for_fix(cue_sheet, cue_options)
+        }
+    }
+}
+
+fn apply_invalid_cue_policy(invalid_cue_policy: InvalidCuePolicy) -> CueFixAction {
+    match invalid_cue_policy {
+        InvalidCuePolicy::Skip => {
+            yellow_ln!("⏭️ Skipping the invalid cue file");
+            CueFixAction::Skipped
         }
-        _ => {
-            println!("Invalid input, please try again");
-            ask_user_for_fix(cue_sheet)
+        InvalidCuePolicy::Abort => {
+            red_ln!("❌ Aborting because of the invalid cue file");
+            CueFixAction::Aborted(exit_code::INVALID_INPUT)
         }
     }
 }
 
+/// Asks the user whether to use the similar audio file, the default answer depends on the score
+fn ask_user_to_accept_match(score: usize, accept_score: usize) -> bool {
+    let default_action: UserDefaultAction = if score > accept_score && score > 85 {
+        green_ln!("🔧 Do you want to use this file instead? (Y/n): ");
+        UserDefaultAction::Yes
+    } else if score > accept_score {
+        yellow_ln!("🔧 Do you want to use this file instead? (Y/n): ");
+        UserDefaultAction::Yes
+    } else {
+        red_ln!("🔧 Do you want to use this file instead? (y/N): ");
+        UserDefaultAction::No
+    };
+
+    match read_user_input().unwrap_or_default().trim() {
+        "y" | "Y" => true,
+        "n" | "N" => false,
+        _ => default_action == UserDefaultAction::Yes,
+    }
+}
+
+/// Reads a line from stdin, returns None if stdin is closed
+fn read_user_input() -> Option<String> {
+    let mut input = String::new();
+    match std::io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input),
+    }
+}
+
 /// Fixes the audio file reference in the cue sheet
 /// This happens e.g. when the case of the audio file path in the cue sheet does not match the actual file path
 /// This is a common issue on Windows file systems
-fn fix_cue_sheet_audio_file_reference(cue_sheet: &mut CueSheet) -> CueFixAction {
+/// If enabled, the fix is saved into the cue file, so the next run does not ask again
+fn fix_cue_sheet_audio_file_reference(
+    cue_sheet: &mut CueSheet,
+    cue_options: &CueOptions,
+) -> CueFixAction {
     let broken_file_name = cue_sheet
         .audio_file_path
         .file_name()
@@ -570,7 +1712,7 @@ fn fix_cue_sheet_audio_file_reference(cue_sheet: &mut CueSheet) -> CueFixAction
 
     let best_match = find_best_match(cue_sheet, parent_dir, broken_file_name);
     if best_match.is_none() {
-        return ask_user_for_fix(cue_sheet);
+        return ask_user_for_fix(cue_sheet, cue_options);
     }
     let best_match = best_match.unwrap();
     let best_match_file_name = best_match.0.file_name().unwrap();
@@ -586,29 +1728,30 @@ fn fix_cue_sheet_audio_file_reference(cue_sheet: &mut CueSheet) -> CueFixAction
         score
     );
 
-    let default_action: UserDefaultAction = if score > 85 {
-        green_ln!("🔧 Do you want to use this file instead? (Y/n): ");
-        UserDefaultAction::Yes
-    } else if score > 70 {
-        yellow_ln!("🔧 Do you want to use this file instead? (Y/n): ");
-        UserDefaultAction::Yes
+    let accept_match = if cue_options
+        .auto_fix_score
+        .is_some_and(|auto_fix_score| score >= auto_fix_score)
+    {
+        green_ln!("🔧 The score reaches the auto fix score, using this file instead");
+        true
+    } else if cue_options.non_interactive {
+        // Nobody confirms a fuzzy match, only the auto fix score accepts it
+        match cue_options.auto_fix_score {
+            Some(_) => {
+                yellow_ln!("🔧 Non-interactive mode, the score does not reach the auto fix score")
+            }
+            None => {
+                yellow_ln!("🔧 Non-interactive mode, the match needs --auto-fix-score to be used")
+            }
+        }
+        false
     } else {
-        red_ln!("🔧 Do you want to use this file instead? (y/N): ");
-        UserDefaultAction::No
-    };
-
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-
-    let cancel_process = match input.trim() {
-        "y" | "Y" => false,
-        "n" | "N" => true,
-        _ => default_action == UserDefaultAction::No,
+        ask_user_to_accept_match(score, cue_options.accept_score)
     };
 
-    if cancel_process {
+    if !accept_match {
         red_ln!("❌ The referenced audio could not be fixed automatically");
-        return ask_user_for_fix(cue_sheet);
+        return ask_user_for_fix(cue_sheet, cue_options);
     }
 
     println!(
@@ -616,9 +1759,38 @@ fn fix_cue_sheet_audio_file_reference(cue_sheet: &mut CueSheet) -> CueFixAction
         cue_sheet.audio_file_path.file_name().unwrap(),
         best_match_file_name
     );
+    // The file type only changes with the extension, e.g. EAC writes WAVE for FLAC files
+    if cue_sheet.audio_file_path.extension() != best_match.0.extension() {
+        cue_sheet.audio_file_type = cue_writer::file_type(&best_match.0).to_string();
+    }
+    cue_sheet.fixes.push(format!(
+        "Replaced the audio file reference {} with {} ({}%)",
+        cue_sheet.audio_file_name,
+        best_match_file_name.to_string_lossy(),
+        score
+    ));
     cue_sheet.audio_file_path = best_match.0.clone();
     cue_sheet.audio_file_name = best_match_file_name.to_str().unwrap().to_string();
 
+    if cue_options.save_fixes {
+        match cue_writer::save_audio_file_reference(cue_sheet) {
+            Ok(backup_file) => {
+                println!(
+                    "💾 Saved the fix into the cue file, the original is kept as {}",
+                    backup_file.display()
+                );
+                cue_sheet
+                    .fixes
+                    .push("Saved the fix into the cue file".to_string());
+            }
+            Err(error_message) => yellow_ln!(
+                "⚠️ Could not save the fix into the cue file {}: {}",
+                cue_sheet.cue_file_path.display(),
+                error_message
+            ),
+        }
+    }
+
     CueFixAction::None
 }
 
@@ -629,18 +1801,31 @@ fn find_best_match(
     broken_file_name: &str,
 ) -> Option<(PathBuf, usize)> {
     // Find all entries in the parent directory
-    let files_in_directory: Vec<DirEntry> = parent_dir
-        .read_dir()
-        .unwrap()
-        .filter_map(|entry| entry.ok())
-        .collect();
+    let files_in_directory: Vec<DirEntry> = match parent_dir.read_dir() {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).collect(),
+        Err(err) => {
+            eprintln!(
+                "❌ Could not read the directory {}: {}",
+                parent_dir.display(),
+                err
+            );
+            return None;
+        }
+    };
 
-    // Then find all valid audio files in the directory
-    let audio_files_in_directory: Vec<PathBuf> = files_in_directory
+    // Then find all valid audio files in the directory, which are long enough to hold all tracks
+    let expected_duration = ExpectedDuration::from_cue_sheet(cue_sheet);
+    let audio_files_in_directory: Vec<(PathBuf, usize)> = files_in_directory
         .par_iter()
-        .filter(|entry| entry.file_type().unwrap().is_file())
-        .filter(|entry| audio_playtime_matches(entry, cue_sheet.tracks.last().unwrap()))
-        .map(|entry| entry.path())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| {
+            let duration = audio_duration::read_audio_duration(&entry.path())?;
+            let duration_score = match expected_duration {
+                Some(expected_duration) => expected_duration.score(duration)?,
+                None => 100,
+            };
+            Some((entry.path(), duration_score))
+        })
         .collect();
 
     if audio_files_in_directory.is_empty() {
@@ -651,34 +1836,33 @@ fn find_best_match(
         return None;
     };
 
-    // Calculate the levenshtein distance between the broken file name and the actual file name
-    let levenshtein_result =
-        find_best_levenshtein_match(broken_file_name, &audio_files_in_directory);
-
-    // Calculate the hamming distance between the broken file name and the actual file name
-    let hamming_result = find_best_hamming_match(broken_file_name, &audio_files_in_directory);
-
-    // If both are present, take the one with the better score
-    // If they are equal, take the levenshtein result
-    if let Some(levenshtein_result) = &levenshtein_result {
-        if let Some(hamming_result) = &hamming_result {
-            if levenshtein_result.0 == hamming_result.0 {
-                return Some(levenshtein_result.clone());
-            }
-            // If they differ, take the one with the better score
-            else if levenshtein_result.1 > hamming_result.1 {
-                return Some(levenshtein_result.clone());
-            } else {
-                return Some(hamming_result.clone());
-            }
-        }
+    // The most common case is a re-encoded audio file, e.g. album.wav -> album.flac
+    let audio_file_paths: Vec<PathBuf> = audio_files_in_directory
+        .iter()
+        .map(|(audio_file, _)| audio_file.clone())
+        .collect();
+    if let Some(same_stem_match) = find_same_stem_match(broken_file_name, &audio_file_paths) {
+        return Some(same_stem_match);
     }
 
-    // If one is missing but the other is present, return the present one
-    if levenshtein_result.is_some() && hamming_result.is_none() {
-        return Some(levenshtein_result.unwrap());
-    } else if levenshtein_result.is_none() && hamming_result.is_some() {
-        return Some(hamming_result.unwrap());
+    // Rank the candidates by name and duration similarity
+    // The name weighs more, as the audio files of a folder often have similar durations
+    let mut ranked_audio_files: Vec<(PathBuf, usize)> = audio_files_in_directory
+        .into_iter()
+        .map(|(audio_file, duration_score)| {
+            let name_score = name_similarity(broken_file_name, &audio_file);
+            let score =
+                (name_score * NAME_SCORE_WEIGHT + duration_score * (100 - NAME_SCORE_WEIGHT)) / 100;
+            (audio_file, score)
+        })
+        .collect();
+    ranked_audio_files.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    // If the best candidates have the same score, we can't determine the best match
+    match ranked_audio_files.as_slice() {
+        [best_match, second_best_match, ..] if best_match.1 == second_best_match.1 => {}
+        [best_match, ..] => return Some(best_match.clone()),
+        [] => {}
     }
 
     yellow_ln!(
@@ -689,158 +1873,80 @@ fn find_best_match(
     None
 }
 
-fn audio_playtime_matches(entry: &DirEntry, last_track: &Track) -> bool {
-    let mut matches = false;
-    if let Some(entry_playtime) = read_audio_playtime(entry) {
-        let last_track_start = last_track.start_time.unwrap();
-        let last_track_start_seconds = last_track_start.minutes * 60 + last_track_start.seconds;
-        matches = entry_playtime >= last_track_start_seconds
-    }
-    matches
-}
-
-/// Read the length of the audio file using ffprobe
+/// Read the length of the audio file
 /// Returns the length in seconds
-/// Example call: ffprobe -v error -show_entries format=duration -of default=noprint_wrappers=1:nokey=1 input.mp3
-fn read_audio_playtime(entry: &DirEntry) -> Option<u32> {
-    // Build ffprobe command
-    let ffprobe_command = format!(
-        "ffprobe -v error -show_entries format=duration -of default=noprint_wrappers=1:nokey=1 \"{}\"",
-        entry.path().display()
-    );
-
-    // Run ffprobe command
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(ffprobe_command)
-        .output()
-        .expect("Failed to execute command");
-
-    // Check if ffprobe command was successful
-    if !output.status.success() {
-        return None;
-    }
-
-    // Parse ffprobe output
-    let output = String::from_utf8_lossy(&output.stdout);
-    if let Ok(playtime) = output.trim().parse::<f32>() {
-        Some(playtime as u32)
-    } else {
-        None
-    }
+fn read_audio_playtime(audio_file_path: &Path) -> Option<u32> {
+    audio_duration::read_audio_duration(audio_file_path).map(|duration| duration as u32)
 }
 
-fn find_best_hamming_match(
-    broken_file_name: &str,
-    audio_files_in_same_dir: &[PathBuf],
-) -> Option<(PathBuf, usize)> {
-    let audio_files_ham: Vec<(PathBuf, usize)> = audio_files_in_same_dir
-        .iter()
-        .map(|audio_entry| {
-            let entry_file_name = audio_entry.file_name();
-            let entry_file_name = entry_file_name.unwrap().to_str().unwrap();
-
-            // Remove extension
-            let entry_file_name = entry_file_name.split('.').next().unwrap();
-            let broken_file_name = broken_file_name.split('.').next().unwrap();
-
-            (
-                audio_entry.clone(),
-                hamming_distance(entry_file_name.as_bytes(), broken_file_name.as_bytes()),
-            )
-        })
-        .collect();
+/// Scores from 0 to 100 how similar the file name is to the broken file name, extensions are ignored
+/// The better score of the levenshtein and the hamming distance is taken
+fn name_similarity(broken_file_name: &str, audio_file: &Path) -> usize {
+    let broken_stem = file_stem(Path::new(broken_file_name));
+    let stem = file_stem(audio_file);
 
-    // If we have multiple entries with the same distance, we can't determine the best match
-    // In this case, we return None
-    let all_have_same_distance = audio_files_ham
-        .iter()
-        .all(|(_, distance)| *distance == audio_files_ham[0].1);
-    if audio_files_ham.len() > 1 && all_have_same_distance {
-        return None;
+    let longest_length = size_of_longest(&broken_stem, &stem);
+    let shortest_length = size_of_shortest(&broken_stem, &stem);
+    if shortest_length == 0 {
+        return 0;
     }
 
-    // Find the best match (smallest distance)
-    let best_match = audio_files_ham.iter().min_by(|a, b| a.1.cmp(&b.1)).unwrap();
+    let levenshtein_distance = levenshtein::levenshtein(&broken_stem, &stem);
+    let levenshtein_score = 100 - (levenshtein_distance * 100 / longest_length);
 
-    // Calculate the success rate
-    let best_match_file_name = best_match.0.file_name().unwrap().to_str().unwrap();
+    let hamming_distance = hamming_distance(&broken_stem, &stem);
+    let hamming_score = 100 - (hamming_distance * 100 / shortest_length);
 
-    // Remove extension
-    let best_match_file_name = best_match_file_name.split('.').next().unwrap();
-    let broken_file_name = broken_file_name.split('.').next().unwrap();
+    levenshtein_score.max(hamming_score)
+}
 
-    let hamming_distance = best_match.1;
-    let shortest_length = size_of_shortest(broken_file_name, best_match_file_name);
+/// Counts the differing characters at the same position, characters beyond the shorter string are ignored
+fn hamming_distance(x: &str, y: &str) -> usize {
+    x.chars().zip(y.chars()).filter(|(a, b)| a != b).count()
+}
 
-    let success_rate = 100 - (hamming_distance * 100 / shortest_length);
+// Returns the length of the longest string in characters
+fn size_of_longest(a: &str, b: &str) -> usize {
+    a.chars().count().max(b.chars().count())
+}
 
-    Some((best_match.0.clone(), success_rate))
+// Returns the length of the shortest string in characters
+fn size_of_shortest(a: &str, b: &str) -> usize {
+    a.chars().count().min(b.chars().count())
 }
 
-fn hamming_distance(x: &[u8], y: &[u8]) -> usize {
-    x.iter().zip(y.iter()).filter(|(a, b)| a != b).count()
+/// Returns the file name without its last extension, e.g. "01. Intro" for "01. Intro.flac"
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
 }
 
-fn find_best_levenshtein_match(
+/// Finds an audio file with the same name but a different extension, e.g. album.flac for album.wav
+/// An exact name scores 100, a name differing only in case 95
+/// If there are several candidates, e.g. album.flac and album.ape, there is no clear match
+fn find_same_stem_match(
     broken_file_name: &str,
     audio_files_in_same_dir: &[PathBuf],
 ) -> Option<(PathBuf, usize)> {
-    let audio_files_lev: Vec<(PathBuf, usize)> = audio_files_in_same_dir
+    let broken_stem = file_stem(Path::new(broken_file_name));
+    let same_stem_matches: Vec<(PathBuf, usize)> = audio_files_in_same_dir
         .iter()
-        .map(|audio_entry| {
-            let entry_file_name = audio_entry.file_name();
-            let entry_file_name = entry_file_name.unwrap().to_str().unwrap();
-
-            // Remove extension
-            let entry_file_name = entry_file_name.split('.').next().unwrap();
-            let broken_file_name = broken_file_name.split('.').next().unwrap();
-
-            (
-                audio_entry.clone(),
-                levenshtein::levenshtein(broken_file_name, entry_file_name),
-            )
+        .filter_map(|audio_file| {
+            let stem = file_stem(audio_file);
+            if stem == broken_stem {
+                Some((audio_file.clone(), 100))
+            } else if stem.to_lowercase() == broken_stem.to_lowercase() {
+                Some((audio_file.clone(), 95))
+            } else {
+                None
+            }
         })
         .collect();
 
-    // If we have multiple entries with the same distance, we can't determine the best match
-    // In this case, we return None
-    let all_have_same_distance = audio_files_lev
-        .iter()
-        .all(|(_, distance)| *distance == audio_files_lev[0].1);
-    if audio_files_lev.len() > 1 && all_have_same_distance {
-        return None;
-    }
-
-    // Find the best match (smallest distance)
-    let best_match = audio_files_lev.iter().min_by(|a, b| a.1.cmp(&b.1)).unwrap();
-
-    // Calculate the success rate
-    let file_name_length = size_of_longest(
-        broken_file_name,
-        best_match.0.file_name().unwrap().to_str().unwrap(),
-    );
-    let levenshtein_distance = best_match.1;
-    let success_rate = 100 - (levenshtein_distance * 100 / file_name_length);
-
-    Some((best_match.0.clone(), success_rate))
-}
-
-// Returns the length of the longest string
-fn size_of_longest(a: &str, b: &str) -> usize {
-    if a.len() > b.len() {
-        a.len()
-    } else {
-        b.len()
-    }
-}
-
-// Returns the length of the shortest string
-fn size_of_shortest(a: &str, b: &str) -> usize {
-    if a.len() < b.len() {
-        a.len()
-    } else {
-        b.len()
+    match same_stem_matches.as_slice() {
+        [same_stem_match] => Some(same_stem_match.clone()),
+        _ => None,
     }
 }
 
@@ -877,27 +1983,58 @@ fn find_cue_files(input_path: &Path) -> Vec<PathBuf> {
 /// Tests if the given commands are available in the users path
 /// The test is done by using the `which` command
 fn check_tools(commands: Vec<&str>) {
-    for command in commands {
-        let output = Command::new("which")
+    if let Some(command) = find_missing_tool(&commands) {
+        eprintln!("Command {} not found in path", command);
+        std::process::exit(exit_code::MISSING_TOOLS);
+    }
+}
+
+/// Returns the first command which is not in the path
+fn find_missing_tool<'a>(commands: &[&'a str]) -> Option<&'a str> {
+    commands.iter().copied().find(|command| {
+        !Command::new("which")
             .arg(command)
             .output()
-            .expect("Failed to execute which command");
+            .expect("Failed to execute which command")
+            .status
+            .success()
+    })
+}
 
-        if !output.status.success() {
-            eprintln!("Command {} not found in path", command);
-            std::process::exit(1);
-        }
+fn augment_with_output_files(
+    cue_sheet: &mut CueSheet,
+    track_number_width: usize,
+    output_options: &OutputOptions,
+) {
+    let output_files: Vec<PathBuf> = cue_sheet
+        .tracks
+        .iter()
+        .map(|track| {
+            PathBuf::from(build_output_name(
+                cue_sheet,
+                track,
+                track_number_width,
+                output_options,
+            ))
+        })
+        .collect();
+
+    for (track, output_file) in cue_sheet.tracks.iter_mut().zip(output_files) {
+        track.output_file = Some(output_file);
     }
 }
 
-fn augment_with_ffmpeg_commands(cue_sheet: &mut CueSheet) {
-    let output_codec = detect_output_codec(cue_sheet);
+fn augment_with_ffmpeg_commands(cue_sheet: &mut CueSheet, output_options: &OutputOptions) {
+    // The codec was probed while verifying, an unknown codec is copied
+    let output_codec = cue_sheet.audio_codec.clone().unwrap_or_default();
 
     let augmented_tracks: Vec<Track> = cue_sheet
         .tracks
         .iter()
         .enumerate()
-        .map(|(index, track)| build_ffmpeg_command(cue_sheet, index, track, &output_codec))
+        .map(|(index, track)| {
+            build_ffmpeg_command(cue_sheet, index, track, &output_codec, output_options)
+        })
         .collect();
 
     cue_sheet.tracks = augmented_tracks;
@@ -908,123 +2045,100 @@ fn build_ffmpeg_command(
     index: usize,
     track: &Track,
     output_codec: &str,
+    output_options: &OutputOptions,
 ) -> Track {
     let cue_duration = track.start_time.as_ref().unwrap();
 
-    // Convert frames to milliseconds (1 CDDA frame = 1/75 second)
-    let milliseconds = cue_duration.frames * 1000 / 75;
-
-    // Convert minutes to hours and remaining minutes
-    let hours = cue_duration.minutes / 60;
-    let minutes = cue_duration.minutes % 60;
-
-    // Format as "hh:mm:ss.mmm"
-    let ffmpeg_start_time = format!(
-        "{:02}:{:02}:{:02}.{:03}",
-        hours, minutes, cue_duration.seconds, milliseconds
-    );
+    let mut ffmpeg_args: Vec<OsString> = vec![
+        "-y".into(),
+        "-i".into(),
+        cue_sheet.audio_file_path.clone().into(),
+        "-map_metadata".into(),
+        "-1".into(),
+        "-ss".into(),
+        ffmpeg_time(cue_duration).into(),
+    ];
 
     // Calculate the end time based on the next track, if we have the last track, skip this param
-    // let ffmpeg_end_time_param = format!("-to {:02}:{:02}:{:02}.{:03}", hours, minutes, cue_duration.seconds + 30, milliseconds);
-    let ffmpeg_end_time = if index < cue_sheet.tracks.len() - 1 {
+    if index < cue_sheet.tracks.len() - 1 {
         let next_track = &cue_sheet.tracks[index + 1];
         let next_cue_duration = next_track.start_time.as_ref().unwrap();
-        let next_milliseconds = next_cue_duration.frames * 1000 / 75;
-        let next_hours = next_cue_duration.minutes / 60;
-        let next_minutes = next_cue_duration.minutes % 60;
-        format!(
-            "-to \"{:02}:{:02}:{:02}.{:03}\"",
-            next_hours, next_minutes, next_cue_duration.seconds, next_milliseconds
-        )
-    } else {
-        "".to_string()
-    };
-
-    let audio_file_path = cue_sheet.audio_file_path.to_str().unwrap();
-    let output_file_name = build_output_name(cue_sheet, track);
+        ffmpeg_args.extend(["-to".into(), ffmpeg_time(next_cue_duration).into()]);
+    }
 
     // For lossless codecs we need to re-encode the audio
     // Lossless codecs such as FLAC or ALAC store the exact number of samples and the sampling rate in their headers.
     // Thus, we need to re-encode the audio to apply the start and end time.
-    let output_codec_parameter = if ["flac", "alac", "wav", "aiff"].contains(&output_codec) {
-        format!("-c:a {}", output_codec)
+    // ffprobe names the PCM codecs of WAV and AIFF files by their sample format, e.g. pcm_s16le or pcm_s24be
+    if let Some(format) = output_options.format {
+        ffmpeg_args.extend(format.codec_args().iter().map(OsString::from));
+    } else if ["flac", "alac"].contains(&output_codec) || output_codec.starts_with("pcm_") {
+        ffmpeg_args.extend(["-c:a".into(), output_codec.into()]);
     } else {
-        "-c:a copy".to_string()
-    };
-
-    let command = format!(
-        "ffmpeg -y -i \"{}\" -map_metadata -1 -ss \"{}\" {} {} \"{}\"",
-        audio_file_path,
-        ffmpeg_start_time,
-        ffmpeg_end_time,
-        output_codec_parameter,
-        output_file_name
-    );
+        ffmpeg_args.extend(["-c:a".into(), "copy".into()]);
+    }
+    ffmpeg_args.push(track.output_file.clone().unwrap().into());
 
     Track {
-        number: track.number,
-        title: track.title.clone(),
-        artist: track.artist.clone(),
-        start_time: track.start_time,
-        output_file: Some(PathBuf::from(output_file_name)),
-        ffmpeg_command: Some(command),
-    }
-}
-
-/// Detects the codec of the audio file associated with the given `CueSheet`.
-///
-/// This function uses `ffprobe` to determine the codec of the audio file. It constructs
-/// a command to run `ffprobe` with the necessary arguments to extract the codec name
-/// from the audio file.
-///
-/// # Returns
-///
-/// A `String` containing the codec name of the audio file.
-fn detect_output_codec(cue_sheet: &CueSheet) -> String {
-    // Construct the ffprobe command to extract the codec name from the audio file
-    let ffprobe_cmd = format!(
-        "ffprobe -v error -select_streams a:0 -show_entries stream=codec_name -of default=noprint_wrappers=1:nokey=1 \"{}\"",
-        cue_sheet.audio_file_path.display()
-    );
+        ffmpeg_command: Some(display_command("ffmpeg", &ffmpeg_args)),
+        ffmpeg_args,
+        ..track.clone()
+    }
+}
 
-    // Execute the ffprobe command
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(ffprobe_cmd)
-        .output()
-        .expect("Failed to execute command");
+/// Formats a cue duration as ffmpeg time: hh:mm:ss.mmm
+fn ffmpeg_time(cue_duration: &CueDuration) -> String {
+    // Convert frames to milliseconds (1 CDDA frame = 1/75 second)
+    let milliseconds = cue_duration.frames * 1000 / 75;
 
-    // Check if the command was successful and return the codec name
-    if output.status.success() {
-        String::from_utf8_lossy(&output.stdout).trim().to_string()
-    } else {
-        // Panic if the codec detection fails
-        panic!(
-            "Failed to detect codec for file: {}",
-            cue_sheet.audio_file_path.display()
-        );
+    // Convert minutes to hours and remaining minutes
+    let hours = cue_duration.minutes / 60;
+    let minutes = cue_duration.minutes % 60;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hours, minutes, cue_duration.seconds, milliseconds
+    )
+}
+
+/// Formats a command for display, e.g. in the dry run output
+/// Commands are run without a shell, the quotes only keep arguments with spaces readable
+fn display_command(program: &str, args: &[impl AsRef<OsStr>]) -> String {
+    let mut command = program.to_string();
+    for arg in args {
+        let arg = arg.as_ref().to_string_lossy();
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+            command.push_str(&format!(" \"{}\"", arg.replace('"', "\\\"")));
+        } else {
+            command.push(' ');
+            command.push_str(&arg);
+        }
     }
+    command
 }
 
-fn build_output_name(cue_sheet: &CueSheet, track: &Track) -> String {
-    let extension = cue_sheet
-        .audio_file_name
-        .split('.')
-        .last()
-        .unwrap_or_else(|| {
-            eprintln!(
-                "❌ Could not determine extension for file {}",
-                cue_sheet.audio_file_name
-            );
-            std::process::exit(1);
-        });
+fn build_output_name(
+    cue_sheet: &CueSheet,
+    track: &Track,
+    track_number_width: usize,
+    output_options: &OutputOptions,
+) -> String {
+    let extension = match output_options.format {
+        Some(format) => format.extension(),
+        None => cue_sheet
+            .audio_file_name
+            .split('.')
+            .next_back()
+            .unwrap_or_default(),
+    };
 
     // Create a sub dir for each cue file
-    let sub_dir_name = if is_multi_disc(cue_sheet) {
-        let disk_number = derive_disk_number(&cue_sheet.cue_file_path);
-        format!("CD{}", disk_number)
-    } else {
-        ".".to_string()
+    // Discs that already live in their own disc folder (e.g. CD1/) are not nested again
+    let sub_dir_name = match cue_sheet.disc_number {
+        Some(disc_number) if !release::is_in_disc_folder(&cue_sheet.cue_file_path) => {
+            format!("CD{}", disc_number)
+        }
+        _ => ".".to_string(),
     };
     let sub_dir = cue_sheet
         .audio_file_path
@@ -1034,162 +2148,140 @@ fn build_output_name(cue_sheet: &CueSheet, track: &Track) -> String {
     let sub_dir = sub_dir.to_str().unwrap();
 
     // Create a filename for each track
-    let track_number = format!("{:02}", track.number);
-    let mut track_title = if let Some(ref title) = track.title {
-        if let Some(ref artist) = track.artist {
-            format!("{} - {}", artist, title)
-        } else {
-            title.to_string()
+    let track_number = format_track_number(
+        cue_sheet,
+        track,
+        track_number_width,
+        output_options.disc_prefix,
+    );
+    let filename = match output_options.output_template {
+        Some(ref output_template) => output_template.render(|placeholder| {
+            let value = match placeholder {
+                "number" => Some(track_number.clone()),
+                "title" => track.title.clone(),
+                "artist" => track.artist.clone().or(cue_sheet.performer.clone()),
+                "album" => cue_sheet.title.clone(),
+                "album_artist" => cue_sheet.performer.clone(),
+                "disc" => Some(cue_sheet.disc_number.unwrap_or(1).to_string()),
+                _ => None,
+            };
+            sanitize_file_name(&value.unwrap_or_else(|| "Unknown".to_string()))
+        }),
+        None => {
+            let track_title = if let Some(ref title) = track.title {
+                if let Some(ref artist) = track.artist {
+                    format!("{} - {}", artist, title)
+                } else {
+                    title.to_string()
+                }
+            } else {
+                "Unknown".to_string()
+            };
+            format!("{} {}", track_number, sanitize_file_name(&track_title))
         }
-    } else {
-        "Unknown".to_string()
     };
 
-    // Replace invalid characters
-    track_title = track_title
+    format!("{}/{}.{}", sub_dir, filename, extension)
+}
+
+/// Replaces characters which are invalid in file names, or would create sub folders
+/// Values made of dots only, like "..", would name the current or the parent folder
+fn sanitize_file_name(name: &str) -> String {
+    let name = name
         .replace("/", "-")
         .replace("\\", "-")
         .replace(":", "-")
         .replace("`", "'")
         .trim()
         .to_string();
-
-    let filename = format!("{} {}", track_number, track_title);
-
-    format!("{}/{}.{}", sub_dir, filename, extension)
+    if name.chars().all(|c| c == '.') {
+        name.replace('.', "_")
+    } else {
+        name
+    }
 }
 
-/// Derives the disk number from the cue file path
-/// This is done by checking the file name for common patterns
-fn derive_disk_number(cue_file_path: &Path) -> usize {
-    // First split the cue file name by common delimiters
-    let cue_file_name = cue_file_path.file_name().unwrap().to_str().unwrap();
-    let delimiter = find_best_delimiter(cue_file_name);
-    let tokens: Vec<&str> = cue_file_name.split(&delimiter).collect();
-
-    // Check if there is a token that starts trimmed ignoring case with CD
-    let mut disk_number = tokens
+/// The zero-padding of the track numbers, derived from the highest track number of all discs, but at least two
+/// digits wide, so all discs of a release are numbered alike
+fn track_number_width(release: &Release) -> usize {
+    let highest_track_number = release
+        .discs
         .iter()
-        .filter_map(|token| {
-            if token.trim().to_lowercase().starts_with("cd") {
-                token
-                    .trim()
-                    .to_lowercase()
-                    .strip_prefix("cd")
-                    .unwrap()
-                    .parse::<usize>()
-                    .ok()
-            } else {
-                None
-            }
+        .flat_map(|cue_sheet| {
+            cue_sheet
+                .tracks
+                .iter()
+                .map(|track| track.number as usize)
+                .chain([cue_sheet.tracks.len()])
         })
-        .next();
-
-    // Check if there is a token that starts trimmed ignoring case with disc
-    if disk_number.is_none() {
-        disk_number = tokens
-            .iter()
-            .filter_map(|token| {
-                if token.trim().to_lowercase().starts_with("disc") {
-                    token
-                        .trim()
-                        .to_lowercase()
-                        .strip_prefix("disc")
-                        .unwrap()
-                        .parse::<usize>()
-                        .ok()
-                } else {
-                    None
-                }
-            })
-            .next();
-    }
-
-    // If we still have no disk number check the first token, if it is a number
-    if disk_number.is_none() {
-        // Strip 0 chars from string, and take only the first char
-        let first_token = tokens[0]
-            .replace("0", "")
-            .trim()
-            .chars()
-            .next()
-            .unwrap()
-            .to_string();
-        if let Ok(number) = first_token.parse::<usize>() {
-            disk_number = Some(number);
-        };
-    };
+        .max()
+        .unwrap_or(0);
+    highest_track_number.to_string().len().max(2)
+}
 
-    // If we have still no disk number check if the cue file name contains a number
-    if disk_number.is_none() {
-        let number = cue_file_name
-            .chars()
-            .filter(|c| c.is_numeric())
-            .collect::<String>();
-        if let Ok(number) = number.parse::<usize>() {
-            disk_number = Some(number);
+/// Formats the track number for the output file name, padded to the given width
+/// If `disc_prefix` is set and the release is a multi-disc release, the disc number is prepended (e.g. 101, 213)
+fn format_track_number(
+    cue_sheet: &CueSheet,
+    track: &Track,
+    width: usize,
+    disc_prefix: bool,
+) -> String {
+    match cue_sheet.disc_number {
+        Some(disc_number) if disc_prefix => {
+            format!("{}{:0width$}", disc_number, track.number, width = width)
         }
+        _ => format!("{:0width$}", track.number, width = width),
     }
-
-    // as default, use 1
-    disk_number.unwrap_or(1)
 }
 
-/// Finds the best delimiter in the file name
-fn find_best_delimiter(file_name: &str) -> String {
-    lazy_static! {
-        static ref DELIMITERS: Vec<&'static str> = vec!["-", "_", " ", "."];
-    }
-
-    let mut best_delimiter = "";
-    let mut best_delimiter_score = 0;
+fn parse_cue_file(
+    cue_file_path: &Path,
+    encoding_override: Option<CueEncoding>,
+) -> Option<CueSheet> {
+    println!();
+    println!("{}", cue_file_path.display());
+    println!("============================================================");
+    println!("📖 Parsing cue file");
 
-    for delimiter in DELIMITERS.iter() {
-        let score = file_name.matches(delimiter).count();
-        if score > best_delimiter_score {
-            best_delimiter = delimiter;
-            best_delimiter_score = score;
+    let decoded_cue = match cue_encoding::read_cue_file(cue_file_path, encoding_override) {
+        Ok(decoded_cue) => decoded_cue,
+        Err(err) => {
+            red_ln!("❌ Could not read cue file: {}", err);
+            return None;
         }
+    };
+    if decoded_cue.uncertain {
+        yellow_ln!(
+            "🔤 Encoding: {}, check the titles or use --cue-encoding",
+            decoded_cue.report
+        );
+    } else {
+        println!("🔤 Encoding: {}", decoded_cue.report);
     }
 
-    best_delimiter.to_string()
-}
+    let mut cue_sheet = parse_cue_text(cue_file_path, &decoded_cue.text);
+    cue_sheet.cue_encoding = decoded_cue.encoding;
 
-/// Determines if the current release is a multidisc release
-/// This is done by checking if there are multiple cue files in the same directory
-/// If there are multiple cue files, the release is considered a multidisc release
-fn is_multi_disc(cue_sheet: &CueSheet) -> bool {
-    let parent_dir = cue_sheet.cue_file_path.parent().unwrap();
-    let cue_files_in_directory: Vec<DirEntry> = parent_dir
-        .read_dir()
-        .unwrap()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().is_file())
-        .filter(|entry| {
-            entry
-                .path()
-                .extension()
-                .unwrap_or("".as_ref())
-                .eq_ignore_ascii_case("cue")
-        })
-        .collect();
+    println!("🎵 Found {} track(s)", cue_sheet.tracks.len());
 
-    cue_files_in_directory.len() > 1
+    Some(cue_sheet)
 }
 
-fn parse_cue_file(cue_file_path: &PathBuf) -> Option<CueSheet> {
-    println!();
-    println!("{}", cue_file_path.display());
-    println!("============================================================");
-    println!("📖 Parsing cue file");
-
-    let file = File::open(cue_file_path).unwrap();
-    let cue_file_content = read_cue_file_content(cue_file_path, file);
-
+/// Parses the content of a cue file
+/// Commands which are not interpreted are kept verbatim, so the cue sheet can be written back without losing anything
+fn parse_cue_text(cue_file_path: &Path, cue_file_content: &str) -> CueSheet {
     let mut audio_file_name = String::new();
+    let mut audio_file_type = String::new();
     let mut tracks = Vec::new();
     let mut current_track = None;
     let mut title = None;
+    let mut performer = None;
+    let mut audio_files: Vec<CueFile> = Vec::new();
+    let mut rem_disc_number = None;
+    let mut rem_total_discs = None;
+    let mut other_commands = Vec::new();
+    let mut invalid_commands = Vec::new();
 
     for line in cue_file_content.lines() {
         let line_split = line.trim().split_once(' ').unwrap_or(("", ""));
@@ -1207,52 +2299,112 @@ fn parse_cue_file(cue_file_path: &PathBuf) -> Option<CueSheet> {
                     let last_index_of_quote = cue_line_value.rfind('\"').unwrap();
                     audio_file_name =
                         cue_line_value[first_index_of_quote + 1..last_index_of_quote].to_string();
+                    audio_file_type = cue_line_value[last_index_of_quote + 1..].trim().to_string();
                 } else {
-                    audio_file_name = cue_line_value
-                        .split_whitespace()
-                        .next()
-                        .unwrap()
-                        .to_string();
+                    let mut file_split = cue_line_value.split_whitespace();
+                    audio_file_name = file_split.next().unwrap_or_default().to_string();
+                    audio_file_type = file_split.next().unwrap_or_default().to_string();
                 }
+                audio_files.push(CueFile {
+                    name: audio_file_name.clone(),
+                    file_type: audio_file_type.clone(),
+                    track_count: 0,
+                    starts_inside_track: current_track
+                        .as_ref()
+                        .is_some_and(|track: &Track| track.start_time.is_none()),
+                });
             }
             "TRACK" => {
                 if let Some(track) = current_track.take() {
                     tracks.push(track);
                 }
 
-                let track_number: u32 = cue_line_value
-                    .split_whitespace()
-                    .next()
-                    .unwrap()
-                    .parse()
-                    .unwrap();
+                if let Some(audio_file) = audio_files.last_mut() {
+                    audio_file.track_count += 1;
+                }
+
+                let mut track_split = cue_line_value.split_whitespace();
+                let previous_track_number = tracks.last().map_or(0, |track: &Track| track.number);
+                let track_number = match track_split.next().and_then(|number| number.parse().ok()) {
+                    Some(track_number) => track_number,
+                    None => {
+                        // Numbered after the previous track, so the following commands still belong to a track
+                        invalid_commands
+                            .push((Some(previous_track_number + 1), line.trim().to_string()));
+                        previous_track_number + 1
+                    }
+                };
+                let track_type = track_split.next().unwrap_or("AUDIO").to_string();
                 current_track = Some(Track {
                     number: track_number,
+                    track_type,
+                    flags: Vec::new(),
                     title: None,
+                    pregap_start_time: None,
                     start_time: None,
+                    other_indexes: Vec::new(),
+                    other_commands: Vec::new(),
                     artist: None,
                     output_file: None,
+                    ffmpeg_args: Vec::new(),
                     ffmpeg_command: None,
                 });
             }
             "TITLE" => {
                 if let Some(ref mut track) = current_track {
-                    track.title = Some(cue_line_value.replace("\"", "").trim().to_string());
+                    track.title = Some(unquote(cue_line_value));
                 } else {
-                    title = Some(cue_line_value.replace("\"", "").trim().to_string());
+                    title = Some(unquote(cue_line_value));
                 }
             }
             "INDEX" => {
                 if let Some(ref mut track) = current_track {
-                    track.start_time = parse_cue_duration(cue_line_value, track);
+                    // INDEX 00 marks the start of the pregap, INDEX 01 the start of the track
+                    let mut index_split = cue_line_value.split_whitespace();
+                    let index_number = index_split
+                        .next()
+                        .and_then(|index_number| index_number.parse::<u32>().ok());
+                    let index_time = index_split.next().and_then(parse_cue_duration);
+                    match (index_number, index_time) {
+                        (Some(0), Some(index_time)) => track.pregap_start_time = Some(index_time),
+                        (Some(1), Some(index_time)) => track.start_time = Some(index_time),
+                        (Some(index_number), Some(index_time)) => {
+                            track.other_indexes.push((index_number, index_time))
+                        }
+                        (Some(_), None) => {
+                            invalid_commands.push((Some(track.number), line.trim().to_string()))
+                        }
+                        (None, _) => track.other_commands.push(line.trim().to_string()),
+                    }
+                }
+            }
+            "FLAGS" => {
+                if let Some(ref mut track) = current_track {
+                    track.flags = cue_line_value
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect();
                 }
             }
             "PERFORMER" => {
                 if let Some(ref mut track) = current_track {
-                    track.artist = Some(cue_line_value.replace("\"", "").trim().to_string());
+                    track.artist = Some(unquote(cue_line_value));
+                } else {
+                    performer = Some(unquote(cue_line_value));
                 }
             }
-            _ => {}
+            "REM" => {
+                let (rem_key, rem_value) =
+                    cue_line_value.trim().split_once(' ').unwrap_or(("", ""));
+                let rem_value = rem_value.replace("\"", "");
+                match rem_key {
+                    "DISCNUMBER" => rem_disc_number = rem_value.trim().parse::<usize>().ok(),
+                    "TOTALDISCS" => rem_total_discs = rem_value.trim().parse::<usize>().ok(),
+                    _ => {}
+                }
+                keep_other_command(&mut current_track, &mut other_commands, line);
+            }
+            _ => keep_other_command(&mut current_track, &mut other_commands, line),
         }
     }
 
@@ -1260,12 +2412,20 @@ fn parse_cue_file(cue_file_path: &PathBuf) -> Option<CueSheet> {
         tracks.push(track);
     }
 
-    println!("🎵 Found {} track(s)", tracks.len());
+    if audio_file_type.is_empty() {
+        audio_file_type = cue_writer::file_type(Path::new(&audio_file_name)).to_string();
+    }
 
-    Some(CueSheet {
+    CueSheet {
         audio_file_name: audio_file_name.clone(),
+        audio_file_type,
+        audio_files,
+        cue_encoding: CueEncoding::utf8(),
         cue_file_path: cue_file_path.to_path_buf(),
         title,
+        performer,
+        rem_disc_number,
+        rem_total_discs,
         audio_file_path: cue_file_path
             .to_path_buf()
             .parent()
@@ -1273,54 +2433,83 @@ fn parse_cue_file(cue_file_path: &PathBuf) -> Option<CueSheet> {
             .join(audio_file_name),
         tracks,
         output_dir: None,
-    })
+        disc_number: None,
+        total_discs: None,
+        source_tags: None,
+        cover: None,
+        audio_codec: None,
+        audio_duration: None,
+        problems: Vec::new(),
+        fixes: Vec::new(),
+        other_commands,
+        invalid_commands,
+    }
 }
 
-fn parse_cue_duration(cue_line_value: &str, track: &mut Track) -> Option<CueDuration> {
-    let cue_duration = cue_line_value.split(' ').last().unwrap();
-    let cue_duration_split: Vec<&str> = cue_duration.split(':').collect();
-    if cue_duration_split.len() != 3 {
-        eprintln!(
-            "❌ Invalid cue duration: {} of track {}",
-            cue_duration, track.number
-        );
-        std::process::exit(1);
-    }
-    let minutes = cue_duration_split[0].parse::<u32>().unwrap();
-    let seconds = cue_duration_split[1].parse::<u32>().unwrap();
-    let frames = cue_duration_split[2].parse::<u32>().unwrap();
-    Some(CueDuration {
-        minutes,
-        seconds,
-        frames,
-    })
+/// Rewrites the cue file as UTF-8
+fn convert_cue_file(
+    cue_sheet: &mut CueSheet,
+    encoding_override: Option<CueEncoding>,
+    conversion: CueConversion,
+) {
+    match cue_encoding::convert_cue_file(&cue_sheet.cue_file_path, encoding_override, conversion) {
+        Ok(Some(backup_file)) => {
+            println!(
+                "🔤 Converted the cue file to UTF-8, the original is kept as {}",
+                backup_file.display()
+            );
+            cue_sheet.fixes.push(format!(
+                "Converted the cue file from {} to UTF-8",
+                cue_sheet.cue_encoding.name()
+            ));
+            cue_sheet.cue_encoding = CueEncoding::utf8();
+        }
+        Ok(None) => {}
+        Err(err) => yellow_ln!("⚠️ Could not convert the cue file to UTF-8: {}", err),
+    }
 }
 
-fn read_cue_file_content(cue_file_path: &Path, file: File) -> String {
-    // Read file content
-    let mut data_buffer: Vec<u8> = Vec::new();
-    let mut cue_file = BufReader::new(file);
-    cue_file.read_to_end(&mut data_buffer).unwrap();
-
-    // Detect encoding and convert to utf8
-    let detected_encoding = chardet::detect(&data_buffer);
-    let encoding_ref =
-        encoding::label::encoding_from_whatwg_label(charset2encoding(&detected_encoding.0));
-    if let Some(encoding_ref) = encoding_ref {
-        encoding_ref
-            .decode(&data_buffer, DecoderTrap::Ignore)
-            .unwrap_or_else(|_| {
-                panic!(
-                    "{}",
-                    format!("❌ Could not decode cue file {}", cue_file_path.display())
-                )
-            })
-    } else {
-        panic!(
-            "{}",
-            format!("❌ Could not decode cue file {}", cue_file_path.display())
-        );
+/// Keeps a command we do not interpret, at track level once the first track started
+/// Reads a value, which may be quoted
+/// Cue sheets have no escaping, so everything between the outer quotes is the value, e.g. "12" Mix"
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted_value) => quoted_value.trim().to_string(),
+        None => value.replace('"', "").trim().to_string(),
+    }
+}
+
+fn keep_other_command(
+    current_track: &mut Option<Track>,
+    other_commands: &mut Vec<String>,
+    line: &str,
+) {
+    match current_track {
+        Some(track) => track.other_commands.push(line.trim().to_string()),
+        None => other_commands.push(line.trim().to_string()),
+    }
+}
+
+/// Parses a cue duration: mm:ss:ff, None if it is malformed or the seconds or frames are out of range
+fn parse_cue_duration(cue_duration: &str) -> Option<CueDuration> {
+    let mut cue_duration_split = cue_duration.split(':');
+    let mut next_number = || cue_duration_split.next()?.parse::<u32>().ok();
+    let cue_duration = CueDuration {
+        minutes: next_number()?,
+        seconds: next_number()?,
+        frames: next_number()?,
+    };
+    if cue_duration_split.next().is_some()
+        || cue_duration.seconds >= 60
+        || cue_duration.frames >= 75
+    {
+        return None;
     }
+    Some(cue_duration)
 }
 
 fn delete_original_audio_files(cue_sheets: Vec<CueSheet>) {
@@ -0,0 +1,233 @@
+use crate::config::{self, Config};
+use crate::cover;
+use crate::cue_encoding::{CueConversion, CueEncoding};
+use crate::output::{OutputFormat, OutputTemplate};
+use crate::tags::TagPrecedence;
+use crate::{is_non_interactive, InvalidCuePolicy, DEFAULT_ACCEPT_SCORE};
+
+/// Options of the pipeline shared by the commands, which finds, parses and verifies cue sheets
+#[derive(Debug, Clone)]
+pub struct CueOptions {
+    pub cue_encoding: Option<CueEncoding>,
+    pub convert_cue: Option<CueConversion>,
+    pub save_fixes: bool,
+    pub auto_fix_score: Option<usize>,
+    /// Score above which a similar audio file is the default answer
+    pub accept_score: usize,
+    pub non_interactive: bool,
+    pub on_invalid_cue: InvalidCuePolicy,
+    /// Accepts cue sheets whose audio file is gone, e.g. tag runs after split moved or deleted it
+    pub allow_missing_audio_file: bool,
+}
+
+/// Options deciding the names and tags of the split tracks, shared by split and tag
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    pub disc_prefix: bool,
+    pub tag_precedence: TagPrecedence,
+    pub no_cover: bool,
+    pub cover_names: String,
+    pub cover_max_size: Option<u32>,
+    /// The file name template of the tracks, None for "<number> <artist> - <title>"
+    pub output_template: Option<OutputTemplate>,
+    /// The format to convert the tracks to, None to keep the codec of the source audio file
+    pub format: Option<OutputFormat>,
+}
+
+/// The command line options of the cue pipeline, as given to split, fix or tag
+/// Commands without some of the options leave them at their defaults
+#[derive(Debug, Default, Clone)]
+pub struct CueArgs {
+    pub cue_encoding: Option<CueEncoding>,
+    pub convert_cue: Option<CueConversion>,
+    pub save_fixes: bool,
+    pub no_save_fixes: bool,
+    pub auto_fix_score: Option<usize>,
+    pub accept_score: Option<usize>,
+    pub yes: bool,
+    pub non_interactive: bool,
+    pub interactive: bool,
+    pub on_invalid_cue: Option<InvalidCuePolicy>,
+}
+
+impl CueArgs {
+    /// Takes the options which are not given on the command line from the config files
+    /// A switch enabled in a config file is disabled by its negating switch, e.g. --interactive
+    pub fn resolve(&self, config: &Config) -> CueOptions {
+        let non_interactive = config::resolve_switch(
+            self.non_interactive,
+            self.interactive,
+            config.non_interactive,
+        );
+        CueOptions {
+            cue_encoding: self.cue_encoding.or(config.cue_encoding),
+            convert_cue: self.convert_cue.or(config.convert_cue),
+            save_fixes: config::resolve_switch(
+                self.save_fixes,
+                self.no_save_fixes,
+                config.save_fixes,
+            ),
+            auto_fix_score: self.auto_fix_score.or(config.auto_fix_score),
+            accept_score: self
+                .accept_score
+                .or(config.accept_score)
+                .unwrap_or(DEFAULT_ACCEPT_SCORE),
+            non_interactive: is_non_interactive(self.yes, non_interactive),
+            on_invalid_cue: self
+                .on_invalid_cue
+                .or(config.on_invalid_cue)
+                .unwrap_or(InvalidCuePolicy::Skip),
+            allow_missing_audio_file: false,
+        }
+    }
+}
+
+/// The command line options of the track names and tags, as given to split or tag
+#[derive(Debug, Default, Clone)]
+pub struct OutputArgs {
+    pub disc_prefix: bool,
+    pub no_disc_prefix: bool,
+    pub tag_precedence: Option<TagPrecedence>,
+    pub no_cover: bool,
+    pub cover: bool,
+    pub cover_names: Option<String>,
+    pub cover_max_size: Option<u32>,
+    pub output_template: Option<OutputTemplate>,
+    pub format: Option<OutputFormat>,
+}
+
+impl OutputArgs {
+    /// Takes the options which are not given on the command line from the config files
+    /// A switch enabled in a config file is disabled by its negating switch, e.g. --no-disc-prefix
+    pub fn resolve(&self, config: &Config) -> OutputOptions {
+        OutputOptions {
+            disc_prefix: config::resolve_switch(
+                self.disc_prefix,
+                self.no_disc_prefix,
+                config.disc_prefix,
+            ),
+            tag_precedence: self
+                .tag_precedence
+                .or(config.tag_precedence)
+                .unwrap_or_default(),
+            no_cover: config::resolve_switch(self.no_cover, self.cover, config.no_cover),
+            cover_names: self
+                .cover_names
+                .clone()
+                .or(config.cover_names.clone())
+                .unwrap_or_else(|| cover::DEFAULT_COVER_NAMES.to_string()),
+            cover_max_size: self.cover_max_size.or(config.cover_max_size),
+            output_template: self
+                .output_template
+                .clone()
+                .or(config.output_template.clone()),
+            format: self.format.or(config.format),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn cue_options_fall_back_to_the_defaults() {
+        let cue_options = CueArgs::default().resolve(&Config::default());
+        assert!(cue_options.cue_encoding.is_none());
+        assert_eq!(cue_options.convert_cue, None);
+        assert!(!cue_options.save_fixes);
+        assert_eq!(cue_options.auto_fix_score, None);
+        assert_eq!(cue_options.accept_score, DEFAULT_ACCEPT_SCORE);
+        assert_eq!(cue_options.on_invalid_cue, InvalidCuePolicy::Skip);
+        assert!(!cue_options.allow_missing_audio_file);
+    }
+
+    #[test]
+    fn cue_options_are_taken_from_the_config() {
+        let config = config(
+            "save_fixes = true\nauto_fix_score = 95\naccept_score = 80\non_invalid_cue = \"abort\"\n\
+             cue_encoding = \"windows-1251\"\nconvert_cue = \"utf8-bom\"\n",
+        );
+        let cue_options = CueArgs::default().resolve(&config);
+        assert!(cue_options.save_fixes);
+        assert_eq!(cue_options.auto_fix_score, Some(95));
+        assert_eq!(cue_options.accept_score, 80);
+        assert_eq!(cue_options.on_invalid_cue, InvalidCuePolicy::Abort);
+        assert_eq!(cue_options.cue_encoding.unwrap().name(), "windows-1251");
+        assert_eq!(cue_options.convert_cue, Some(CueConversion::Utf8Bom));
+    }
+
+    #[test]
+    fn command_line_cue_options_win_over_the_config() {
+        let config = config("save_fixes = true\naccept_score = 80\non_invalid_cue = \"abort\"\n");
+        let cue_args = CueArgs {
+            no_save_fixes: true,
+            accept_score: Some(60),
+            on_invalid_cue: Some(InvalidCuePolicy::Skip),
+            ..CueArgs::default()
+        };
+        let cue_options = cue_args.resolve(&config);
+        assert!(!cue_options.save_fixes);
+        assert_eq!(cue_options.accept_score, 60);
+        assert_eq!(cue_options.on_invalid_cue, InvalidCuePolicy::Skip);
+    }
+
+    #[test]
+    fn output_options_fall_back_to_the_defaults() {
+        let output_options = OutputArgs::default().resolve(&Config::default());
+        assert!(!output_options.disc_prefix);
+        assert_eq!(output_options.tag_precedence, TagPrecedence::default());
+        assert!(!output_options.no_cover);
+        assert_eq!(output_options.cover_names, cover::DEFAULT_COVER_NAMES);
+        assert_eq!(output_options.cover_max_size, None);
+    }
+
+    #[test]
+    fn command_line_output_options_win_over_the_config() {
+        let config = config(
+            "disc_prefix = true\nno_cover = true\ncover_names = \"front\"\ncover_max_size = 500\n",
+        );
+        assert!(OutputArgs::default().resolve(&config).disc_prefix);
+        assert!(OutputArgs::default().resolve(&config).no_cover);
+
+        let output_args = OutputArgs {
+            no_disc_prefix: true,
+            cover: true,
+            cover_names: Some("folder".to_string()),
+            cover_max_size: Some(1000),
+            ..OutputArgs::default()
+        };
+        let output_options = output_args.resolve(&config);
+        assert!(!output_options.disc_prefix);
+        assert!(!output_options.no_cover);
+        assert_eq!(output_options.cover_names, "folder");
+        assert_eq!(output_options.cover_max_size, Some(1000));
+    }
+
+    #[test]
+    fn command_line_output_template_and_format_win_over_the_config() {
+        let config = config("output_template = \"{album}/{number}\"\nformat = \"mp3\"\n");
+        let output_options = OutputArgs::default().resolve(&config);
+        assert_eq!(
+            output_options.output_template,
+            Some("{album}/{number}".parse().unwrap())
+        );
+        assert_eq!(output_options.format, Some(OutputFormat::Mp3));
+
+        let output_args = OutputArgs {
+            output_template: Some("{number} {title}".parse().unwrap()),
+            format: Some(OutputFormat::Opus),
+            ..OutputArgs::default()
+        };
+        let output_options = output_args.resolve(&config);
+        assert_eq!(
+            output_options.output_template,
+            Some("{number} {title}".parse().unwrap())
+        );
+        assert_eq!(output_options.format, Some(OutputFormat::Opus));
+    }
+}
@@ -0,0 +1,261 @@
+use std::str::FromStr;
+
+/// Placeholders which can be used in output templates
+const TEMPLATE_PLACEHOLDERS: [&str; 6] =
+    ["number", "title", "artist", "album", "album_artist", "disc"];
+
+/// Audio formats the split tracks can be converted to
+/// Without a format, the tracks keep the codec of the source audio file
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    Flac,
+    /// Apple Lossless in an MP4 container
+    Alac,
+    /// VBR V0 encoded with LAME
+    Mp3,
+    /// 160 kbit/s encoded with libopus
+    Opus,
+}
+
+impl OutputFormat {
+    /// The ffmpeg arguments to encode into this format
+    /// Embedded cover art is left out, as not every container can hold it, it is added again when tagging
+    pub fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            OutputFormat::Flac => &["-vn", "-c:a", "flac"],
+            OutputFormat::Alac => &["-vn", "-c:a", "alac"],
+            OutputFormat::Mp3 => &["-vn", "-c:a", "libmp3lame", "-q:a", "0"],
+            OutputFormat::Opus => &["-vn", "-c:a", "libopus", "-b:a", "160k"],
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Flac => "flac",
+            OutputFormat::Alac => "m4a",
+            OutputFormat::Mp3 => "mp3",
+            OutputFormat::Opus => "opus",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "flac" => Ok(OutputFormat::Flac),
+            "alac" => Ok(OutputFormat::Alac),
+            "mp3" => Ok(OutputFormat::Mp3),
+            "opus" => Ok(OutputFormat::Opus),
+            _ => Err(format!(
+                "unknown output format '{}', expected one of: flac, alac, mp3, opus",
+                value
+            )),
+        }
+    }
+}
+
+/// Template for the file names of the split tracks, without extension, e.g. "{number} - {title}"
+/// A `/` creates sub folders, e.g. "{album_artist}/{album}/{number} {title}"
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputTemplate(String);
+
+impl OutputTemplate {
+    /// Replaces the placeholders with the values returned for their names
+    pub fn render(&self, value_of: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            // The template was validated, every placeholder is closed
+            let end = start + rest[start..].find('}').unwrap();
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(&value_of(&rest[start + 1..end]));
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.trim().is_empty() {
+            return Err("the output template is empty".to_string());
+        }
+        if value.starts_with('/') || value.split('/').any(|part| part.trim() == "..") {
+            return Err(format!(
+                "the output template '{}' must be relative to the folder of the audio file",
+                value
+            ));
+        }
+
+        let mut rest = value;
+        while let Some(start) = rest.find('{') {
+            let Some(length) = rest[start..].find('}') else {
+                return Err(format!(
+                    "unclosed placeholder in output template '{}'",
+                    value
+                ));
+            };
+            let placeholder = &rest[start + 1..start + length];
+            if !TEMPLATE_PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder '{{{}}}' in output template, expected one of: {}",
+                    placeholder,
+                    TEMPLATE_PLACEHOLDERS
+                        .map(|name| format!("{{{}}}", name))
+                        .join(", ")
+                ));
+            }
+            rest = &rest[start + length + 1..];
+        }
+        Ok(OutputTemplate(value.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::options::OutputArgs;
+    use crate::{build_ffmpeg_command, build_output_name, parse_cue_text};
+
+    const CUE_TEXT: &str = r#"PERFORMER "Artist"
+TITLE "Album: Live"
+FILE "album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "First/Second"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE ".."
+    PERFORMER "Guest"
+    INDEX 01 03:00:00
+"#;
+
+    fn output_names(output_args: OutputArgs) -> Vec<String> {
+        let cue_sheet = parse_cue_text(Path::new("/music/album.cue"), CUE_TEXT);
+        let output_options = output_args.resolve(&Config::default());
+        cue_sheet
+            .tracks
+            .iter()
+            .map(|track| build_output_name(&cue_sheet, track, 2, &output_options))
+            .collect()
+    }
+
+    #[test]
+    fn parses_output_formats() {
+        assert_eq!("opus".parse(), Ok(OutputFormat::Opus));
+        assert_eq!(OutputFormat::Alac.extension(), "m4a");
+        assert!("wav".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let output_template: OutputTemplate =
+            "{album_artist}/{album}/{number} - {title}".parse().unwrap();
+        let rendered = output_template.render(|placeholder| placeholder.to_uppercase());
+        assert_eq!(rendered, "ALBUM_ARTIST/ALBUM/NUMBER - TITLE");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!("".parse::<OutputTemplate>().is_err());
+        assert!("/music/{title}".parse::<OutputTemplate>().is_err());
+        assert!("../{title}".parse::<OutputTemplate>().is_err());
+        assert!("{album}/../{title}".parse::<OutputTemplate>().is_err());
+        assert!("{number".parse::<OutputTemplate>().is_err());
+        assert!("{year} {title}".parse::<OutputTemplate>().is_err());
+        assert!("{album}/..{title}".parse::<OutputTemplate>().is_ok());
+    }
+
+    #[test]
+    fn names_tracks_after_number_artist_and_title_by_default() {
+        assert_eq!(
+            output_names(OutputArgs::default()),
+            [
+                "/music/./01 First-Second.flac",
+                "/music/./02 Guest - ...flac"
+            ]
+        );
+    }
+
+    #[test]
+    fn names_tracks_after_the_output_template_and_format() {
+        let output_args = OutputArgs {
+            output_template: Some(
+                "{album_artist}/{album}/{number} {artist} - {title}"
+                    .parse()
+                    .unwrap(),
+            ),
+            format: Some(OutputFormat::Alac),
+            ..OutputArgs::default()
+        };
+        assert_eq!(
+            output_names(output_args),
+            [
+                "/music/./Artist/Album- Live/01 Artist - First-Second.m4a",
+                "/music/./Artist/Album- Live/02 Guest - __.m4a"
+            ]
+        );
+    }
+
+    #[test]
+    fn values_can_not_leave_the_folder_of_the_audio_file() {
+        let output_args = OutputArgs {
+            output_template: Some("{title}/{number}".parse().unwrap()),
+            ..OutputArgs::default()
+        };
+        assert_eq!(
+            output_names(output_args),
+            ["/music/./First-Second/01.flac", "/music/./__/02.flac"]
+        );
+    }
+
+    fn codec_args(output_codec: &str, output_args: OutputArgs) -> Vec<String> {
+        let mut cue_sheet = parse_cue_text(Path::new("/music/album.cue"), CUE_TEXT);
+        let output_options = output_args.resolve(&Config::default());
+        cue_sheet.tracks[0].output_file = Some("/music/01.flac".into());
+        let track = &cue_sheet.tracks[0];
+        let track = build_ffmpeg_command(&cue_sheet, 0, track, output_codec, &output_options);
+        let args: Vec<String> = track
+            .ffmpeg_args
+            .iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let codec_start = args.iter().position(|arg| arg == "-to").unwrap() + 2;
+        args[codec_start..args.len() - 1].to_vec()
+    }
+
+    #[test]
+    fn reencodes_lossless_and_pcm_tracks() {
+        assert_eq!(codec_args("flac", OutputArgs::default()), ["-c:a", "flac"]);
+        assert_eq!(codec_args("alac", OutputArgs::default()), ["-c:a", "alac"]);
+        assert_eq!(
+            codec_args("pcm_s16le", OutputArgs::default()),
+            ["-c:a", "pcm_s16le"]
+        );
+        assert_eq!(
+            codec_args("pcm_s24be", OutputArgs::default()),
+            ["-c:a", "pcm_s24be"]
+        );
+        assert_eq!(codec_args("mp3", OutputArgs::default()), ["-c:a", "copy"]);
+        assert_eq!(codec_args("", OutputArgs::default()), ["-c:a", "copy"]);
+    }
+
+    #[test]
+    fn encodes_into_the_output_format() {
+        let output_args = OutputArgs {
+            format: Some(OutputFormat::Mp3),
+            ..OutputArgs::default()
+        };
+        assert_eq!(
+            codec_args("pcm_s16le", output_args),
+            ["-vn", "-c:a", "libmp3lame", "-q:a", "0"]
+        );
+    }
+}
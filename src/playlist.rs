@@ -0,0 +1,446 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use colour::yellow_ln;
+use encoding::all::WINDOWS_1252;
+use encoding::{EncoderTrap, Encoding};
+
+use crate::release::Release;
+use crate::{read_audio_playtime, CueSheet};
+
+/// Supported playlist formats
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlaylistFormat {
+    M3u8,
+    M3u,
+    Xspf,
+    Pls,
+}
+
+impl PlaylistFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "m3u8",
+            PlaylistFormat::M3u => "m3u",
+            PlaylistFormat::Xspf => "xspf",
+            PlaylistFormat::Pls => "pls",
+        }
+    }
+}
+
+impl FromStr for PlaylistFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "m3u8" => Ok(PlaylistFormat::M3u8),
+            "m3u" => Ok(PlaylistFormat::M3u),
+            "xspf" => Ok(PlaylistFormat::Xspf),
+            "pls" => Ok(PlaylistFormat::Pls),
+            _ => Err(format!(
+                "unknown playlist format '{}', expected one of: m3u8, m3u, xspf, pls",
+                value
+            )),
+        }
+    }
+}
+
+/// A single playlist entry, the path is relative to the playlist file
+#[derive(Debug, Clone)]
+struct PlaylistEntry {
+    path: PathBuf,
+    duration_millis: Option<u32>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: u32,
+}
+
+impl PlaylistEntry {
+    fn display_title(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.to_string(),
+            _ => self.path.file_stem().unwrap().to_string_lossy().to_string(),
+        }
+    }
+}
+
+/// Writes one playlist per cue sheet into its output directory
+/// Multi-disc releases get an additional playlist spanning all discs
+pub fn write_playlists(releases: &[Release], format: PlaylistFormat) {
+    println!("📃 Writing playlists");
+    for release in releases {
+        for cue_sheet in &release.discs {
+            let output_dir = cue_sheet.output_dir.as_ref().unwrap();
+            let name = playlist_name(cue_sheet.title.as_deref(), &cue_sheet.cue_file_path);
+            write_playlist(output_dir, &name, std::slice::from_ref(cue_sheet), format);
+        }
+
+        if release.discs.len() > 1 {
+            let output_dirs: Vec<&Path> = release
+                .discs
+                .iter()
+                .map(|cue_sheet| cue_sheet.output_dir.as_deref().unwrap())
+                .collect();
+            let release_dir = common_ancestor(&output_dirs);
+            let name = playlist_name(release.title.as_deref(), &release.discs[0].cue_file_path);
+            write_playlist(&release_dir, &name, &release.discs, format);
+        }
+    }
+}
+
+fn write_playlist(
+    playlist_dir: &Path,
+    name: &str,
+    cue_sheets: &[CueSheet],
+    format: PlaylistFormat,
+) {
+    let entries: Vec<PlaylistEntry> = cue_sheets
+        .iter()
+        .flat_map(|cue_sheet| build_entries(playlist_dir, cue_sheet))
+        .collect();
+
+    let playlist_file = playlist_dir.join(format!("{}.{}", name, format.extension()));
+    let content = match format {
+        PlaylistFormat::M3u8 => build_m3u(&entries).into_bytes(),
+        // Plain m3u files are expected to be Latin-1 encoded
+        PlaylistFormat::M3u => WINDOWS_1252
+            .encode(&build_m3u(&entries), EncoderTrap::Replace)
+            .unwrap(),
+        PlaylistFormat::Xspf => build_xspf(name, &entries).into_bytes(),
+        PlaylistFormat::Pls => build_pls(&entries).into_bytes(),
+    };
+
+    match fs::write(&playlist_file, content) {
+        Ok(_) => println!("📃 Wrote playlist: {}", playlist_file.display()),
+        Err(err) => yellow_ln!(
+            "⚠️ Could not write playlist {}: {}",
+            playlist_file.display(),
+            err
+        ),
+    }
+}
+
+/// Builds the playlist entries of a cue sheet
+/// The duration of each track is derived from the cue timings, the last track ends with the source audio file
+fn build_entries(playlist_dir: &Path, cue_sheet: &CueSheet) -> Vec<PlaylistEntry> {
+    let audio_file_millis =
+        read_audio_playtime(&cue_sheet.audio_file_path).map(|seconds| seconds * 1000);
+
+    cue_sheet
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(index, track)| {
+            let start_millis = track
+                .start_time
+                .map(|start| start.total_frames() * 1000 / 75);
+            let end_millis = match cue_sheet.tracks.get(index + 1) {
+                Some(next_track) => next_track
+                    .start_time
+                    .map(|start| start.total_frames() * 1000 / 75),
+                None => audio_file_millis,
+            };
+            let duration_millis = match (start_millis, end_millis) {
+                (Some(start), Some(end)) if end > start => Some(end - start),
+                _ => None,
+            };
+
+            let output_file = track.output_file.as_ref().unwrap();
+            PlaylistEntry {
+                path: relative_path(playlist_dir, output_file),
+                duration_millis,
+                title: track.title.clone(),
+                artist: track.artist.clone().or(cue_sheet.performer.clone()),
+                album: cue_sheet.title.clone(),
+                track_number: track.number,
+            }
+        })
+        .collect()
+}
+
+fn build_m3u(entries: &[PlaylistEntry]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for entry in entries {
+        let seconds = entry
+            .duration_millis
+            .map_or(-1, |millis| (millis / 1000) as i64);
+        playlist.push_str(&format!("#EXTINF:{},{}\n", seconds, entry.display_title()));
+        playlist.push_str(&format!("{}\n", entry.path.display()));
+    }
+    playlist
+}
+
+fn build_pls(entries: &[PlaylistEntry]) -> String {
+    let mut playlist = String::from("[playlist]\n");
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        let seconds = entry
+            .duration_millis
+            .map_or(-1, |millis| (millis / 1000) as i64);
+        playlist.push_str(&format!("File{}={}\n", number, entry.path.display()));
+        playlist.push_str(&format!("Title{}={}\n", number, entry.display_title()));
+        playlist.push_str(&format!("Length{}={}\n", number, seconds));
+    }
+    playlist.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+    playlist
+}
+
+fn build_xspf(name: &str, entries: &[PlaylistEntry]) -> String {
+    let mut playlist = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    playlist.push_str(&format!("  <title>{}</title>\n", escape_xml(name)));
+    playlist.push_str("  <trackList>\n");
+    for entry in entries {
+        playlist.push_str("    <track>\n");
+        playlist.push_str(&format!(
+            "      <location>{}</location>\n",
+            escape_xml(&encode_uri_path(&entry.path))
+        ));
+        if let Some(ref title) = entry.title {
+            playlist.push_str(&format!("      <title>{}</title>\n", escape_xml(title)));
+        }
+        if let Some(ref artist) = entry.artist {
+            playlist.push_str(&format!(
+                "      <creator>{}</creator>\n",
+                escape_xml(artist)
+            ));
+        }
+        if let Some(ref album) = entry.album {
+            playlist.push_str(&format!("      <album>{}</album>\n", escape_xml(album)));
+        }
+        playlist.push_str(&format!(
+            "      <trackNum>{}</trackNum>\n",
+            entry.track_number
+        ));
+        if let Some(duration_millis) = entry.duration_millis {
+            playlist.push_str(&format!("      <duration>{}</duration>\n", duration_millis));
+        }
+        playlist.push_str("    </track>\n");
+    }
+    playlist.push_str("  </trackList>\n</playlist>\n");
+    playlist
+}
+
+/// Uses the album title as playlist name, falls back to the cue file name
+fn playlist_name(title: Option<&str>, cue_file_path: &Path) -> String {
+    let name = title.map(str::to_string).unwrap_or_else(|| {
+        cue_file_path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    });
+
+    // Replace invalid characters
+    name.replace(['/', '\\', ':'], "-")
+        .replace('`', "'")
+        .trim()
+        .to_string()
+}
+
+/// Returns the path of the file relative to the given directory
+pub fn relative_path(dir: &Path, file: &Path) -> PathBuf {
+    let dir_components: Vec<Component> = normalized_components(dir);
+    let file_components: Vec<Component> = normalized_components(file);
+
+    let common_length = dir_components
+        .iter()
+        .zip(&file_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative_path = PathBuf::new();
+    for _ in common_length..dir_components.len() {
+        relative_path.push("..");
+    }
+    for component in &file_components[common_length..] {
+        relative_path.push(component);
+    }
+    relative_path
+}
+
+/// Returns the deepest directory that contains all given directories
+pub fn common_ancestor(dirs: &[&Path]) -> PathBuf {
+    let mut ancestor: Vec<Component> = normalized_components(dirs[0]);
+    for dir in &dirs[1..] {
+        let components = normalized_components(dir);
+        let common_length = ancestor
+            .iter()
+            .zip(&components)
+            .take_while(|(a, b)| a == b)
+            .count();
+        ancestor.truncate(common_length);
+    }
+    ancestor.iter().collect()
+}
+
+fn normalized_components(path: &Path) -> Vec<Component<'_>> {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Percent-encodes a relative path for use as URI, path separators are kept
+fn encode_uri_path(path: &Path) -> String {
+    path.components()
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_string_lossy()
+                .bytes()
+                .map(|byte| {
+                    if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                        (byte as char).to_string()
+                    } else {
+                        format!("%{:02X}", byte)
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<PlaylistEntry> {
+        vec![
+            PlaylistEntry {
+                path: PathBuf::from("CD1/01 Intro & Outro.flac"),
+                duration_millis: Some(61_500),
+                title: Some("Intro & Outro".to_string()),
+                artist: Some("Artist".to_string()),
+                album: Some("Album".to_string()),
+                track_number: 1,
+            },
+            PlaylistEntry {
+                path: PathBuf::from("CD1/02 Track.flac"),
+                duration_millis: None,
+                title: None,
+                artist: None,
+                album: None,
+                track_number: 2,
+            },
+        ]
+    }
+
+    #[test]
+    fn parses_playlist_formats() {
+        assert_eq!("m3u8".parse(), Ok(PlaylistFormat::M3u8));
+        assert_eq!("pls".parse(), Ok(PlaylistFormat::Pls));
+        assert!("wpl".parse::<PlaylistFormat>().is_err());
+    }
+
+    #[test]
+    fn builds_m3u_playlists() {
+        assert_eq!(
+            build_m3u(&entries()),
+            "#EXTM3U\n\
+             #EXTINF:61,Artist - Intro & Outro\nCD1/01 Intro & Outro.flac\n\
+             #EXTINF:-1,02 Track\nCD1/02 Track.flac\n"
+        );
+    }
+
+    #[test]
+    fn builds_pls_playlists() {
+        assert_eq!(
+            build_pls(&entries()),
+            "[playlist]\n\
+             File1=CD1/01 Intro & Outro.flac\nTitle1=Artist - Intro & Outro\nLength1=61\n\
+             File2=CD1/02 Track.flac\nTitle2=02 Track\nLength2=-1\n\
+             NumberOfEntries=2\nVersion=2\n"
+        );
+    }
+
+    #[test]
+    fn builds_xspf_playlists() {
+        let playlist = build_xspf("Rock & Roll", &entries());
+        assert!(playlist.contains("  <title>Rock &amp; Roll</title>\n"));
+        assert!(playlist.contains(
+            "    <track>\n\
+             \x20     <location>CD1/01%20Intro%20%26%20Outro.flac</location>\n\
+             \x20     <title>Intro &amp; Outro</title>\n\
+             \x20     <creator>Artist</creator>\n\
+             \x20     <album>Album</album>\n\
+             \x20     <trackNum>1</trackNum>\n\
+             \x20     <duration>61500</duration>\n\
+             \x20   </track>\n"
+        ));
+        assert!(playlist.contains(
+            "    <track>\n\
+             \x20     <location>CD1/02%20Track.flac</location>\n\
+             \x20     <trackNum>2</trackNum>\n\
+             \x20   </track>\n"
+        ));
+    }
+
+    #[test]
+    fn encodes_uri_paths() {
+        assert_eq!(
+            encode_uri_path(Path::new("../Disc 1/01 Café #1.flac")),
+            "../Disc%201/01%20Caf%C3%A9%20%231.flac"
+        );
+    }
+
+    #[test]
+    fn derives_relative_paths() {
+        assert_eq!(
+            relative_path(
+                Path::new("/music/Album"),
+                Path::new("/music/Album/CD1/01.flac")
+            ),
+            PathBuf::from("CD1/01.flac")
+        );
+        assert_eq!(
+            relative_path(
+                Path::new("/music/Album/CD1"),
+                Path::new("/music/Album/CD2/01.flac")
+            ),
+            PathBuf::from("../CD2/01.flac")
+        );
+        assert_eq!(
+            relative_path(Path::new("./Album"), Path::new("Album/./01.flac")),
+            PathBuf::from("01.flac")
+        );
+    }
+
+    #[test]
+    fn finds_the_common_ancestor() {
+        assert_eq!(
+            common_ancestor(&[
+                Path::new("/music/Album/CD1"),
+                Path::new("/music/Album/CD2"),
+                Path::new("/music/Album/./CD3"),
+            ]),
+            PathBuf::from("/music/Album")
+        );
+        assert_eq!(
+            common_ancestor(&[Path::new("/music/Album")]),
+            PathBuf::from("/music/Album")
+        );
+    }
+
+    #[test]
+    fn derives_playlist_names() {
+        assert_eq!(
+            playlist_name(Some("AC/DC: Live `92` "), Path::new("rip.cue")),
+            "AC-DC- Live '92'"
+        );
+        assert_eq!(playlist_name(None, Path::new("/music/rip.cue")), "rip");
+    }
+}
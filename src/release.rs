@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use colour::yellow_ln;
+
+use crate::disc_number::{self, Confidence, DiscNumber};
+use crate::CueSheet;
+
+/// A release (album) consisting of one or more discs, ordered by disc number
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub discs: Vec<CueSheet>,
+}
+
+/// Groups the given cue sheets into releases
+///
+/// Cue sheets are considered discs of the same release if they live in the same release directory
+/// (either the same folder or sibling disc folders like `CD1/` and `CD2/`), share the same album
+/// title and performer, and each of them has a distinct disc number.
+/// The disc number is taken from `REM DISCNUMBER`, the disc folder name or the cue file name,
+/// in this order.
+/// Cue sheets that can not be told apart by their disc number (e.g. one cue sheet per codec or a
+/// stray backup copy) are treated as separate single-disc releases.
+pub fn group_into_releases(cue_sheets: Vec<CueSheet>) -> Vec<Release> {
+    let mut candidates: BTreeMap<(PathBuf, String, String), Vec<CueSheet>> = BTreeMap::new();
+    for cue_sheet in cue_sheets {
+        let key = (
+            release_dir(&cue_sheet.cue_file_path),
+            normalize_album_title(cue_sheet.title.as_deref().unwrap_or_default()),
+            cue_sheet
+                .performer
+                .as_deref()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase(),
+        );
+        candidates.entry(key).or_default().push(cue_sheet);
+    }
+
+    let mut releases = Vec::new();
+    for (_, group) in candidates {
+        let disc_numbers: Vec<Option<DiscNumber>> = group.iter().map(detect_disc_number).collect();
+        let mut distinct_disc_numbers: Vec<usize> = disc_numbers
+            .iter()
+            .flatten()
+            .map(|disc| disc.number)
+            .collect();
+        distinct_disc_numbers.sort();
+        distinct_disc_numbers.dedup();
+
+        let is_multi_disc = group.len() > 1 && distinct_disc_numbers.len() == group.len();
+        if !is_multi_disc {
+            if group.len() > 1 {
+                warn_about_undetected_disc_numbers(&group, &disc_numbers);
+            }
+            releases.extend(group.into_iter().map(single_disc_release));
+            continue;
+        }
+
+        let mut discs: Vec<(DiscNumber, CueSheet)> =
+            disc_numbers.into_iter().flatten().zip(group).collect();
+        discs.sort_by_key(|(disc, _)| disc.number);
+
+        let total_discs = discs
+            .iter()
+            .filter_map(|(disc, _)| disc.total)
+            .max()
+            .unwrap_or(discs.len())
+            .max(discs.len());
+
+        let discs: Vec<CueSheet> = discs
+            .into_iter()
+            .map(|(disc, mut cue_sheet)| {
+                cue_sheet.disc_number = Some(disc.number);
+                cue_sheet.total_discs = Some(total_discs);
+                cue_sheet
+            })
+            .collect();
+
+        releases.push(Release {
+            title: discs[0]
+                .title
+                .as_deref()
+                .map(strip_disc_suffix)
+                .map(str::to_string),
+            performer: discs[0].performer.clone(),
+            discs,
+        });
+    }
+
+    releases
+}
+
+fn single_disc_release(mut cue_sheet: CueSheet) -> Release {
+    // A single cue sheet can still be part of a larger release, if the cue sheet says so
+    if let Some(total_discs) = cue_sheet.rem_total_discs.filter(|total| *total > 1) {
+        cue_sheet.disc_number = cue_sheet.rem_disc_number;
+        cue_sheet.total_discs = Some(total_discs);
+    }
+
+    Release {
+        title: cue_sheet.title.clone(),
+        performer: cue_sheet.performer.clone(),
+        discs: vec![cue_sheet],
+    }
+}
+
+/// Detects the disc number of a cue sheet
+/// `REM DISCNUMBER` wins over the file and folder name
+fn detect_disc_number(cue_sheet: &CueSheet) -> Option<DiscNumber> {
+    if let Some(number) = cue_sheet.rem_disc_number {
+        return Some(DiscNumber {
+            number,
+            total: cue_sheet.rem_total_discs,
+            confidence: Confidence::High,
+        });
+    }
+    disc_number::detect(&cue_sheet.cue_file_path)
+}
+
+/// Tells the user why cue sheets of the same album are not treated as a multi-disc release
+fn warn_about_undetected_disc_numbers(group: &[CueSheet], disc_numbers: &[Option<DiscNumber>]) {
+    for (cue_sheet, disc_number) in group.iter().zip(disc_numbers) {
+        match disc_number {
+            None => yellow_ln!(
+                "⚠️ Could not derive a disc number from {}, treating it as a separate release",
+                cue_sheet.cue_file_path.display()
+            ),
+            Some(disc) => yellow_ln!(
+                "⚠️ Disc number {} of {} is not unique, treating it as a separate release",
+                disc.number,
+                cue_sheet.cue_file_path.display()
+            ),
+        }
+    }
+}
+
+/// Returns the directory that contains the whole release
+/// If the cue file lives in a disc folder like `CD1/`, this is the parent of the disc folder
+fn release_dir(cue_file_path: &Path) -> PathBuf {
+    let cue_dir = cue_file_path.parent().unwrap_or(Path::new("."));
+    if is_disc_folder(cue_dir) {
+        cue_dir.parent().unwrap_or(cue_dir).to_path_buf()
+    } else {
+        cue_dir.to_path_buf()
+    }
+}
+
+/// Checks if the cue file lives in its own disc folder like `CD1/` or `Disc 2/`
+pub fn is_in_disc_folder(cue_file_path: &Path) -> bool {
+    cue_file_path.parent().is_some_and(is_disc_folder)
+}
+
+fn is_disc_folder(dir: &Path) -> bool {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(disc_number::is_disc_folder_name)
+}
+
+/// Normalizes an album title for comparison, so that "Album (CD1)" and "Album [Disc 2]" are equal
+fn normalize_album_title(title: &str) -> String {
+    strip_disc_suffix(title).to_lowercase()
+}
+
+/// Strips disc suffixes like " (CD1)", " - Disc 2" or " [Disk 3]" from an album title
+/// The marker has to be a word of its own, so "Disco 2" or "Blue Discovery 3" are kept
+fn strip_disc_suffix(title: &str) -> &str {
+    let title = title.trim();
+    let lowercase_title = title.to_ascii_lowercase();
+    let suffix_start = ["cd", "disc", "disk"]
+        .iter()
+        .flat_map(|marker| {
+            lowercase_title
+                .match_indices(marker)
+                .map(move |(start, _)| (start, start + marker.len()))
+        })
+        .find(|&(start, end)| is_disc_suffix(&lowercase_title[..start], &lowercase_title[end..]))
+        .map(|(start, _)| start);
+
+    match suffix_start {
+        Some(suffix_start) => title[..suffix_start].trim_end_matches([' ', '-', '_', '(', '[']),
+        None => title,
+    }
+}
+
+/// Checks if a disc marker with the given surroundings ends the title, e.g. "Album (" + "CD" + "1)"
+fn is_disc_suffix(before_marker: &str, after_marker: &str) -> bool {
+    let follows_separator = before_marker.ends_with([' ', '-', '_', '(', '[']);
+    let number = after_marker
+        .trim_start_matches([' ', '.', '_', '-'])
+        .trim_end_matches([')', ']', ' ']);
+    follows_separator && !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue_sheet(cue_file_path: &str, cue_text: &str) -> CueSheet {
+        crate::parse_cue_text(Path::new(cue_file_path), cue_text)
+    }
+
+    fn album(title: &str) -> String {
+        format!(
+            "PERFORMER \"Artist\"\nTITLE \"{}\"\nFILE \"album.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n",
+            title
+        )
+    }
+
+    #[test]
+    fn strips_disc_suffixes() {
+        assert_eq!(strip_disc_suffix("Album (CD1)"), "Album");
+        assert_eq!(strip_disc_suffix("Album - Disc 2"), "Album");
+        assert_eq!(strip_disc_suffix("Album [Disk 3]"), "Album");
+        assert_eq!(strip_disc_suffix("Album CD.4"), "Album");
+        assert_eq!(strip_disc_suffix(" Album "), "Album");
+    }
+
+    #[test]
+    fn keeps_markers_inside_words() {
+        assert_eq!(strip_disc_suffix("Disco 2"), "Disco 2");
+        assert_eq!(strip_disc_suffix("Blue Discovery 3"), "Blue Discovery 3");
+        assert_eq!(strip_disc_suffix("Abcd 2"), "Abcd 2");
+        assert_eq!(strip_disc_suffix("Disc 2 Remixes"), "Disc 2 Remixes");
+    }
+
+    #[test]
+    fn groups_discs_of_the_same_album() {
+        let releases = group_into_releases(vec![
+            cue_sheet("/music/Album/CD2/album.cue", &album("Album (CD2)")),
+            cue_sheet("/music/Album/CD1/album.cue", &album("Album (CD1)")),
+        ]);
+
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].title.as_deref(), Some("Album"));
+        let discs: Vec<(Option<usize>, Option<usize>)> = releases[0]
+            .discs
+            .iter()
+            .map(|disc| (disc.disc_number, disc.total_discs))
+            .collect();
+        assert_eq!(discs, vec![(Some(1), Some(2)), (Some(2), Some(2))]);
+    }
+
+    #[test]
+    fn keeps_albums_with_disc_like_words_apart() {
+        let releases = group_into_releases(vec![
+            cue_sheet("/music/Various/CD1/album.cue", &album("Disco 2")),
+            cue_sheet("/music/Various/CD2/album.cue", &album("Disco 3")),
+        ]);
+
+        assert_eq!(releases.len(), 2);
+        assert!(releases.iter().all(|release| release.discs.len() == 1));
+    }
+
+    #[test]
+    fn treats_discs_without_distinct_numbers_as_separate_releases() {
+        let releases = group_into_releases(vec![
+            cue_sheet("/music/Album/album.cue", &album("Album")),
+            cue_sheet("/music/Album/album (backup).cue", &album("Album")),
+        ]);
+
+        assert_eq!(releases.len(), 2);
+        assert!(releases
+            .iter()
+            .all(|release| release.discs[0].disc_number.is_none()));
+    }
+}
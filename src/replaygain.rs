@@ -0,0 +1,369 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use colour::yellow_ln;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::id3::v2::Id3v2Tag;
+use lofty::ogg::OpusFile;
+use lofty::tag::{ItemKey, Tag, TagExt, TagType};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::CueSheet;
+
+/// ReplayGain 2.0 reference loudness in LUFS
+const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// EBU R128 reference loudness in LUFS, used by the R128_* tags of Opus files
+const R128_REFERENCE_LUFS: f64 = -23.0;
+
+/// Loudness of a track or album measured by the ffmpeg ebur128 filter
+#[derive(Debug, Copy, Clone)]
+pub struct Loudness {
+    pub integrated_lufs: f64,
+    pub true_peak_dbfs: f64,
+}
+
+impl Loudness {
+    fn replaygain_db(&self) -> f64 {
+        REPLAYGAIN_REFERENCE_LUFS - self.integrated_lufs
+    }
+
+    /// Gain in Q7.8 fixed point format, as required by the R128_* tags
+    fn r128_gain(&self) -> i32 {
+        ((R128_REFERENCE_LUFS - self.integrated_lufs) * 256.0).round() as i32
+    }
+
+    /// The true peak as linear amplitude, 1.0 is full scale
+    fn linear_peak(&self) -> f64 {
+        10f64.powf(self.true_peak_dbfs / 20.0)
+    }
+}
+
+/// Measures track and album loudness of all split tracks and writes the gain tags
+/// The full-length source audio file of a cue sheet is measured as album
+pub fn apply_replaygain(cue_sheets: &[CueSheet]) {
+    println!("🔊 Analyzing loudness");
+
+    cue_sheets.par_iter().for_each(|cue_sheet| {
+        let album_loudness = match measure_loudness(&cue_sheet.audio_file_path) {
+            Ok(loudness) => Some(loudness),
+            Err(error_message) => {
+                yellow_ln!(
+                    "⚠️ Could not measure album loudness of {}, only track gain is written\n{}",
+                    cue_sheet.audio_file_path.display(),
+                    error_message
+                );
+                None
+            }
+        };
+
+        let output_files: Vec<PathBuf> = cue_sheet
+            .tracks
+            .iter()
+            .filter_map(|track| track.output_file.clone())
+            .collect();
+
+        let mut report: Vec<String> = output_files
+            .par_iter()
+            .map(|output_file| {
+                let file_name = output_file.file_name().unwrap().to_string_lossy();
+                match measure_loudness(output_file).and_then(|track_loudness| {
+                    write_gain_tags(output_file, &track_loudness, album_loudness.as_ref())
+                        .map(|_| track_loudness)
+                }) {
+                    Ok(loudness) => format_loudness(&file_name, &loudness),
+                    Err(error_message) => format!("\t❌ {}: {}", file_name, error_message),
+                }
+            })
+            .collect();
+
+        if let Some(album_loudness) = album_loudness {
+            report.push(format_loudness("Album", &album_loudness));
+        }
+
+        println!(
+            "🔊 {}\n{}",
+            cue_sheet.cue_file_path.display(),
+            report.join("\n")
+        );
+    });
+}
+
+fn format_loudness(name: &str, loudness: &Loudness) -> String {
+    let clipping_hint = if loudness.true_peak_dbfs > 0.0 {
+        " (clipping)"
+    } else {
+        ""
+    };
+    format!(
+        "\t{}: {:.1} LUFS, gain {:+.2} dB, peak {:.6}{}",
+        name,
+        loudness.integrated_lufs,
+        loudness.replaygain_db(),
+        loudness.linear_peak(),
+        clipping_hint
+    )
+}
+
+/// Measures the integrated loudness and true peak of an audio file
+/// Example call: ffmpeg -nostats -hide_banner -i input.flac -map 0:a:0 -filter:a ebur128=peak=true -f null -
+pub fn measure_loudness(audio_file: &Path) -> Result<Loudness, String> {
+    let output = Command::new("ffmpeg")
+        .args(["-nostats", "-hide_banner", "-i"])
+        .arg(audio_file)
+        .args([
+            "-map",
+            "0:a:0",
+            "-filter:a",
+            "ebur128=peak=true",
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .map_err(|err| format!("Could not run ffmpeg: {}", err))?;
+
+    // The ebur128 filter writes its summary to stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(stderr.to_string());
+    }
+
+    let summary = stderr
+        .rsplit_once("Summary:")
+        .map(|(_, summary)| summary)
+        .ok_or("ffmpeg did not print a loudness summary")?;
+
+    let integrated_lufs = parse_summary_value(summary, "I:").filter(|value| value.is_finite());
+    // A silent track has a true peak of -inf dBFS, which is a linear peak of 0.0
+    let true_peak_dbfs = parse_summary_value(summary, "Peak:").filter(|value| !value.is_nan());
+    match (integrated_lufs, true_peak_dbfs) {
+        (Some(integrated_lufs), Some(true_peak_dbfs)) => Ok(Loudness {
+            integrated_lufs,
+            true_peak_dbfs,
+        }),
+        _ => Err(format!("Could not parse loudness summary: {}", summary)),
+    }
+}
+
+/// Parses a value like "I:         -16.4 LUFS" from the ebur128 summary
+fn parse_summary_value(summary: &str, key: &str) -> Option<f64> {
+    summary
+        .lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse::<f64>().ok())
+}
+
+/// Writes the gain tags without touching the audio data
+/// Opus files get R128_* tags, all other files REPLAYGAIN_* tags
+fn write_gain_tags(
+    audio_file: &Path,
+    track_loudness: &Loudness,
+    album_loudness: Option<&Loudness>,
+) -> Result<(), String> {
+    let tagged_file = lofty::read_from_path(audio_file).map_err(|err| err.to_string())?;
+
+    if tagged_file.file_type() == FileType::Opus {
+        return write_r128_tags(audio_file, track_loudness, album_loudness);
+    }
+
+    let mut tag = tagged_file
+        .primary_tag()
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type()));
+
+    // lofty drops ReplayGain items when converting to ID3v2 (MP3, WAV, AIFF), so they are written as TXXX frames
+    if tag.tag_type() == TagType::Id3v2 {
+        let mut id3v2_tag = Id3v2Tag::from(tag);
+        for (item_key, value) in gain_tag_values(track_loudness, album_loudness) {
+            let description = item_key.map_key(TagType::Id3v2).unwrap_or_default();
+            id3v2_tag.insert_user_text(description.to_string(), value);
+        }
+        return id3v2_tag
+            .save_to_path(audio_file, WriteOptions::default())
+            .map_err(|err| err.to_string());
+    }
+
+    for (item_key, value) in gain_tag_values(track_loudness, album_loudness) {
+        if !tag.insert_text(item_key, value) {
+            return Err(format!(
+                "{:?} tags can not hold ReplayGain values",
+                tag.tag_type()
+            ));
+        }
+    }
+
+    tag.save_to_path(audio_file, WriteOptions::default())
+        .map_err(|err| err.to_string())
+}
+
+/// Formats the REPLAYGAIN_* values, the album values are left out if the album was not measured
+fn gain_tag_values(
+    track_loudness: &Loudness,
+    album_loudness: Option<&Loudness>,
+) -> Vec<(ItemKey, String)> {
+    let mut values = vec![
+        (
+            ItemKey::ReplayGainTrackGain,
+            format!("{:.2} dB", track_loudness.replaygain_db()),
+        ),
+        (
+            ItemKey::ReplayGainTrackPeak,
+            format!("{:.6}", track_loudness.linear_peak()),
+        ),
+    ];
+    if let Some(album_loudness) = album_loudness {
+        values.push((
+            ItemKey::ReplayGainAlbumGain,
+            format!("{:.2} dB", album_loudness.replaygain_db()),
+        ));
+        values.push((
+            ItemKey::ReplayGainAlbumPeak,
+            format!("{:.6}", album_loudness.linear_peak()),
+        ));
+    }
+    values
+}
+
+/// Writes the R128_TRACK_GAIN and R128_ALBUM_GAIN tags to an Opus file
+/// Opus has no peak tags, as its decoder output gain is applied before clipping
+fn write_r128_tags(
+    audio_file: &Path,
+    track_loudness: &Loudness,
+    album_loudness: Option<&Loudness>,
+) -> Result<(), String> {
+    let mut file = File::open(audio_file).map_err(|err| err.to_string())?;
+    let mut opus_file =
+        OpusFile::read_from(&mut file, ParseOptions::new()).map_err(|err| err.to_string())?;
+
+    let vorbis_comments = opus_file.vorbis_comments_mut();
+    vorbis_comments.insert(
+        "R128_TRACK_GAIN".to_string(),
+        track_loudness.r128_gain().to_string(),
+    );
+    if let Some(album_loudness) = album_loudness {
+        vorbis_comments.insert(
+            "R128_ALBUM_GAIN".to_string(),
+            album_loudness.r128_gain().to_string(),
+        );
+    }
+
+    opus_file
+        .save_to_path(audio_file, WriteOptions::default())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = "
+  Integrated loudness:
+    I:         -16.4 LUFS
+    Threshold: -26.7 LUFS
+
+  Loudness range:
+    LRA:         6.1 LU
+
+  True peak:
+    Peak:        0.5 dBFS";
+
+    fn loudness(integrated_lufs: f64, true_peak_dbfs: f64) -> Loudness {
+        Loudness {
+            integrated_lufs,
+            true_peak_dbfs,
+        }
+    }
+
+    /// A second of 16 bit stereo silence as WAV file
+    fn silent_wav() -> Vec<u8> {
+        let data_length: u32 = 44100 * 4;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&44100u32.to_le_bytes());
+        wav.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        wav.extend_from_slice(&4u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_length.to_le_bytes());
+        wav.resize(wav.len() + data_length as usize, 0);
+        wav
+    }
+
+    #[test]
+    fn parses_the_ebur128_summary() {
+        assert_eq!(parse_summary_value(SUMMARY, "I:"), Some(-16.4));
+        assert_eq!(parse_summary_value(SUMMARY, "Peak:"), Some(0.5));
+        assert_eq!(parse_summary_value(SUMMARY, "LRA:"), Some(6.1));
+        assert_eq!(parse_summary_value(SUMMARY, "M:"), None);
+        assert_eq!(
+            parse_summary_value("    Peak:       -inf dBFS", "Peak:"),
+            Some(f64::NEG_INFINITY)
+        );
+    }
+
+    #[test]
+    fn calculates_gains_relative_to_the_reference_loudness() {
+        let track_loudness = loudness(-16.4, -1.0);
+        assert!((track_loudness.replaygain_db() - -1.6).abs() < 1e-9);
+        // -6.6 dB in Q7.8 fixed point
+        assert_eq!(track_loudness.r128_gain(), -1690);
+        assert!((track_loudness.linear_peak() - 0.891251).abs() < 1e-6);
+        assert_eq!(loudness(-70.0, f64::NEG_INFINITY).linear_peak(), 0.0);
+    }
+
+    #[test]
+    fn formats_gain_tag_values() {
+        assert_eq!(
+            gain_tag_values(&loudness(-16.4, -1.0), Some(&loudness(-20.0, 0.5))),
+            vec![
+                (ItemKey::ReplayGainTrackGain, "-1.60 dB".to_string()),
+                (ItemKey::ReplayGainTrackPeak, "0.891251".to_string()),
+                (ItemKey::ReplayGainAlbumGain, "2.00 dB".to_string()),
+                (ItemKey::ReplayGainAlbumPeak, "1.059254".to_string()),
+            ]
+        );
+        assert_eq!(gain_tag_values(&loudness(-16.4, -1.0), None).len(), 2);
+    }
+
+    #[test]
+    fn formats_loudness_with_a_clipping_hint() {
+        assert_eq!(
+            format_loudness("01 Track.flac", &loudness(-16.4, -1.0)),
+            "\t01 Track.flac: -16.4 LUFS, gain -1.60 dB, peak 0.891251"
+        );
+        assert_eq!(
+            format_loudness("Album", &loudness(-8.0, 0.5)),
+            "\tAlbum: -8.0 LUFS, gain -10.00 dB, peak 1.059254 (clipping)"
+        );
+    }
+
+    #[test]
+    fn writes_gain_tags_to_wav_files() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let wav_file = temp_dir.path().join("01 Track.wav");
+        std::fs::write(&wav_file, silent_wav()).unwrap();
+
+        write_gain_tags(&wav_file, &loudness(-16.4, -1.0), None).unwrap();
+
+        let tagged_file = lofty::read_from_path(&wav_file).unwrap();
+        let tag = tagged_file.primary_tag().unwrap();
+        assert_eq!(
+            tag.get_string(ItemKey::ReplayGainTrackGain),
+            Some("-1.60 dB")
+        );
+        assert_eq!(
+            tag.get_string(ItemKey::ReplayGainTrackPeak),
+            Some("0.891251")
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::fd::FromRawFd;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use colour::red_ln;
+use serde_json::{json, Value};
+
+use crate::release::Release;
+use crate::{CueDuration, CueSheet, SkippedCueFile, Track, TrackResult};
+
+/// Version of the report schema, increased on incompatible changes
+/// Fields may be added without increasing it
+const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// The original stdout, once the rest of the output was redirected to stderr
+static REPORT_STDOUT: OnceLock<File> = OnceLock::new();
+
+/// Machine-readable formats of the split report
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReportFormat {
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!(
+                "unknown report format '{}', expected one of: json",
+                value
+            )),
+        }
+    }
+}
+
+/// Everything a split run did, collected for the report
+pub struct SplitReport<'a> {
+    pub started_at: SystemTime,
+    pub dry_run: bool,
+    /// The exit code of the run, see exit_code
+    pub exit_code: i32,
+    pub releases: &'a [Release],
+    pub skipped_cue_files: &'a [SkippedCueFile],
+    /// Empty on a dry run
+    pub track_results: &'a [TrackResult],
+    /// Elapsed time of each phase of the run, e.g. ("split", 12s)
+    pub timings: &'a [(&'static str, Duration)],
+}
+
+/// Redirects stdout to stderr for the rest of the process, so a report printed to stdout can be piped
+/// The report is written to the original stdout, everything else, including the output of ffmpeg, goes to stderr
+pub fn redirect_stdout_to_stderr() {
+    if REPORT_STDOUT.get().is_some() {
+        return;
+    }
+    let _ = io::stdout().flush();
+
+    // SAFETY: dup and dup2 only duplicate file descriptors, the duplicate of stdout is owned by the File below
+    let stdout_fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if stdout_fd < 0 {
+        red_ln!(
+            "❌ Could not redirect the output to stderr: {}",
+            io::Error::last_os_error()
+        );
+        return;
+    }
+    let report_stdout = unsafe { File::from_raw_fd(stdout_fd) };
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        red_ln!(
+            "❌ Could not redirect the output to stderr: {}",
+            io::Error::last_os_error()
+        );
+        return;
+    }
+    let _ = REPORT_STDOUT.set(report_stdout);
+}
+
+/// Writes the report into the given file, or prints it to stdout if there is none
+pub fn write_report(
+    split_report: &SplitReport,
+    report_format: ReportFormat,
+    report_file: Option<&Path>,
+) {
+    let report = match report_format {
+        ReportFormat::Json => build_json_report(split_report),
+    };
+    let report = serde_json::to_string_pretty(&report).unwrap();
+
+    match report_file {
+        Some(report_file) => match fs::write(report_file, report + "\n") {
+            Ok(()) => println!("📝 Wrote the report to {}", report_file.display()),
+            Err(err) => red_ln!(
+                "❌ Could not write the report to {}: {}",
+                report_file.display(),
+                err
+            ),
+        },
+        None => match REPORT_STDOUT.get() {
+            Some(mut report_stdout) => {
+                if let Err(err) = writeln!(report_stdout, "{}", report) {
+                    red_ln!("❌ Could not print the report: {}", err);
+                }
+            }
+            None => println!("{}", report),
+        },
+    }
+}
+
+/// Builds the JSON report, the schema is documented in the README
+fn build_json_report(split_report: &SplitReport) -> Value {
+    let valid_sheets = split_report
+        .releases
+        .iter()
+        .flat_map(|release| &release.discs)
+        .map(|cue_sheet| sheet_to_json(cue_sheet, split_report));
+    let skipped_sheets = split_report
+        .skipped_cue_files
+        .iter()
+        .map(skipped_sheet_to_json);
+    let sheets: Vec<Value> = valid_sheets.chain(skipped_sheets).collect();
+
+    let mut failures: Vec<Value> = split_report
+        .skipped_cue_files
+        .iter()
+        .map(|skipped_cue_file| {
+            json!({
+                "cue_file": skipped_cue_file.cue_file_path.display().to_string(),
+                "track": null,
+                "message": skipped_cue_file.reason,
+            })
+        })
+        .collect();
+    for cue_sheet in split_report
+        .releases
+        .iter()
+        .flat_map(|release| &release.discs)
+    {
+        for track in &cue_sheet.tracks {
+            if let Some(error) =
+                find_track_result(split_report, track).and_then(|result| result.error.as_ref())
+            {
+                failures.push(json!({
+                    "cue_file": cue_sheet.cue_file_path.display().to_string(),
+                    "track": track.number,
+                    "message": error,
+                }));
+            }
+        }
+    }
+
+    let planned_tracks: usize = split_report
+        .releases
+        .iter()
+        .flat_map(|release| &release.discs)
+        .map(|cue_sheet| cue_sheet.tracks.len())
+        .sum();
+    let failed_tracks = split_report
+        .track_results
+        .iter()
+        .filter(|track_result| track_result.error.is_some())
+        .count();
+    let timings: serde_json::Map<String, Value> = split_report
+        .timings
+        .iter()
+        .map(|(phase, elapsed)| (format!("{}_seconds", phase), json!(elapsed.as_secs_f64())))
+        .collect();
+    let started_at = split_report
+        .started_at
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    json!({
+        "schema_version": REPORT_SCHEMA_VERSION,
+        "cue_splatter_version": env!("CARGO_PKG_VERSION"),
+        "started_at": started_at,
+        "dry_run": split_report.dry_run,
+        "exit_code": split_report.exit_code,
+        "timings": timings,
+        "sheets": sheets,
+        "failures": failures,
+        "summary": {
+            "sheets": split_report.releases.iter().map(|release| release.discs.len()).sum::<usize>(),
+            "skipped_sheets": split_report.skipped_cue_files.len(),
+            "planned_tracks": planned_tracks,
+            "produced_tracks": split_report.track_results.len() - failed_tracks,
+            "failed_tracks": failed_tracks,
+        },
+    })
+}
+
+fn sheet_to_json(cue_sheet: &CueSheet, split_report: &SplitReport) -> Value {
+    let tracks: Vec<Value> = cue_sheet
+        .tracks
+        .iter()
+        .map(|track| track_to_json(track, split_report))
+        .collect();
+
+    json!({
+        "cue_file": cue_sheet.cue_file_path.display().to_string(),
+        "status": "valid",
+        "reason": null,
+        "encoding": cue_sheet.cue_encoding.name(),
+        "title": cue_sheet.title,
+        "performer": cue_sheet.performer,
+        "disc_number": cue_sheet.disc_number,
+        "total_discs": cue_sheet.total_discs,
+        "audio_file": cue_sheet.audio_file_path.display().to_string(),
+        "probe": {
+            "codec": cue_sheet.audio_codec,
+            "duration_seconds": cue_sheet.audio_duration,
+        },
+        "problems": cue_sheet.problems,
+        "fixes": cue_sheet.fixes,
+        "output_dir": cue_sheet.output_dir.as_ref().map(|output_dir| output_dir.display().to_string()),
+        "tracks": tracks,
+    })
+}
+
+/// Skipped cue sheets have the same fields as valid ones, those which are unknown are null or empty
+fn skipped_sheet_to_json(skipped_cue_file: &SkippedCueFile) -> Value {
+    json!({
+        "cue_file": skipped_cue_file.cue_file_path.display().to_string(),
+        "status": "skipped",
+        "reason": skipped_cue_file.reason,
+        "encoding": null,
+        "title": null,
+        "performer": null,
+        "disc_number": null,
+        "total_discs": null,
+        "audio_file": null,
+        "probe": null,
+        "problems": [],
+        "fixes": [],
+        "output_dir": null,
+        "tracks": [],
+    })
+}
+
+fn track_to_json(track: &Track, split_report: &SplitReport) -> Value {
+    let track_result = find_track_result(split_report, track);
+    // Tracks without a result were not split, which only happens on a dry run
+    let status = match track_result {
+        None => "planned",
+        Some(TrackResult { error: None, .. }) => "produced",
+        Some(TrackResult { error: Some(_), .. }) => "failed",
+    };
+
+    json!({
+        "number": track.number,
+        "title": track.title,
+        "artist": track.artist,
+        "start": track.start_time.map(|start_time| start_time.to_string()),
+        "start_seconds": track.start_time.map(seconds),
+        "pregap_start": track.pregap_start_time.map(|pregap_start_time| pregap_start_time.to_string()),
+        "output_file": track.output_file.as_ref().map(|output_file| output_file.display().to_string()),
+        "ffmpeg_command": track.ffmpeg_command,
+        "status": status,
+        "error": track_result.and_then(|track_result| track_result.error.clone()),
+        "elapsed_seconds": track_result.map(|track_result| track_result.elapsed.as_secs_f64()),
+    })
+}
+
+/// Tracks are told apart by their output file, which is unique across all releases
+fn find_track_result<'a>(split_report: &'a SplitReport, track: &Track) -> Option<&'a TrackResult> {
+    split_report
+        .track_results
+        .iter()
+        .find(|track_result| track_result.track.output_file == track.output_file)
+}
+
+fn seconds(cue_duration: CueDuration) -> f64 {
+    cue_duration.total_frames() as f64 / 75.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const CUE_TEXT: &str = r#"PERFORMER "Artist"
+TITLE "Album"
+FILE "album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    INDEX 00 03:00:00
+    INDEX 01 03:02:00
+"#;
+
+    fn releases() -> Vec<Release> {
+        let mut cue_sheet = crate::parse_cue_text(Path::new("/music/album.cue"), CUE_TEXT);
+        cue_sheet
+            .fixes
+            .push("Replaced the audio file reference".to_string());
+        for track in &mut cue_sheet.tracks {
+            track.output_file = Some(PathBuf::from(format!("/music/{:02}.flac", track.number)));
+        }
+        vec![Release {
+            title: cue_sheet.title.clone(),
+            performer: cue_sheet.performer.clone(),
+            discs: vec![cue_sheet],
+        }]
+    }
+
+    fn track_result(release: &Release, index: usize, error: Option<&str>) -> TrackResult {
+        TrackResult {
+            track: release.discs[0].tracks[index].clone(),
+            elapsed: Duration::from_millis(1500),
+            error: error.map(str::to_string),
+        }
+    }
+
+    fn skipped_cue_files() -> Vec<SkippedCueFile> {
+        vec![SkippedCueFile {
+            cue_file_path: PathBuf::from("/music/broken.cue"),
+            reason: "The audio file was not found".to_string(),
+        }]
+    }
+
+    fn split_report<'a>(
+        releases: &'a [Release],
+        skipped_cue_files: &'a [SkippedCueFile],
+        track_results: &'a [TrackResult],
+        timings: &'a [(&'static str, Duration)],
+    ) -> SplitReport<'a> {
+        SplitReport {
+            started_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            dry_run: track_results.is_empty(),
+            exit_code: 0,
+            releases,
+            skipped_cue_files,
+            track_results,
+            timings,
+        }
+    }
+
+    #[test]
+    fn parses_report_formats() {
+        assert_eq!("json".parse(), Ok(ReportFormat::Json));
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+
+    #[test]
+    fn reports_produced_and_failed_tracks() {
+        let releases = releases();
+        let skipped_cue_files = skipped_cue_files();
+        let track_results = [
+            track_result(&releases[0], 0, None),
+            track_result(&releases[0], 1, Some("ffmpeg failed")),
+        ];
+        let timings = [("split", Duration::from_secs(3))];
+        let report = build_json_report(&split_report(
+            &releases,
+            &skipped_cue_files,
+            &track_results,
+            &timings,
+        ));
+
+        assert_eq!(report["schema_version"], REPORT_SCHEMA_VERSION);
+        assert_eq!(report["started_at"], 1_700_000_000);
+        assert_eq!(report["dry_run"], false);
+        assert_eq!(report["timings"]["split_seconds"], 3.0);
+        assert_eq!(
+            report["summary"],
+            json!({
+                "sheets": 1,
+                "skipped_sheets": 1,
+                "planned_tracks": 2,
+                "produced_tracks": 1,
+                "failed_tracks": 1,
+            })
+        );
+        assert_eq!(
+            report["failures"],
+            json!([
+                {"cue_file": "/music/broken.cue", "track": null, "message": "The audio file was not found"},
+                {"cue_file": "/music/album.cue", "track": 2, "message": "ffmpeg failed"},
+            ])
+        );
+
+        let tracks = &report["sheets"][0]["tracks"];
+        assert_eq!(tracks[0]["status"], "produced");
+        assert_eq!(tracks[0]["error"], Value::Null);
+        assert_eq!(tracks[0]["elapsed_seconds"], 1.5);
+        assert_eq!(tracks[1]["status"], "failed");
+        assert_eq!(tracks[1]["error"], "ffmpeg failed");
+        assert_eq!(tracks[1]["start"], "03:02:00");
+        assert_eq!(tracks[1]["start_seconds"], 182.0);
+        assert_eq!(tracks[1]["pregap_start"], "03:00:00");
+        assert_eq!(tracks[1]["output_file"], "/music/02.flac");
+    }
+
+    #[test]
+    fn reports_valid_and_skipped_sheets_with_the_same_fields() {
+        let releases = releases();
+        let skipped_cue_files = skipped_cue_files();
+        let report = build_json_report(&split_report(&releases, &skipped_cue_files, &[], &[]));
+
+        let sheets = report["sheets"].as_array().unwrap();
+        assert_eq!(sheets.len(), 2);
+        assert_eq!(sheets[0]["status"], "valid");
+        assert_eq!(sheets[0]["title"], "Album");
+        assert_eq!(sheets[0]["performer"], "Artist");
+        assert_eq!(sheets[0]["audio_file"], "/music/album.flac");
+        assert_eq!(
+            sheets[0]["fixes"],
+            json!(["Replaced the audio file reference"])
+        );
+        assert_eq!(sheets[1]["status"], "skipped");
+        assert_eq!(sheets[1]["reason"], "The audio file was not found");
+        assert_eq!(sheets[1]["tracks"], json!([]));
+
+        let valid_keys: Vec<&String> = sheets[0].as_object().unwrap().keys().collect();
+        let skipped_keys: Vec<&String> = sheets[1].as_object().unwrap().keys().collect();
+        assert_eq!(valid_keys, skipped_keys);
+    }
+
+    #[test]
+    fn tracks_of_a_dry_run_are_planned() {
+        let releases = releases();
+        let report = build_json_report(&split_report(&releases, &[], &[], &[]));
+
+        assert_eq!(report["dry_run"], true);
+        assert_eq!(report["summary"]["planned_tracks"], 2);
+        assert_eq!(report["summary"]["produced_tracks"], 0);
+        assert_eq!(report["failures"], json!([]));
+        for track in report["sheets"][0]["tracks"].as_array().unwrap() {
+            assert_eq!(track["status"], "planned");
+            assert_eq!(track["elapsed_seconds"], Value::Null);
+        }
+    }
+
+    #[test]
+    fn writes_the_report_into_the_report_file() {
+        let temp_dir = self_update::TempDir::new().unwrap();
+        let report_file = temp_dir.path().join("report.json");
+        let releases = releases();
+        write_report(
+            &split_report(&releases, &[], &[], &[]),
+            ReportFormat::Json,
+            Some(&report_file),
+        );
+
+        let report: Value =
+            serde_json::from_str(&fs::read_to_string(&report_file).unwrap()).unwrap();
+        assert_eq!(report["sheets"][0]["cue_file"], "/music/album.cue");
+    }
+}
@@ -0,0 +1,236 @@
+use argh::FromArgs;
+use colour::{green_ln, red_ln, yellow_ln};
+
+use crate::config::Config;
+use crate::cue_encoding::CueEncoding;
+use crate::options::{CueArgs, CueOptions, OutputArgs, OutputOptions};
+use crate::output::{OutputFormat, OutputTemplate};
+use crate::tags::TagPrecedence;
+use crate::{
+    check_tools, exit_code, find_all_cue_files, load_cue_sheets, prepare_releases, print_goodbye,
+    write_audio_metadata_to_track, InvalidCuePolicy,
+};
+
+/// Write the tags of the cue sheets into already split tracks, without splitting again
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "tag")]
+pub struct TagArgs {
+    /// prefix track numbers with the disc number on multi-disc releases, e.g. 101, 213
+    /// has to match the split run, as it is part of the track file names
+    #[argh(switch)]
+    disc_prefix: bool,
+
+    /// do not prefix track numbers with the disc number, even if disc_prefix is enabled in a config file
+    #[argh(switch)]
+    no_disc_prefix: bool,
+
+    /// which tags win if both the cue sheet and the source audio file provide a value:
+    /// cue, source or fill-gaps (default: cue)
+    #[argh(option)]
+    tag_precedence: Option<TagPrecedence>,
+
+    /// do not embed cover art into the tracks
+    #[argh(switch)]
+    no_cover: bool,
+
+    /// embed cover art, even if no_cover is enabled in a config file
+    #[argh(switch)]
+    cover: bool,
+
+    /// comma separated cover art file names to look for, without extension, `*` is a wildcard
+    /// default is "cover,folder,front"
+    #[argh(option)]
+    cover_names: Option<String>,
+
+    /// scale cover art down to fit into the given size in pixels and recompress it as JPEG
+    #[argh(option)]
+    cover_max_size: Option<u32>,
+
+    /// file name template of the tracks without extension, has to match the split run, see split --help
+    #[argh(option)]
+    output_template: Option<OutputTemplate>,
+
+    /// format the tracks were converted to by split: flac, alac, mp3 or opus
+    /// has to match the split run, as it is part of the track file names
+    #[argh(option)]
+    format: Option<OutputFormat>,
+
+    /// decode cue files with the given encoding instead of detecting it, e.g. windows-1251 or shift_jis
+    #[argh(option)]
+    cue_encoding: Option<CueEncoding>,
+
+    /// do not ask any questions, same as --non-interactive
+    #[argh(switch, short = 'y')]
+    yes: bool,
+
+    /// do not ask any questions, every question takes its default answer and invalid cue files are handled by
+    /// --on-invalid-cue, this is enabled automatically if stdin is not a terminal
+    #[argh(switch)]
+    non_interactive: bool,
+
+    /// ask questions, even if non_interactive is enabled in a config file
+    #[argh(switch)]
+    interactive: bool,
+
+    /// what to do with invalid cue files in non-interactive mode: skip or abort (default: skip)
+    #[argh(option)]
+    on_invalid_cue: Option<InvalidCuePolicy>,
+
+    /// file or folder paths to parse
+    /// default is "."
+    #[argh(positional, greedy)]
+    pub cue_file_or_folders: Vec<String>,
+}
+
+impl TagArgs {
+    /// Nothing is fixed while tagging, and the audio file may be gone after split --delete or --transfer
+    fn cue_options(&self, config: &Config) -> CueOptions {
+        let cue_args = CueArgs {
+            cue_encoding: self.cue_encoding,
+            yes: self.yes,
+            non_interactive: self.non_interactive,
+            interactive: self.interactive,
+            on_invalid_cue: self.on_invalid_cue,
+            ..CueArgs::default()
+        };
+        CueOptions {
+            convert_cue: None,
+            save_fixes: false,
+            auto_fix_score: None,
+            allow_missing_audio_file: true,
+            ..cue_args.resolve(config)
+        }
+    }
+
+    /// Has to resolve like split does, as the options decide the track file names
+    fn output_options(&self, config: &Config) -> OutputOptions {
+        OutputArgs {
+            disc_prefix: self.disc_prefix,
+            no_disc_prefix: self.no_disc_prefix,
+            tag_precedence: self.tag_precedence,
+            no_cover: self.no_cover,
+            cover: self.cover,
+            cover_names: self.cover_names.clone(),
+            cover_max_size: self.cover_max_size,
+            output_template: self.output_template.clone(),
+            format: self.format,
+        }
+        .resolve(config)
+    }
+}
+
+/// Runs the tag command
+/// The track files are expected where split writes them, missing track files are reported
+/// The source audio file is only read for its tags and cover, so it works after split --delete or --transfer
+pub fn tag(tag_args: TagArgs, config: &Config) {
+    check_tools(vec!["ffprobe"]);
+
+    let cue_options = tag_args.cue_options(config);
+    let output_options = tag_args.output_options(config);
+
+    let cue_file_paths = find_all_cue_files(&tag_args.cue_file_or_folders);
+    let loaded_cue_sheets =
+        load_cue_sheets(&cue_file_paths, &cue_options, Some("Proceed with tagging?"));
+    if let Some(abort_exit_code) = loaded_cue_sheets.abort_exit_code {
+        print_goodbye(abort_exit_code);
+        std::process::exit(abort_exit_code);
+    }
+    let skipped_cue_files = loaded_cue_sheets.skipped_cue_files;
+    let releases = prepare_releases(loaded_cue_sheets.cue_sheets, &output_options);
+
+    let mut tagged_count = 0;
+    let mut failed_count = 0;
+    for release in &releases {
+        for cue_sheet in &release.discs {
+            for track in &cue_sheet.tracks {
+                let output_file = track.output_file.as_ref().unwrap();
+                if !output_file.exists() {
+                    yellow_ln!(
+                        "⚠️ Track file not found, split it first: {}",
+                        output_file.display()
+                    );
+                    failed_count += 1;
+                    continue;
+                }
+
+                let (is_ok, error_message) =
+                    write_audio_metadata_to_track(release, cue_sheet, track, &output_options);
+                if is_ok {
+                    println!("🏷️ Tagged {}", output_file.display());
+                    tagged_count += 1;
+                } else {
+                    red_ln!("❌ {}", error_message);
+                    failed_count += 1;
+                }
+            }
+        }
+    }
+
+    if failed_count == 0 {
+        green_ln!("🎉 All {} track(s) have been tagged", tagged_count);
+    } else {
+        yellow_ln!(
+            "⚠️ {} track(s) have been tagged, {} failed",
+            tagged_count,
+            failed_count
+        );
+    }
+
+    let exit_status = exit_code::from_track_counts(
+        failed_count,
+        tagged_count + failed_count,
+        cue_file_paths.is_empty() || !skipped_cue_files.is_empty(),
+    );
+    print_goodbye(exit_status);
+    if exit_status != exit_code::SUCCESS {
+        std::process::exit(exit_status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SplitArgs;
+
+    #[test]
+    fn nothing_is_fixed_while_tagging() {
+        let config: Config = toml::from_str(
+            "save_fixes = true\nauto_fix_score = 90\nconvert_cue = \"utf8\"\non_invalid_cue = \"abort\"",
+        )
+        .unwrap();
+        let tag_args = TagArgs::from_args(&["tag"], &[]).unwrap();
+        let cue_options = tag_args.cue_options(&config);
+        assert!(!cue_options.save_fixes);
+        assert_eq!(cue_options.auto_fix_score, None);
+        assert_eq!(cue_options.convert_cue, None);
+        assert!(cue_options.allow_missing_audio_file);
+        assert_eq!(cue_options.on_invalid_cue, InvalidCuePolicy::Abort);
+    }
+
+    #[test]
+    fn output_options_are_resolved_like_split() {
+        let config: Config = toml::from_str(
+            "disc_prefix = true\ntag_precedence = \"source\"\nno_cover = true\ncover_names = \"front\"",
+        )
+        .unwrap();
+        for args in [
+            &[][..],
+            &["--no-disc-prefix", "--cover", "--cover-max-size", "300"][..],
+            &["--tag-precedence", "fill-gaps", "--cover-names", "folder"][..],
+        ] {
+            let tag_args = TagArgs::from_args(&["tag"], args).unwrap();
+            let split_args = SplitArgs::from_args(&["split"], args).unwrap();
+            assert_eq!(
+                format!("{:?}", tag_args.output_options(&config)),
+                format!("{:?}", split_args.output_options(&config))
+            );
+        }
+
+        let tag_args = TagArgs::from_args(&["tag"], &["--no-disc-prefix"]).unwrap();
+        let output_options = tag_args.output_options(&config);
+        assert!(!output_options.disc_prefix);
+        assert_eq!(output_options.tag_precedence, TagPrecedence::Source);
+        assert!(output_options.no_cover);
+        assert_eq!(output_options.cover_names, "front");
+    }
+}
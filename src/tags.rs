@@ -0,0 +1,223 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use lofty::file::TaggedFileExt;
+use lofty::picture::Picture;
+use lofty::tag::{ItemKey, Tag, TagItem};
+
+/// Tags of the source image, that are not valid for a single split track
+/// The track gain and peak refer to the whole image, so they are dropped as well
+const IMAGE_ONLY_KEYS: [ItemKey; 5] = [
+    ItemKey::TrackTitle,
+    ItemKey::TrackNumber,
+    ItemKey::TrackTotal,
+    ItemKey::ReplayGainTrackGain,
+    ItemKey::ReplayGainTrackPeak,
+];
+
+/// Keys that describe a single track, all other keys written from the cue sheet describe the album
+const TRACK_LEVEL_KEYS: [ItemKey; 3] = [
+    ItemKey::TrackTitle,
+    ItemKey::TrackArtist,
+    ItemKey::TrackNumber,
+];
+
+/// Keys the cue sheet sets per track, the source value only fills the gap of tracks without one, whatever the
+/// precedence is, e.g. the artist of a compilation image would otherwise overwrite the PERFORMER of every track
+const PER_TRACK_KEYS: [ItemKey; 1] = [ItemKey::TrackArtist];
+
+/// Decides which value is written, if both the cue sheet and the source file provide one
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum TagPrecedence {
+    /// Cue sheet values overwrite source file tags
+    #[default]
+    Cue,
+    /// Source file tags overwrite cue sheet values
+    Source,
+    /// The cue sheet is preferred for track fields (title, artist, number),
+    /// the source file for album fields (album, album artist, disc)
+    FillGaps,
+}
+
+impl FromStr for TagPrecedence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cue" => Ok(TagPrecedence::Cue),
+            "source" => Ok(TagPrecedence::Source),
+            "fill-gaps" => Ok(TagPrecedence::FillGaps),
+            _ => Err(format!(
+                "unknown tag precedence '{}', expected one of: cue, source, fill-gaps",
+                value
+            )),
+        }
+    }
+}
+
+/// Tags read from the full-length source audio file
+#[derive(Debug, Clone, Default)]
+pub struct SourceTags {
+    pub items: Vec<TagItem>,
+    pub pictures: Vec<Picture>,
+}
+
+/// Reads the tags embedded in the source audio file
+/// Returns `None` if the file can not be read or has no tags
+pub fn read_source_tags(audio_file_path: &Path) -> Option<SourceTags> {
+    let tagged_file = lofty::read_from_path(audio_file_path).ok()?;
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())?;
+
+    Some(SourceTags {
+        items: tag
+            .items()
+            .filter(|item| !IMAGE_ONLY_KEYS.contains(&item.key()))
+            .cloned()
+            .collect(),
+        pictures: tag.pictures().to_vec(),
+    })
+}
+
+/// Merges the source file tags with the tag built from the cue sheet
+pub fn merge_tags(
+    source_tags: Option<&SourceTags>,
+    cue_tag: &Tag,
+    precedence: TagPrecedence,
+) -> Tag {
+    let mut merged_tag = Tag::new(cue_tag.tag_type());
+
+    if let Some(source_tags) = source_tags {
+        for item in &source_tags.items {
+            // Push to keep multi-value items like multiple artists
+            merged_tag.push(item.clone());
+        }
+        for picture in &source_tags.pictures {
+            merged_tag.push_picture(picture.clone());
+        }
+    }
+
+    for item in cue_tag.items() {
+        let source_has_value = merged_tag.get(item.key()).is_some();
+        let cue_wins = match precedence {
+            TagPrecedence::Cue => true,
+            TagPrecedence::Source => !source_has_value,
+            TagPrecedence::FillGaps => TRACK_LEVEL_KEYS.contains(&item.key()) || !source_has_value,
+        } || PER_TRACK_KEYS.contains(&item.key());
+
+        if cue_wins {
+            merged_tag.insert(item.clone());
+        }
+    }
+
+    merged_tag
+}
+
+#[cfg(test)]
+mod tests {
+    use lofty::tag::{ItemValue, TagType};
+
+    use super::*;
+
+    fn text_item(key: ItemKey, value: &str) -> TagItem {
+        TagItem::new(key, ItemValue::Text(value.to_string()))
+    }
+
+    fn source_tags() -> SourceTags {
+        SourceTags {
+            items: vec![
+                text_item(ItemKey::AlbumTitle, "Source Album"),
+                text_item(ItemKey::TrackArtist, "Various Artists"),
+                text_item(ItemKey::Label, "Source Label"),
+            ],
+            pictures: Vec::new(),
+        }
+    }
+
+    fn cue_tag() -> Tag {
+        let mut cue_tag = Tag::new(TagType::VorbisComments);
+        cue_tag.insert_text(ItemKey::AlbumTitle, "Cue Album".to_string());
+        cue_tag.insert_text(ItemKey::TrackArtist, "Track Performer".to_string());
+        cue_tag.insert_text(ItemKey::TrackTitle, "Track Title".to_string());
+        cue_tag
+    }
+
+    fn merged_value(merged_tag: &Tag, key: ItemKey) -> Option<&str> {
+        merged_tag.get_string(key)
+    }
+
+    #[test]
+    fn cue_values_win_by_default() {
+        let merged_tag = merge_tags(Some(&source_tags()), &cue_tag(), TagPrecedence::Cue);
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::AlbumTitle),
+            Some("Cue Album")
+        );
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::Label),
+            Some("Source Label")
+        );
+    }
+
+    #[test]
+    fn source_values_win_with_source_precedence() {
+        let merged_tag = merge_tags(Some(&source_tags()), &cue_tag(), TagPrecedence::Source);
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::AlbumTitle),
+            Some("Source Album")
+        );
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::TrackTitle),
+            Some("Track Title")
+        );
+    }
+
+    #[test]
+    fn fill_gaps_prefers_source_album_fields_and_cue_track_fields() {
+        let merged_tag = merge_tags(Some(&source_tags()), &cue_tag(), TagPrecedence::FillGaps);
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::AlbumTitle),
+            Some("Source Album")
+        );
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::TrackTitle),
+            Some("Track Title")
+        );
+    }
+
+    #[test]
+    fn track_performers_are_kept_with_every_precedence() {
+        for precedence in [
+            TagPrecedence::Cue,
+            TagPrecedence::Source,
+            TagPrecedence::FillGaps,
+        ] {
+            let merged_tag = merge_tags(Some(&source_tags()), &cue_tag(), precedence);
+            assert_eq!(
+                merged_value(&merged_tag, ItemKey::TrackArtist),
+                Some("Track Performer")
+            );
+        }
+    }
+
+    #[test]
+    fn source_artist_fills_tracks_without_performer() {
+        let mut cue_tag = Tag::new(TagType::VorbisComments);
+        cue_tag.insert_text(ItemKey::TrackTitle, "Track Title".to_string());
+        let merged_tag = merge_tags(Some(&source_tags()), &cue_tag, TagPrecedence::Cue);
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::TrackArtist),
+            Some("Various Artists")
+        );
+    }
+
+    #[test]
+    fn merges_without_source_tags() {
+        let merged_tag = merge_tags(None, &cue_tag(), TagPrecedence::Source);
+        assert_eq!(
+            merged_value(&merged_tag, ItemKey::AlbumTitle),
+            Some("Cue Album")
+        );
+    }
+}
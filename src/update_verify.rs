@@ -0,0 +1,464 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::digest;
+use ring::signature::{UnparsedPublicKey, ED25519};
+
+/// Minisign algorithm id of signatures over the file content itself
+/// Prehashed signatures ("ED") would need BLAKE2b, so releases are signed with `minisign -S -l`
+const LEGACY_SIGNATURE_ALGORITHM: &[u8] = b"Ed";
+
+/// Minisign algorithm id of prehashed signatures, only used to explain the rejection
+const PREHASHED_SIGNATURE_ALGORITHM: &[u8] = b"ED";
+
+const KEY_ID_LENGTH: usize = 8;
+const PUBLIC_KEY_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = 64;
+
+/// Minisign public key, the release binaries are signed with the matching secret key
+#[derive(Debug, Clone)]
+pub struct PublicKey {
+    key_id: [u8; KEY_ID_LENGTH],
+    key: [u8; PUBLIC_KEY_LENGTH],
+}
+
+impl PublicKey {
+    /// Parses a minisign public key, either the content of a minisign.pub file or only its base64 line
+    pub fn parse(text: &str) -> Result<PublicKey, String> {
+        let encoded_key = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .ok_or("The public key is empty")?;
+        let decoded_key = decode_base64(encoded_key, "public key")?;
+        check_length(
+            &decoded_key,
+            LEGACY_SIGNATURE_ALGORITHM.len() + KEY_ID_LENGTH + PUBLIC_KEY_LENGTH,
+            "public key",
+        )?;
+        let (algorithm, rest) = decoded_key.split_at(LEGACY_SIGNATURE_ALGORITHM.len());
+        if algorithm != LEGACY_SIGNATURE_ALGORITHM {
+            return Err("The public key is no ed25519 minisign key".to_string());
+        }
+        let (key_id, key) = rest.split_at(KEY_ID_LENGTH);
+
+        Ok(PublicKey {
+            key_id: key_id.try_into().unwrap(),
+            key: key.try_into().unwrap(),
+        })
+    }
+
+    /// Key id as printed by minisign, the bytes are stored little endian
+    pub fn key_id(&self) -> String {
+        format_key_id(&self.key_id)
+    }
+}
+
+/// Parsed content of a .minisig file
+struct Signature {
+    key_id: [u8; KEY_ID_LENGTH],
+    signature: [u8; SIGNATURE_LENGTH],
+    trusted_comment: String,
+    global_signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl Signature {
+    /// Parses a minisign signature file:
+    /// untrusted comment, signature line, trusted comment, global signature line
+    fn parse(text: &str) -> Result<Signature, String> {
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+        let mut next_line = |name: &str| {
+            lines
+                .next()
+                .ok_or_else(|| format!("The signature file has no {}", name))
+        };
+
+        let untrusted_comment = next_line("untrusted comment")?;
+        if !untrusted_comment.starts_with("untrusted comment:") {
+            return Err("The signature file does not start with an untrusted comment".to_string());
+        }
+
+        let decoded_signature = decode_base64(next_line("signature")?, "signature")?;
+        check_length(
+            &decoded_signature,
+            LEGACY_SIGNATURE_ALGORITHM.len() + KEY_ID_LENGTH + SIGNATURE_LENGTH,
+            "signature",
+        )?;
+        let (algorithm, rest) = decoded_signature.split_at(LEGACY_SIGNATURE_ALGORITHM.len());
+        if algorithm == PREHASHED_SIGNATURE_ALGORITHM {
+            return Err(
+                "Prehashed minisign signatures are not supported, sign with `minisign -S -l`"
+                    .to_string(),
+            );
+        }
+        if algorithm != LEGACY_SIGNATURE_ALGORITHM {
+            return Err("The signature is no ed25519 minisign signature".to_string());
+        }
+        let (key_id, signature) = rest.split_at(KEY_ID_LENGTH);
+
+        let trusted_comment = next_line("trusted comment")?
+            .strip_prefix("trusted comment: ")
+            .ok_or("The signature file has no trusted comment")?
+            .to_string();
+
+        let global_signature = decode_base64(next_line("global signature")?, "global signature")?;
+        check_length(&global_signature, SIGNATURE_LENGTH, "global signature")?;
+
+        Ok(Signature {
+            key_id: key_id.try_into().unwrap(),
+            signature: signature.try_into().unwrap(),
+            trusted_comment,
+            global_signature: global_signature.as_slice().try_into().unwrap(),
+        })
+    }
+}
+
+/// Verifies the minisign signature of the downloaded release asset
+///
+/// The trusted comment is covered by the global signature. It has to name the downloaded asset as the signed
+/// file, so a validly signed binary of another target or version can not be passed off.
+pub fn verify_signature(
+    public_key: &PublicKey,
+    asset_name: &str,
+    content: &[u8],
+    signature_file: &str,
+) -> Result<(), String> {
+    let signature = Signature::parse(signature_file)?;
+    if signature.key_id != public_key.key_id {
+        return Err(format!(
+            "The release is signed with key {}, expected key {}",
+            format_key_id(&signature.key_id),
+            public_key.key_id()
+        ));
+    }
+
+    let verifying_key = UnparsedPublicKey::new(&ED25519, public_key.key);
+    verifying_key
+        .verify(content, &signature.signature)
+        .map_err(|_| format!("The signature of {} is invalid", asset_name))?;
+
+    let signed_comment = [
+        &signature.signature[..],
+        signature.trusted_comment.as_bytes(),
+    ]
+    .concat();
+    verifying_key
+        .verify(&signed_comment, &signature.global_signature)
+        .map_err(|_| {
+            format!(
+                "The trusted comment of the signature of {} is invalid",
+                asset_name
+            )
+        })?;
+
+    let signed_file_name = signature
+        .trusted_comment
+        .split('\t')
+        .find_map(|field| field.strip_prefix("file:"));
+    match signed_file_name {
+        Some(signed_file_name) if signed_file_name == asset_name => Ok(()),
+        Some(signed_file_name) => Err(format!(
+            "The signature was made for {}, not for {}",
+            signed_file_name, asset_name
+        )),
+        None => Err(format!(
+            "The trusted comment of the signature of {} does not name the signed file",
+            asset_name
+        )),
+    }
+}
+
+/// Verifies the SHA-256 checksum of the downloaded release asset
+/// The checksum file has the format of sha256sum: "<hex checksum>  <file name>", one line per file
+/// Only a line naming the asset counts, so the checksum of another file can not be passed off
+pub fn verify_checksum(
+    asset_name: &str,
+    content: &[u8],
+    checksum_file: &str,
+) -> Result<(), String> {
+    let expected_checksum = checksum_file
+        .lines()
+        .filter_map(|line| {
+            let (checksum, file_name) = line.trim().split_once(char::is_whitespace)?;
+            let file_name = file_name.trim_start().trim_start_matches('*');
+            (file_name == asset_name).then_some(checksum)
+        })
+        .find(|checksum| !checksum.is_empty())
+        .ok_or_else(|| format!("The checksum file has no checksum for {}", asset_name))?;
+
+    let actual_checksum = to_hex(digest::digest(&digest::SHA256, content).as_ref());
+    if expected_checksum.eq_ignore_ascii_case(&actual_checksum) {
+        Ok(())
+    } else {
+        Err(format!(
+            "The SHA-256 checksum of {} is {}, expected {}",
+            asset_name, actual_checksum, expected_checksum
+        ))
+    }
+}
+
+fn decode_base64(text: &str, name: &str) -> Result<Vec<u8>, String> {
+    BASE64
+        .decode(text.trim())
+        .map_err(|err| format!("The {} is no valid base64: {}", name, err))
+}
+
+fn check_length(bytes: &[u8], expected_length: usize, name: &str) -> Result<(), String> {
+    if bytes.len() == expected_length {
+        Ok(())
+    } else {
+        Err(format!(
+            "The {} has {} bytes, expected {}",
+            name,
+            bytes.len(),
+            expected_length
+        ))
+    }
+}
+
+fn format_key_id(key_id: &[u8; KEY_ID_LENGTH]) -> String {
+    key_id
+        .iter()
+        .rev()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Signs files like `minisign -S -l`, so tests can verify releases without the release key
+#[cfg(test)]
+pub mod test_signing {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+
+    pub struct TestKey {
+        key_id: [u8; KEY_ID_LENGTH],
+        key_pair: Ed25519KeyPair,
+    }
+
+    impl TestKey {
+        pub fn new(seed: u8) -> TestKey {
+            TestKey {
+                key_id: [seed; KEY_ID_LENGTH],
+                key_pair: Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap(),
+            }
+        }
+
+        /// The content of a minisign.pub file
+        pub fn public_key_file(&self) -> String {
+            let key = [
+                LEGACY_SIGNATURE_ALGORITHM,
+                &self.key_id,
+                self.key_pair.public_key().as_ref(),
+            ]
+            .concat();
+            format!(
+                "untrusted comment: minisign public key\n{}\n",
+                BASE64.encode(key)
+            )
+        }
+
+        pub fn public_key(&self) -> PublicKey {
+            PublicKey::parse(&self.public_key_file()).unwrap()
+        }
+
+        /// The content of a .minisig file, the trusted comment names the signed file
+        pub fn sign(&self, file_name: &str, content: &[u8]) -> String {
+            let trusted_comment = format!("timestamp:1700000000\tfile:{}", file_name);
+            self.sign_with_trusted_comment(&trusted_comment, content)
+        }
+
+        /// The content of a .minisig file with the given trusted comment
+        pub fn sign_with_trusted_comment(&self, trusted_comment: &str, content: &[u8]) -> String {
+            let signature = self.key_pair.sign(content);
+            let global_signature = self
+                .key_pair
+                .sign(&[signature.as_ref(), trusted_comment.as_bytes()].concat());
+            format!(
+                "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+                BASE64.encode([LEGACY_SIGNATURE_ALGORITHM, &self.key_id, signature.as_ref()].concat()),
+                trusted_comment,
+                BASE64.encode(global_signature.as_ref())
+            )
+        }
+    }
+
+    /// The content of a .sha256 file as written by sha256sum
+    pub fn checksum_file(file_name: &str, content: &[u8]) -> String {
+        format!(
+            "{}  {}\n",
+            to_hex(digest::digest(&digest::SHA256, content).as_ref()),
+            file_name
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_signing::{checksum_file, TestKey};
+    use super::*;
+
+    const ASSET_NAME: &str = "cue-splatter-1.5.0-x86_64-unknown-linux-musl";
+    const CONTENT: &[u8] = b"new cue-splatter binary";
+
+    #[test]
+    fn parses_public_key_files_and_lines() {
+        let test_key = TestKey::new(7);
+        let public_key_file = test_key.public_key_file();
+        let public_key = PublicKey::parse(&public_key_file).unwrap();
+        assert_eq!(public_key.key_id(), "0707070707070707");
+
+        let key_line = public_key_file.lines().nth(1).unwrap();
+        assert_eq!(PublicKey::parse(key_line).unwrap().key, public_key.key);
+    }
+
+    #[test]
+    fn rejects_invalid_public_keys() {
+        assert!(PublicKey::parse("").is_err());
+        assert!(PublicKey::parse("untrusted comment: only a comment").is_err());
+        assert!(PublicKey::parse("not base64!").is_err());
+        assert!(PublicKey::parse(&BASE64.encode([0u8; 10])).is_err());
+
+        let other_algorithm = BASE64.encode([b"XX".as_slice(), &[0; 40]].concat());
+        let error_message = PublicKey::parse(&other_algorithm).unwrap_err();
+        assert!(error_message.contains("no ed25519"));
+    }
+
+    #[test]
+    fn parses_signature_files() {
+        let signature_file = TestKey::new(1).sign(ASSET_NAME, CONTENT);
+        let signature = Signature::parse(&signature_file).unwrap();
+        assert_eq!(signature.key_id, [1; KEY_ID_LENGTH]);
+        assert_eq!(
+            signature.trusted_comment,
+            format!("timestamp:1700000000\tfile:{}", ASSET_NAME)
+        );
+
+        // Signature files written on Windows have CRLF line endings
+        let crlf_signature_file = signature_file.replace('\n', "\r\n");
+        assert!(Signature::parse(&crlf_signature_file).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_signature_files() {
+        let signature_file = TestKey::new(1).sign(ASSET_NAME, CONTENT);
+        let lines: Vec<&str> = signature_file.lines().collect();
+
+        assert!(Signature::parse("").is_err());
+        assert!(Signature::parse(&lines[1..].join("\n")).is_err());
+        assert!(Signature::parse(&lines[..2].join("\n")).is_err());
+        let without_trusted_prefix = [lines[0], lines[1], "timestamp:0", lines[3]].join("\n");
+        assert!(Signature::parse(&without_trusted_prefix).is_err());
+
+        let mut prehashed_signature = BASE64.decode(lines[1]).unwrap();
+        prehashed_signature[..2].copy_from_slice(PREHASHED_SIGNATURE_ALGORITHM);
+        let prehashed_signature = BASE64.encode(prehashed_signature);
+        let prehashed_file = [lines[0], &prehashed_signature, lines[2], lines[3]].join("\n");
+        let error_message = Signature::parse(&prehashed_file).err().unwrap();
+        assert!(error_message.contains("Prehashed"));
+    }
+
+    #[test]
+    fn verifies_valid_signatures() {
+        let test_key = TestKey::new(1);
+        let signature_file = test_key.sign(ASSET_NAME, CONTENT);
+        verify_signature(&test_key.public_key(), ASSET_NAME, CONTENT, &signature_file).unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_content() {
+        let test_key = TestKey::new(1);
+        let signature_file = test_key.sign(ASSET_NAME, CONTENT);
+        let error_message = verify_signature(
+            &test_key.public_key(),
+            ASSET_NAME,
+            b"tampered binary",
+            &signature_file,
+        )
+        .unwrap_err();
+        assert!(error_message.contains("is invalid"));
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_keys() {
+        let signature_file = TestKey::new(2).sign(ASSET_NAME, CONTENT);
+        let error_message = verify_signature(
+            &TestKey::new(1).public_key(),
+            ASSET_NAME,
+            CONTENT,
+            &signature_file,
+        )
+        .unwrap_err();
+        assert!(error_message.contains("signed with key 0202020202020202"));
+    }
+
+    #[test]
+    fn rejects_tampered_trusted_comments() {
+        let test_key = TestKey::new(1);
+        let signature_file = test_key
+            .sign("cue-splatter-1.0.0-other-target", CONTENT)
+            .replace("cue-splatter-1.0.0-other-target", ASSET_NAME);
+        let error_message =
+            verify_signature(&test_key.public_key(), ASSET_NAME, CONTENT, &signature_file)
+                .unwrap_err();
+        assert!(error_message.contains("trusted comment"));
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_files() {
+        let test_key = TestKey::new(1);
+        let signature_file = test_key.sign("cue-splatter-1.0.0-other-target", CONTENT);
+        let error_message =
+            verify_signature(&test_key.public_key(), ASSET_NAME, CONTENT, &signature_file)
+                .unwrap_err();
+        assert!(error_message.contains("was made for cue-splatter-1.0.0-other-target"));
+    }
+
+    #[test]
+    fn rejects_trusted_comments_without_file_name() {
+        let test_key = TestKey::new(1);
+        let signature_file = test_key.sign_with_trusted_comment("timestamp:1700000000", CONTENT);
+        let error_message =
+            verify_signature(&test_key.public_key(), ASSET_NAME, CONTENT, &signature_file)
+                .unwrap_err();
+        assert!(error_message.contains("does not name the signed file"));
+    }
+
+    #[test]
+    fn verifies_checksums_of_the_named_asset() {
+        let checksum_line = checksum_file(ASSET_NAME, CONTENT);
+        let (checksum, _) = checksum_line.split_once(' ').unwrap();
+        for checksum_file in [
+            checksum_line.clone(),
+            checksum_file("other-file", b"other content") + &checksum_line,
+            format!("{} *{}", checksum, ASSET_NAME),
+            format!("{}  {}\r\n", checksum.to_uppercase(), ASSET_NAME),
+        ] {
+            verify_checksum(ASSET_NAME, CONTENT, &checksum_file).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_checksum_mismatches() {
+        let checksum_file = checksum_file(ASSET_NAME, b"other content");
+        let error_message = verify_checksum(ASSET_NAME, CONTENT, &checksum_file).unwrap_err();
+        assert!(error_message.contains("expected"));
+    }
+
+    #[test]
+    fn requires_the_exact_asset_name() {
+        let checksum = checksum_file(ASSET_NAME, CONTENT);
+        let checksum = checksum.split_whitespace().next().unwrap();
+        for checksum_file in [
+            checksum.to_string(),
+            format!("{}  -", checksum),
+            format!("{}  {}.old", checksum, ASSET_NAME),
+        ] {
+            let error_message = verify_checksum(ASSET_NAME, CONTENT, &checksum_file).unwrap_err();
+            assert!(error_message.contains("has no checksum"));
+        }
+    }
+}
@@ -1,42 +1,622 @@
-use std::path::PathBuf;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
 use std::time::Duration;
-use std::{env, io, process, thread};
+use std::{env, fs, io, process, thread};
 
+use argh::FromArgs;
+use colour::yellow_ln;
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, USER_AGENT};
 use self_update::cargo_crate_version;
+use semver::{Version, VersionReq};
+
+use crate::config::UpdateConfig;
+use crate::{exit_code, update_verify};
+
+/// Environment variable to disable the automatic update check, e.g. CUE_SPLATTER_NO_UPDATE=1
+pub const NO_UPDATE_ENV: &str = "CUE_SPLATTER_NO_UPDATE";
+
+/// Environment variable to select the release channel, e.g. CUE_SPLATTER_UPDATE_CHANNEL=prerelease
+const UPDATE_CHANNEL_ENV: &str = "CUE_SPLATTER_UPDATE_CHANNEL";
+
+/// Environment variable to pin updates to a semver range, e.g. CUE_SPLATTER_UPDATE_PIN="~1.4"
+const UPDATE_PIN_ENV: &str = "CUE_SPLATTER_UPDATE_PIN";
+
+/// Environment variable to get releases from another source, e.g. a local mirror
+const UPDATE_API_URL_ENV: &str = "CUE_SPLATTER_UPDATE_API_URL";
+
+/// Seconds to wait for the release server, before the update check is given up
+pub const DEFAULT_UPDATE_TIMEOUT_SECS: u64 = 10;
+
+/// Release list of the GitHub repository, mirrors have to serve the same JSON format
+const DEFAULT_RELEASE_URL: &str = "https://api.github.com/repos/rouhim/cue-splatter/releases";
+
+/// Minisign public key the release binaries are verified with, it is compiled in by the release build
+/// Builds without a key can not install updates
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("CUE_SPLATTER_UPDATE_PUBLIC_KEY");
+
+/// Suffix of the release asset holding the SHA-256 checksum of a binary, e.g. "cue-splatter-1.0.0-x86_64.sha256"
+const CHECKSUM_SUFFIX: &str = "sha256";
+
+/// Suffix of the release asset holding the minisign signature of a binary
+const SIGNATURE_SUFFIX: &str = "minisig";
+
+/// Suffix of the binary replaced by the last update, e.g. "cue-splatter.old"
+const PREVIOUS_BINARY_SUFFIX: &str = "old";
+
+/// Suffix of the file naming the version the last rollback went back from, e.g. "cue-splatter.skip"
+/// The automatic update does not install this version again
+const SKIPPED_VERSION_SUFFIX: &str = "skip";
+
+/// Check for a new release of cue-splatter and install it
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "update")]
+pub struct UpdateArgs {
+    /// only check for a new release, do not install it
+    #[argh(switch)]
+    check: bool,
+
+    /// seconds to wait for the release server (default: 10)
+    #[argh(option)]
+    timeout: Option<u64>,
+
+    /// release channel: stable or prerelease (default: stable, or CUE_SPLATTER_UPDATE_CHANNEL)
+    #[argh(option)]
+    channel: Option<ReleaseChannel>,
+
+    /// only update to versions matching this semver range, e.g. "~1.4" or ">=1.2, <2" (default:
+    /// CUE_SPLATTER_UPDATE_PIN)
+    #[argh(option)]
+    pin: Option<VersionReq>,
+
+    /// url of the GitHub compatible release list, e.g. of a local mirror (default: the cue-splatter GitHub
+    /// repository, or CUE_SPLATTER_UPDATE_API_URL)
+    #[argh(option)]
+    release_url: Option<String>,
+}
+
+/// Restore the version of cue-splatter which was replaced by the last update
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rollback")]
+pub struct RollbackArgs {}
+
+/// Which releases are considered as update
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ReleaseChannel {
+    /// Only releases without a pre-release version, e.g. 1.4.0
+    #[default]
+    Stable,
+    /// Also pre-releases, e.g. 1.5.0-beta.1
+    Prerelease,
+}
+
+impl FromStr for ReleaseChannel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "stable" => Ok(ReleaseChannel::Stable),
+            "prerelease" | "pre-release" => Ok(ReleaseChannel::Prerelease),
+            _ => Err(format!(
+                "unknown release channel '{}', expected one of: stable, prerelease",
+                value
+            )),
+        }
+    }
+}
+
+impl Display for ReleaseChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReleaseChannel::Stable => write!(f, "stable"),
+            ReleaseChannel::Prerelease => write!(f, "prerelease"),
+        }
+    }
+}
+
+/// Which release the updater installs and where it gets it from
+#[derive(Debug, Clone)]
+struct UpdatePolicy {
+    channel: ReleaseChannel,
+    pin: Option<VersionReq>,
+    /// The version rolled back from, only an explicit update installs it again
+    skipped_version: Option<Version>,
+    release_url: String,
+    timeout: Duration,
+}
+
+impl UpdatePolicy {
+    /// Reads the policy of the automatic update from the environment, falling back to the config files
+    fn from_env(timeout: Duration, configured: &UpdateConfig) -> Result<UpdatePolicy, String> {
+        let channel = match read_env(UPDATE_CHANNEL_ENV) {
+            Some(channel) => channel
+                .parse()
+                .map_err(|err| format!("{}: {}", UPDATE_CHANNEL_ENV, err))?,
+            None => configured.channel.unwrap_or_default(),
+        };
+        let pin = read_env(UPDATE_PIN_ENV)
+            .map(|pin| {
+                pin.parse::<VersionReq>().map_err(|err| {
+                    format!(
+                        "{}: invalid version range '{}': {}",
+                        UPDATE_PIN_ENV, pin, err
+                    )
+                })
+            })
+            .transpose()?
+            .or_else(|| configured.pin.clone());
+        let release_url = read_env(UPDATE_API_URL_ENV)
+            .or_else(|| configured.release_url.clone())
+            .unwrap_or_else(|| DEFAULT_RELEASE_URL.to_string());
+
+        Ok(UpdatePolicy {
+            channel,
+            pin,
+            skipped_version: read_skipped_version(),
+            release_url,
+            timeout,
+        })
+    }
+
+    /// Checks if the release may be installed, regardless of the installed version
+    fn allows(&self, release: &Release) -> bool {
+        let channel_allows = match self.channel {
+            ReleaseChannel::Stable => !release.prerelease && release.version.pre.is_empty(),
+            ReleaseChannel::Prerelease => true,
+        };
+        channel_allows
+            && self
+                .pin
+                .as_ref()
+                .is_none_or(|pin| pin.matches(&release.version))
+            && self.skipped_version.as_ref() != Some(&release.version)
+    }
+
+    /// Describes the restrictions of the policy for messages, e.g. "stable, pinned to ~1.4, skipping 1.5.0"
+    fn describe(&self) -> String {
+        let mut description = self.channel.to_string();
+        if let Some(ref pin) = self.pin {
+            description.push_str(&format!(", pinned to {}", pin));
+        }
+        if let Some(ref skipped_version) = self.skipped_version {
+            description.push_str(&format!(", skipping {}", skipped_version));
+        }
+        description
+    }
+}
+
+/// A release as listed by the release source
+#[derive(Debug, Clone)]
+struct Release {
+    version: Version,
+    prerelease: bool,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Clone)]
+struct ReleaseAsset {
+    name: String,
+    download_url: String,
+}
+
+/// Runs the update subcommand
+pub fn run(update_args: UpdateArgs, configured: &UpdateConfig) {
+    let timeout = Duration::from_secs(
+        update_args
+            .timeout
+            .or(configured.timeout)
+            .unwrap_or(DEFAULT_UPDATE_TIMEOUT_SECS),
+    );
+    let mut policy = UpdatePolicy::from_env(timeout, configured).unwrap_or_else(|err| {
+        yellow_ln!("⚠️ Invalid update settings: {}", err);
+        process::exit(exit_code::ERROR);
+    });
+    if let Some(channel) = update_args.channel {
+        policy.channel = channel;
+    }
+    if let Some(pin) = update_args.pin {
+        policy.pin = Some(pin);
+    }
+    if let Some(release_url) = update_args.release_url {
+        policy.release_url = release_url;
+    }
+    // Asking for an update explicitly installs a version skipped after a rollback, too
+    policy.skipped_version = None;
+
+    if update_args.check {
+        report_newer_release(&policy);
+        return;
+    }
+
+    match fetch_newer_release(&policy) {
+        Err(err) => {
+            yellow_ln!("⚠️ Could not check for updates: {}", err);
+            process::exit(exit_code::ERROR);
+        }
+        Ok(None) => print_up_to_date(&policy),
+        Ok(Some(release)) => {
+            if let Err(err) = install_release(&release, policy.timeout) {
+                yellow_ln!(
+                    "⚠️ Failed to update, keeping the installed version: {}",
+                    err
+                );
+                process::exit(exit_code::ERROR);
+            }
+        }
+    }
+}
+
+/// Checks if the automatic update is disabled by the environment variable, or else by the config files
+/// Any value of the variable except an empty one, 0, false or no disables it
+pub fn is_disabled(disabled_by_config: bool) -> bool {
+    match env::var(NO_UPDATE_ENV) {
+        Ok(value) => !matches!(
+            value.trim().to_lowercase().as_str(),
+            "" | "0" | "false" | "no"
+        ),
+        Err(_) => disabled_by_config,
+    }
+}
+
+/// Only reports whether a new release is available
+pub fn check_update(timeout: Duration, configured: &UpdateConfig) {
+    match UpdatePolicy::from_env(timeout, configured) {
+        Ok(policy) => report_newer_release(&policy),
+        Err(err) => {
+            yellow_ln!("⚠️ Invalid update settings: {}", err);
+            process::exit(exit_code::ERROR);
+        }
+    }
+}
 
 /// Checks for updates
-/// If an update is available, download and install it
+/// If an update is available, download, verify and install it
 /// If no update is available, do nothing
 /// Automatically restart the application after update
-pub fn update() {
-    // In release mode, don't ask for confirmation
-    let no_confirm: bool = !cfg!(debug_assertions);
-
-    let current_executable = env::current_exe().expect("current exe");
-
-    let status = self_update::backends::github::Update::configure()
-        .repo_owner("rouhim")
-        .repo_name("cue-splatter")
-        .bin_name("cue-splatter")
-        .show_download_progress(true)
-        .no_confirm(no_confirm)
-        .current_version(cargo_crate_version!())
-        .build()
-        .unwrap()
-        .update();
+/// If the release server does not answer within the timeout, the check is skipped
+pub fn update(timeout: Duration, configured: &UpdateConfig) {
+    let policy = match UpdatePolicy::from_env(timeout, configured) {
+        Ok(policy) => policy,
+        Err(err) => {
+            yellow_ln!(
+                "⚠️ Skipping the update check, invalid update settings: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    match fetch_newer_release(&policy) {
+        Err(err) => yellow_ln!("⚠️ Skipping the update check: {}", err),
+        Ok(None) => print_up_to_date(&policy),
+        Ok(Some(release)) => match install_release(&release, policy.timeout) {
+            Err(err) => yellow_ln!(
+                "⚠️ Failed to update, keeping the installed version: {}",
+                err
+            ),
+            Ok(()) => restart_process(env::current_exe().expect("current exe")),
+        },
+    }
+}
 
-    match status {
-        Err(err) => println!("Failed to update: {}", err),
-        Ok(self_update::Status::UpToDate(version)) => {
-            println!("cue-splatter {} is up to date", version);
+/// Runs the rollback subcommand
+/// The previous binary and the running one are swapped, so a rollback can be undone by another rollback
+/// The running version is skipped by the automatic update, so it is not installed again right away
+pub fn rollback(_rollback_args: RollbackArgs) {
+    match restore_previous_binary() {
+        Ok(previous_binary) => {
+            println!(
+                "⏪ Restored the previous version of cue-splatter, the replaced version is kept as {}",
+                previous_binary.display()
+            );
+            match write_skipped_version(cargo_crate_version!()) {
+                Ok(()) => println!(
+                    "The automatic update skips {}, newer releases are installed again, `cue-splatter update` installs it anyway",
+                    cargo_crate_version!()
+                ),
+                Err(err) => yellow_ln!(
+                    "⚠️ Could not save {} as skipped version, pin the version with {} or disable updates with --no-update: {}",
+                    cargo_crate_version!(),
+                    UPDATE_PIN_ENV,
+                    err
+                ),
+            }
         }
-        Ok(self_update::Status::Updated(version)) => {
-            println!("cue-splatter updated to {}", version);
-            restart_process(current_executable);
+        Err(err) => {
+            yellow_ln!("⚠️ Failed to roll back: {}", err);
+            process::exit(exit_code::ERROR);
         }
     }
 }
 
+fn report_newer_release(policy: &UpdatePolicy) {
+    match fetch_newer_release(policy) {
+        Err(err) => {
+            yellow_ln!("⚠️ Could not check for updates: {}", err);
+            process::exit(exit_code::ERROR);
+        }
+        Ok(None) => print_up_to_date(policy),
+        Ok(Some(release)) => println!(
+            "cue-splatter {} is available, currently installed is {}, run `cue-splatter update` to install it",
+            release.version,
+            cargo_crate_version!()
+        ),
+    }
+}
+
+fn print_up_to_date(policy: &UpdatePolicy) {
+    println!(
+        "cue-splatter {} is up to date ({})",
+        cargo_crate_version!(),
+        policy.describe()
+    );
+}
+
+/// Fetches the newest release allowed by the policy, if it is newer than the running version
+/// The release server is queried in a separate thread, so an unreachable server can not stall the run
+fn fetch_newer_release(policy: &UpdatePolicy) -> Result<Option<Release>, String> {
+    let (sender, receiver) = mpsc::channel();
+    let release_url = policy.release_url.clone();
+    thread::spawn(move || {
+        let _ = sender.send(fetch_releases(&release_url));
+    });
+
+    let releases = receiver
+        .recv_timeout(policy.timeout)
+        .map_err(|_| format!("no answer within {}s", policy.timeout.as_secs()))??;
+    let current_version = Version::parse(cargo_crate_version!()).map_err(|err| err.to_string())?;
+
+    Ok(releases
+        .into_iter()
+        .filter(|release| policy.allows(release))
+        .max_by(|release, other_release| release.version.cmp(&other_release.version))
+        .filter(|release| release.version > current_version))
+}
+
+/// Fetches the release list in the format of the GitHub releases API
+/// Drafts and releases without a semver tag are left out
+fn fetch_releases(release_url: &str) -> Result<Vec<Release>, String> {
+    let response = reqwest::blocking::Client::new()
+        .get(release_url)
+        .header(USER_AGENT, "cue-splatter")
+        .header(ACCEPT, "application/vnd.github+json")
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    let release_list: serde_json::Value = response.json().map_err(|err| err.to_string())?;
+
+    let releases = release_list
+        .as_array()
+        .ok_or_else(|| format!("{} returned no release list", release_url))?
+        .iter()
+        .filter(|release| !release["draft"].as_bool().unwrap_or(false))
+        .filter_map(parse_release)
+        .collect();
+    Ok(releases)
+}
+
+fn parse_release(release: &serde_json::Value) -> Option<Release> {
+    let tag_name = release["tag_name"].as_str()?;
+    let version = Version::parse(tag_name.trim_start_matches('v')).ok()?;
+    let assets = release["assets"]
+        .as_array()?
+        .iter()
+        .filter_map(|asset| {
+            Some(ReleaseAsset {
+                name: asset["name"].as_str()?.to_string(),
+                download_url: asset["url"].as_str()?.to_string(),
+            })
+        })
+        .collect();
+
+    Some(Release {
+        version,
+        prerelease: release["prerelease"].as_bool().unwrap_or(false),
+        assets,
+    })
+}
+
+/// Downloads and installs the release
+///
+/// The binary is only replaced if its SHA-256 checksum matches the published checksum file and its minisign
+/// signature was made with the compiled in public key. Otherwise the installed binary stays untouched.
+/// A download is given up, if the release server does not answer or send data within the timeout.
+fn install_release(release: &Release, timeout: Duration) -> Result<(), String> {
+    let public_key = UPDATE_PUBLIC_KEY
+        .ok_or("this build has no update signing key, please download the new release manually")?;
+    let public_key = update_verify::PublicKey::parse(public_key)?;
+    install_verified_release(release, &public_key, timeout, replace_binary)
+}
+
+/// Downloads and verifies the release, the binary is only handed to `replace_binary` once it is verified
+fn install_verified_release(
+    release: &Release,
+    public_key: &update_verify::PublicKey,
+    timeout: Duration,
+    replace_binary: impl FnOnce(&[u8]) -> Result<PathBuf, String>,
+) -> Result<(), String> {
+    let binary_asset = find_binary_asset(release)?;
+    let checksum_asset = find_asset(
+        release,
+        &format!("{}.{}", binary_asset.name, CHECKSUM_SUFFIX),
+    )?;
+    let signature_asset = find_asset(
+        release,
+        &format!("{}.{}", binary_asset.name, SIGNATURE_SUFFIX),
+    )?;
+
+    println!(
+        "⬇️ Downloading cue-splatter {} ({})",
+        release.version, binary_asset.name
+    );
+    let binary = download_asset(binary_asset, timeout, true)?;
+    let checksum_file = download_text_asset(checksum_asset, timeout)?;
+    let signature_file = download_text_asset(signature_asset, timeout)?;
+
+    update_verify::verify_checksum(&binary_asset.name, &binary, &checksum_file)?;
+    update_verify::verify_signature(public_key, &binary_asset.name, &binary, &signature_file)?;
+    println!(
+        "🔏 Checksum and signature of {} are valid (key {})",
+        binary_asset.name,
+        public_key.key_id()
+    );
+
+    let previous_binary = replace_binary(&binary)?;
+    println!(
+        "cue-splatter updated to {}, the previous version is kept as {}",
+        release.version,
+        previous_binary.display()
+    );
+    Ok(())
+}
+
+/// Finds the binary for the target this binary was built for, checksum and signature assets share its name
+fn find_binary_asset(release: &Release) -> Result<&ReleaseAsset, String> {
+    let target = self_update::get_target();
+    release
+        .assets
+        .iter()
+        .find(|asset| {
+            asset.name.contains(target)
+                && !asset.name.ends_with(&format!(".{}", CHECKSUM_SUFFIX))
+                && !asset.name.ends_with(&format!(".{}", SIGNATURE_SUFFIX))
+        })
+        .ok_or_else(|| format!("release {} has no binary for {}", release.version, target))
+}
+
+fn find_asset<'a>(release: &'a Release, asset_name: &str) -> Result<&'a ReleaseAsset, String> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name == asset_name)
+        .ok_or_else(|| format!("release {} has no {}", release.version, asset_name))
+}
+
+/// Downloads the release asset into memory
+/// The download url is the API url of the asset, which only returns the content for binary requests
+/// The timeout applies to connecting and to each read, so a slow but steady download is not aborted
+fn download_asset(
+    asset: &ReleaseAsset,
+    timeout: Duration,
+    show_progress: bool,
+) -> Result<Vec<u8>, String> {
+    let download_error = |err: String| format!("could not download {}: {}", asset.name, err);
+    let mut response = reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|err| download_error(err.to_string()))?
+        .get(&asset.download_url)
+        .header(USER_AGENT, "cue-splatter")
+        .header(ACCEPT, "application/octet-stream")
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| download_error(err.to_string()))?;
+
+    let content_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let progress_bar = match content_length {
+        Some(content_length) if show_progress => ProgressBar::new(content_length).with_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes}")
+                .unwrap()
+                .progress_chars("=>-"),
+        ),
+        _ => ProgressBar::hidden(),
+    };
+
+    let mut content = Vec::new();
+    io::copy(&mut response, &mut progress_bar.wrap_write(&mut content))
+        .map_err(|err| download_error(err.to_string()))?;
+    progress_bar.finish_and_clear();
+    Ok(content)
+}
+
+fn download_text_asset(asset: &ReleaseAsset, timeout: Duration) -> Result<String, String> {
+    String::from_utf8(download_asset(asset, timeout, false)?)
+        .map_err(|_| format!("{} is no text file", asset.name))
+}
+
+/// Replaces the running binary with the downloaded one
+/// The running binary is kept next to it, so the update can be rolled back. Returns the path of the kept binary.
+fn replace_binary(binary: &[u8]) -> Result<PathBuf, String> {
+    let current_executable = env::current_exe().map_err(|err| err.to_string())?;
+    let previous_binary = previous_binary_path(&current_executable);
+
+    let temp_dir = self_update::TempDir::new().map_err(|err| err.to_string())?;
+    let new_executable = temp_dir.path().join("cue-splatter");
+    fs::write(&new_executable, binary).map_err(|err| err.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(&new_executable, fs::Permissions::from_mode(0o755))
+            .map_err(|err| err.to_string())?;
+    }
+
+    fs::copy(&current_executable, &previous_binary)
+        .map_err(|err| format!("could not keep the previous version: {}", err))?;
+    self_update::self_replace::self_replace(&new_executable).map_err(|err| err.to_string())?;
+    Ok(previous_binary)
+}
+
+/// Swaps the running binary with the one kept by the last update
+/// Returns the path the replaced binary is kept at
+fn restore_previous_binary() -> Result<PathBuf, String> {
+    let current_executable = env::current_exe().map_err(|err| err.to_string())?;
+    let previous_binary = previous_binary_path(&current_executable);
+    if !previous_binary.is_file() {
+        return Err(format!(
+            "there is no previous version at {}",
+            previous_binary.display()
+        ));
+    }
+
+    let temp_dir = self_update::TempDir::new().map_err(|err| err.to_string())?;
+    let replaced_executable = temp_dir.path().join("cue-splatter");
+    fs::copy(&current_executable, &replaced_executable).map_err(|err| err.to_string())?;
+    self_update::self_replace::self_replace(&previous_binary).map_err(|err| err.to_string())?;
+    fs::copy(&replaced_executable, &previous_binary).map_err(|err| err.to_string())?;
+    Ok(previous_binary)
+}
+
+fn previous_binary_path(current_executable: &Path) -> PathBuf {
+    suffixed_path(current_executable, PREVIOUS_BINARY_SUFFIX)
+}
+
+fn skipped_version_path(current_executable: &Path) -> PathBuf {
+    suffixed_path(current_executable, SKIPPED_VERSION_SUFFIX)
+}
+
+fn suffixed_path(current_executable: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = current_executable.file_name().unwrap().to_os_string();
+    file_name.push(format!(".{}", suffix));
+    current_executable.with_file_name(file_name)
+}
+
+/// Reads the version the last rollback went back from, a missing or unreadable file skips nothing
+fn read_skipped_version() -> Option<Version> {
+    let current_executable = env::current_exe().ok()?;
+    let skipped_version = fs::read_to_string(skipped_version_path(&current_executable)).ok()?;
+    Version::parse(skipped_version.trim()).ok()
+}
+
+fn write_skipped_version(version: &str) -> Result<(), String> {
+    let current_executable = env::current_exe().map_err(|err| err.to_string())?;
+    fs::write(skipped_version_path(&current_executable), version).map_err(|err| err.to_string())
+}
+
+/// Reads an environment variable, empty values count as unset
+fn read_env(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
 /// Restarts the current process
 fn restart_process(current_executable: PathBuf) {
     println!("Waiting 5s before restarting {:?} ...", current_executable);
@@ -54,3 +634,259 @@ fn exec(command: &mut process::Command) -> io::Error {
     // of the current process stops here.
     command.exec()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::update_verify::test_signing::{checksum_file, TestKey};
+
+    const VERSION: &str = "99.0.0";
+    const OLD_BINARY: &[u8] = b"old cue-splatter binary";
+    const NEW_BINARY: &[u8] = b"new cue-splatter binary";
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Serves the files by their path from a local HTTP server, other paths answer 404
+    struct TestServer {
+        listener: TcpListener,
+        base_url: String,
+    }
+
+    impl TestServer {
+        fn new() -> TestServer {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            TestServer { listener, base_url }
+        }
+
+        fn serve(self, files: HashMap<String, Vec<u8>>) -> String {
+            thread::spawn(move || {
+                for mut stream in self.listener.incoming().map_while(Result::ok) {
+                    let mut request_lines = BufReader::new(&stream)
+                        .lines()
+                        .map_while(Result::ok)
+                        .take_while(|line| !line.is_empty());
+                    let request_line = request_lines.next().unwrap_or_default();
+                    request_lines.for_each(drop);
+
+                    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                    let (status, body) = match files.get(path) {
+                        Some(body) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    let _ = stream.write_all(&[header.as_bytes(), &body].concat());
+                }
+            });
+            format!("{}/releases", self.base_url)
+        }
+    }
+
+    fn binary_asset_name() -> String {
+        format!("cue-splatter-{}-{}", VERSION, self_update::get_target())
+    }
+
+    /// Publishes a release with the given assets, returns the url of the release list
+    fn publish_release(assets: Vec<(String, Vec<u8>)>) -> String {
+        let server = TestServer::new();
+        let asset_list: Vec<serde_json::Value> = assets
+            .iter()
+            .map(|(name, _)| {
+                serde_json::json!({
+                    "name": name,
+                    "url": format!("{}/assets/{}", server.base_url, name),
+                })
+            })
+            .collect();
+        let release_list = serde_json::json!([
+            { "tag_name": "v1.0.0", "prerelease": false, "draft": false, "assets": [] },
+            { "tag_name": format!("v{}", VERSION), "prerelease": false, "draft": false, "assets": asset_list },
+            { "tag_name": "v100.0.0-beta.1", "prerelease": true, "draft": false, "assets": [] },
+            { "tag_name": "v101.0.0", "prerelease": false, "draft": true, "assets": [] },
+        ]);
+
+        let mut files: HashMap<String, Vec<u8>> = assets
+            .into_iter()
+            .map(|(name, content)| (format!("/assets/{}", name), content))
+            .collect();
+        files.insert(
+            "/releases".to_string(),
+            release_list.to_string().into_bytes(),
+        );
+        server.serve(files)
+    }
+
+    fn signed_release_assets(
+        binary: &[u8],
+        signed_binary: &[u8],
+        test_key: &TestKey,
+    ) -> Vec<(String, Vec<u8>)> {
+        let name = binary_asset_name();
+        vec![
+            (name.clone(), binary.to_vec()),
+            (
+                format!("{}.{}", name, CHECKSUM_SUFFIX),
+                checksum_file(&name, signed_binary).into_bytes(),
+            ),
+            (
+                format!("{}.{}", name, SIGNATURE_SUFFIX),
+                test_key.sign(&name, signed_binary).into_bytes(),
+            ),
+        ]
+    }
+
+    fn policy(release_url: String) -> UpdatePolicy {
+        UpdatePolicy {
+            channel: ReleaseChannel::Stable,
+            pin: None,
+            skipped_version: None,
+            release_url,
+            timeout: TIMEOUT,
+        }
+    }
+
+    /// Installs the newest release into a copy of the installed binary, returns the result and the installed binary
+    fn install(release_url: String, test_key: &TestKey) -> (Result<(), String>, Vec<u8>) {
+        let installed_binary = std::env::temp_dir().join(format!(
+            "cue-splatter-update-test-{}-{:?}",
+            process::id(),
+            thread::current().id()
+        ));
+        fs::write(&installed_binary, OLD_BINARY).unwrap();
+
+        let release = fetch_newer_release(&policy(release_url)).unwrap().unwrap();
+        let result =
+            install_verified_release(&release, &test_key.public_key(), TIMEOUT, |binary| {
+                fs::write(&installed_binary, binary).map_err(|err| err.to_string())?;
+                Ok(installed_binary.with_extension(PREVIOUS_BINARY_SUFFIX))
+            });
+        let installed_content = fs::read(&installed_binary).unwrap();
+        let _ = fs::remove_file(&installed_binary);
+        (result, installed_content)
+    }
+
+    #[test]
+    fn fetches_the_newest_allowed_release() {
+        let release_url = publish_release(Vec::new());
+        let release = fetch_newer_release(&policy(release_url.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(release.version.to_string(), VERSION);
+
+        let mut prerelease_policy = policy(release_url.clone());
+        prerelease_policy.channel = ReleaseChannel::Prerelease;
+        let release = fetch_newer_release(&prerelease_policy).unwrap().unwrap();
+        assert_eq!(release.version.to_string(), "100.0.0-beta.1");
+
+        prerelease_policy.pin = Some("~99".parse().unwrap());
+        let release = fetch_newer_release(&prerelease_policy).unwrap().unwrap();
+        assert_eq!(release.version.to_string(), VERSION);
+
+        let mut pinned_policy = policy(release_url.clone());
+        pinned_policy.pin = Some("~2.0".parse().unwrap());
+        assert!(fetch_newer_release(&pinned_policy).unwrap().is_none());
+    }
+
+    #[test]
+    fn skips_the_version_rolled_back_from() {
+        let release_url = publish_release(Vec::new());
+        let mut skipping_policy = policy(release_url);
+        skipping_policy.skipped_version = Some(VERSION.parse().unwrap());
+        let release = fetch_newer_release(&skipping_policy).unwrap().unwrap();
+        assert_eq!(release.version.to_string(), "1.0.0");
+        assert_eq!(skipping_policy.describe(), "stable, skipping 99.0.0");
+    }
+
+    #[test]
+    fn installs_a_verified_release() {
+        let test_key = TestKey::new(1);
+        let release_url = publish_release(signed_release_assets(NEW_BINARY, NEW_BINARY, &test_key));
+        let (result, installed_content) = install(release_url, &test_key);
+        result.unwrap();
+        assert_eq!(installed_content, NEW_BINARY);
+    }
+
+    #[test]
+    fn keeps_the_installed_binary_on_a_bad_signature() {
+        let release_key = TestKey::new(1);
+        let release_url = publish_release(signed_release_assets(
+            NEW_BINARY,
+            NEW_BINARY,
+            &TestKey::new(2),
+        ));
+        let (result, installed_content) = install(release_url, &release_key);
+        assert!(result.unwrap_err().contains("signed with key"));
+        assert_eq!(installed_content, OLD_BINARY);
+    }
+
+    #[test]
+    fn keeps_the_installed_binary_on_a_tampered_binary() {
+        let test_key = TestKey::new(1);
+        let mut assets = signed_release_assets(NEW_BINARY, NEW_BINARY, &test_key);
+        // The checksum matches the tampered binary, only the signature does not
+        let tampered_binary = b"tampered cue-splatter binary".to_vec();
+        assets[1].1 = checksum_file(&binary_asset_name(), &tampered_binary).into_bytes();
+        assets[0].1 = tampered_binary;
+        let (result, installed_content) = install(publish_release(assets), &test_key);
+        assert!(result.unwrap_err().contains("signature"));
+        assert_eq!(installed_content, OLD_BINARY);
+    }
+
+    #[test]
+    fn keeps_the_installed_binary_on_a_checksum_mismatch() {
+        let test_key = TestKey::new(1);
+        let release_url = publish_release(signed_release_assets(
+            b"truncated binary",
+            NEW_BINARY,
+            &test_key,
+        ));
+        let (result, installed_content) = install(release_url, &test_key);
+        assert!(result.unwrap_err().contains("SHA-256 checksum"));
+        assert_eq!(installed_content, OLD_BINARY);
+    }
+
+    #[test]
+    fn keeps_the_installed_binary_on_missing_assets() {
+        let test_key = TestKey::new(1);
+        for missing_asset in 0..3 {
+            let mut assets = signed_release_assets(NEW_BINARY, NEW_BINARY, &test_key);
+            assets.remove(missing_asset);
+            let (result, installed_content) = install(publish_release(assets), &test_key);
+            assert!(result.unwrap_err().contains("has no"));
+            assert_eq!(installed_content, OLD_BINARY);
+        }
+    }
+
+    #[test]
+    fn keeps_the_installed_binary_on_unavailable_downloads() {
+        let test_key = TestKey::new(1);
+        let server = TestServer::new();
+        let name = binary_asset_name();
+        let asset_list: Vec<serde_json::Value> = [
+            name.clone(),
+            format!("{}.{}", name, CHECKSUM_SUFFIX),
+            format!("{}.{}", name, SIGNATURE_SUFFIX),
+        ]
+        .iter()
+        .map(|name| serde_json::json!({ "name": name, "url": format!("{}/gone/{}", server.base_url, name) }))
+        .collect();
+        let release_list = serde_json::json!([
+            { "tag_name": format!("v{}", VERSION), "assets": asset_list },
+        ]);
+        let release_url = server.serve(HashMap::from([(
+            "/releases".to_string(),
+            release_list.to_string().into_bytes(),
+        )]));
+
+        let (result, installed_content) = install(release_url, &test_key);
+        assert!(result.unwrap_err().contains("could not download"));
+        assert_eq!(installed_content, OLD_BINARY);
+    }
+}
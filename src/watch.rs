@@ -0,0 +1,399 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{fs, io, process};
+
+use argh::FromArgs;
+use colour::{green_ln, red_ln, yellow_ln};
+use notify::{RecursiveMode, Watcher};
+
+use crate::config::{self, Config};
+use crate::{check_tools, exit_code, find_cue_files, split_cue_files, InvalidCuePolicy, SplitArgs};
+
+/// Seconds the file sizes of a rip have to stay unchanged, before it is processed
+const DEFAULT_SETTLE_TIME_SECS: u64 = 10;
+
+/// How often the file sizes of pending rips are compared
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watch folders for new rips and split them once they are completely copied
+#[derive(Debug, FromArgs)]
+#[argh(
+    subcommand,
+    name = "watch",
+    note = "The split options are taken from the config files, questions are never asked"
+)]
+pub struct WatchArgs {
+    /// folder split rips are moved into (default: done/ in the watched folder)
+    #[argh(option)]
+    done_dir: Option<PathBuf>,
+
+    /// folder rips which could not be split are moved into (default: failed/ in the watched folder)
+    #[argh(option)]
+    failed_dir: Option<PathBuf>,
+
+    /// seconds the file sizes of a rip have to stay unchanged, before it is split (default: 10)
+    #[argh(option)]
+    settle_time: Option<u64>,
+
+    /// folders to watch, each folder copied into them is processed as one rip
+    /// default is the folders of the config file, or "."
+    #[argh(positional, greedy)]
+    pub folders: Vec<PathBuf>,
+}
+
+/// A watched folder and where its rips are moved after processing
+struct WatchedFolder {
+    folder: PathBuf,
+    done_dir: PathBuf,
+    failed_dir: PathBuf,
+}
+
+/// A rip that changed recently, it is processed once its file sizes settle
+struct PendingRip {
+    file_sizes: Vec<(PathBuf, u64)>,
+    unchanged_since: Instant,
+}
+
+/// Runs the watch command until it is interrupted
+/// Rips are split one after another, so a large batch does not start a ffmpeg process per track of each rip
+/// The config files are loaded again for each rip, `config_file` is the one given by --config
+pub fn watch(watch_args: WatchArgs, config: &Config, config_file: Option<&Path>) {
+    check_tools(vec!["ffmpeg", "ffprobe"]);
+
+    let settle_time = Duration::from_secs(
+        watch_args
+            .settle_time
+            .or(config.watch.settle_time)
+            .unwrap_or(DEFAULT_SETTLE_TIME_SECS),
+    );
+    let folders = if !watch_args.folders.is_empty() {
+        watch_args.folders.clone()
+    } else {
+        config
+            .watch
+            .folders
+            .clone()
+            .unwrap_or_else(|| vec![PathBuf::from(".")])
+    };
+    let watched_folders: Vec<WatchedFolder> = folders
+        .iter()
+        .map(|folder| prepare_watched_folder(folder, &watch_args, config))
+        .collect();
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).unwrap_or_else(|err| {
+        red_ln!("❌ Could not start watching: {}", err);
+        process::exit(exit_code::ERROR);
+    });
+    for watched_folder in &watched_folders {
+        if let Err(err) = watcher.watch(&watched_folder.folder, RecursiveMode::Recursive) {
+            red_ln!(
+                "❌ Could not watch {}: {}",
+                watched_folder.folder.display(),
+                err
+            );
+            process::exit(exit_code::ERROR);
+        }
+        println!(
+            "👀 Watching {} for new rips",
+            watched_folder.folder.display()
+        );
+    }
+    println!(
+        "⏳ Rips are split once their files kept their size for {} seconds, press Ctrl+C to stop",
+        settle_time.as_secs()
+    );
+
+    let mut pending_rips: HashMap<PathBuf, PendingRip> = HashMap::new();
+    // Rips which were ignored or could not be moved away, they are not looked at again
+    let mut ignored_paths: HashSet<PathBuf> = HashSet::new();
+
+    // Rips copied while cue-splatter was not running
+    for watched_folder in &watched_folders {
+        for dir_entry in fs::read_dir(&watched_folder.folder).into_iter().flatten() {
+            let Ok(dir_entry) = dir_entry else {
+                continue;
+            };
+            mark_changed(
+                &watched_folders,
+                &dir_entry.path(),
+                &mut pending_rips,
+                &mut ignored_paths,
+            );
+        }
+    }
+
+    let mut last_check = Instant::now();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                for path in &event.paths {
+                    mark_changed(
+                        &watched_folders,
+                        path,
+                        &mut pending_rips,
+                        &mut ignored_paths,
+                    );
+                }
+            }
+            Ok(Err(err)) => yellow_ln!("⚠️ Error while watching: {}", err),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                red_ln!("❌ Stopped receiving file changes");
+                process::exit(exit_code::ERROR);
+            }
+        }
+
+        // Events arrive in bursts while a rip is copied, the sizes are compared at most once per interval
+        if last_check.elapsed() < POLL_INTERVAL {
+            continue;
+        }
+        last_check = Instant::now();
+
+        for rip in find_settled_rips(&mut pending_rips, settle_time) {
+            let watched_folder = watched_folders
+                .iter()
+                .find(|watched_folder| rip.starts_with(&watched_folder.folder))
+                .unwrap();
+            if !process_rip(&rip, watched_folder, config_file) {
+                ignored_paths.insert(rip);
+            }
+        }
+    }
+}
+
+/// Resolves the done and failed folders of a watched folder and creates them
+/// Paths are made absolute, as the file changes are reported with absolute paths
+fn prepare_watched_folder(folder: &Path, watch_args: &WatchArgs, config: &Config) -> WatchedFolder {
+    let folder = fs::canonicalize(folder).unwrap_or_else(|err| {
+        red_ln!("❌ Can not watch {}: {}", folder.display(), err);
+        process::exit(exit_code::INVALID_INPUT);
+    });
+    let done_dir = watch_args
+        .done_dir
+        .clone()
+        .or(config.watch.done_dir.clone())
+        .unwrap_or_else(|| folder.join("done"));
+    let failed_dir = watch_args
+        .failed_dir
+        .clone()
+        .or(config.watch.failed_dir.clone())
+        .unwrap_or_else(|| folder.join("failed"));
+
+    WatchedFolder {
+        done_dir: create_dir(&done_dir),
+        failed_dir: create_dir(&failed_dir),
+        folder,
+    }
+}
+
+fn create_dir(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::canonicalize(dir))
+        .unwrap_or_else(|err| {
+            red_ln!("❌ Could not create {}: {}", dir.display(), err);
+            process::exit(exit_code::ERROR);
+        })
+}
+
+/// Marks the rip containing the changed path as pending
+/// A rip is a folder directly in a watched folder, files directly in it are ignored, as it is not known which of
+/// them belong together
+fn mark_changed(
+    watched_folders: &[WatchedFolder],
+    path: &Path,
+    pending_rips: &mut HashMap<PathBuf, PendingRip>,
+    ignored_paths: &mut HashSet<PathBuf>,
+) {
+    let Some(watched_folder) = watched_folders.iter().find(|watched_folder| {
+        path.starts_with(&watched_folder.folder)
+            && !path.starts_with(&watched_folder.done_dir)
+            && !path.starts_with(&watched_folder.failed_dir)
+    }) else {
+        return;
+    };
+    let Some(Component::Normal(name)) = path
+        .strip_prefix(&watched_folder.folder)
+        .ok()
+        .and_then(|relative_path| relative_path.components().next())
+    else {
+        return;
+    };
+    // Hidden entries are e.g. temporary files of a copy or the folder config file
+    if name.to_string_lossy().starts_with('.') {
+        return;
+    }
+
+    let rip = watched_folder.folder.join(name);
+    if ignored_paths.contains(&rip) || pending_rips.contains_key(&rip) {
+        return;
+    }
+    if rip.is_file() {
+        yellow_ln!(
+            "⚠️ Ignoring {}, only folders copied into the watched folder are split",
+            rip.display()
+        );
+        ignored_paths.insert(rip);
+        return;
+    }
+    pending_rips.insert(
+        rip,
+        PendingRip {
+            file_sizes: Vec::new(),
+            unchanged_since: Instant::now(),
+        },
+    );
+}
+
+/// Compares the file sizes of the pending rips with the last check
+/// Returns the rips whose sizes did not change for the settle time and removes them from the pending rips
+fn find_settled_rips(
+    pending_rips: &mut HashMap<PathBuf, PendingRip>,
+    settle_time: Duration,
+) -> Vec<PathBuf> {
+    let mut settled_rips = Vec::new();
+    pending_rips.retain(|rip, pending_rip| {
+        // Moved away or deleted, e.g. by us after processing it
+        if !rip.is_dir() {
+            return false;
+        }
+
+        let file_sizes = read_file_sizes(rip);
+        if file_sizes != pending_rip.file_sizes {
+            pending_rip.file_sizes = file_sizes;
+            pending_rip.unchanged_since = Instant::now();
+            return true;
+        }
+        if pending_rip.unchanged_since.elapsed() < settle_time {
+            return true;
+        }
+        settled_rips.push(rip.clone());
+        false
+    });
+    settled_rips.sort();
+    settled_rips
+}
+
+/// Lists the sizes of all files in the folder and its sub folders, sorted by path
+fn read_file_sizes(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let mut file_sizes = Vec::new();
+    for dir_entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = dir_entry.path();
+        let Ok(metadata) = dir_entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            file_sizes.extend(read_file_sizes(&path));
+        } else {
+            file_sizes.push((path, metadata.len()));
+        }
+    }
+    file_sizes.sort();
+    file_sizes
+}
+
+/// Watch mode runs unattended, an invalid cue file must not stop it
+fn unattended_split_args(config: &Config) -> SplitArgs {
+    let mut split_args = SplitArgs::default();
+    split_args.apply_config(config);
+    split_args.non_interactive = true;
+    split_args.on_invalid_cue = Some(InvalidCuePolicy::Skip);
+    split_args
+}
+
+/// Splits the cue files of a settled rip and moves it into the done or failed folder
+/// Rips without cue files are left alone, the cue file may still be copied later
+/// Returns false if the rip could not be moved, so it has to be ignored from now on
+fn process_rip(rip: &Path, watched_folder: &WatchedFolder, config_file: Option<&Path>) -> bool {
+    let cue_file_paths = find_cue_files(rip);
+    if cue_file_paths.is_empty() {
+        return true;
+    }
+
+    println!();
+    println!("📥 Splitting {}", rip.display());
+    // Each rip is split with the settings of its nearest directory config file, which may come with the rip
+    let exit_status = match config::load(config_file, &[rip.to_path_buf()]) {
+        Ok(rip_config) => split_cue_files(
+            &unattended_split_args(&rip_config),
+            &rip_config,
+            &cue_file_paths,
+        ),
+        Err(err) => {
+            red_ln!("❌ Invalid config: {}", err);
+            exit_code::INVALID_INPUT
+        }
+    };
+    let target_dir = if exit_status == exit_code::SUCCESS {
+        &watched_folder.done_dir
+    } else {
+        &watched_folder.failed_dir
+    };
+
+    match move_rip(rip, target_dir) {
+        Ok(moved_rip) if exit_status == exit_code::SUCCESS => {
+            green_ln!(
+                "✅ Split {}, moved it to {}",
+                rip.display(),
+                moved_rip.display()
+            );
+            true
+        }
+        Ok(moved_rip) => {
+            yellow_ln!(
+                "⚠️ Could not split {} ({}), moved it to {}",
+                rip.display(),
+                exit_code::describe(exit_status),
+                moved_rip.display()
+            );
+            true
+        }
+        Err(err) => {
+            red_ln!(
+                "❌ Could not move {} to {}: {}",
+                rip.display(),
+                target_dir.display(),
+                err
+            );
+            false
+        }
+    }
+}
+
+/// Moves the rip into the target folder, a number is appended to its name if the target folder already has one
+/// Folders on another file system are copied and then removed
+fn move_rip(rip: &Path, target_dir: &Path) -> io::Result<PathBuf> {
+    let name = rip.file_name().unwrap().to_string_lossy();
+    let mut moved_rip = target_dir.join(name.as_ref());
+    let mut number = 2;
+    while moved_rip.exists() {
+        moved_rip = target_dir.join(format!("{} ({})", name, number));
+        number += 1;
+    }
+
+    match fs::rename(rip, &moved_rip) {
+        Ok(()) => Ok(moved_rip),
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            copy_dir(rip, &moved_rip)?;
+            fs::remove_dir_all(rip)?;
+            Ok(moved_rip)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn copy_dir(source_dir: &Path, target_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(target_dir)?;
+    for dir_entry in fs::read_dir(source_dir)? {
+        let dir_entry = dir_entry?;
+        let target_path = target_dir.join(dir_entry.file_name());
+        if dir_entry.file_type()?.is_dir() {
+            copy_dir(&dir_entry.path(), &target_path)?;
+        } else {
+            fs::copy(dir_entry.path(), target_path)?;
+        }
+    }
+    Ok(())
+}